@@ -1,7 +1,14 @@
-use crate::pinned_cert_verifier::PinnedCertVerifier;
-use anyhow::{Context, Result, anyhow};
-use rustls::{ClientConfig, pki_types::ServerName};
-use std::{sync::Arc, time::Duration};
+use crate::{
+    insecure_cert_verifier::InsecureCertVerifier, pinned_cert_verifier::PinnedCertVerifier,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use rustls::{
+    ClientConfig, ConfigBuilder, RootCertStore, WantsVerifier,
+    client::danger::ServerCertVerifier,
+    crypto::{CryptoProvider, aws_lc_rs},
+    pki_types::{CertificateDer, ServerName},
+};
+use std::{fs, io, sync::Arc, time::Duration};
 use tokio::{io::BufReader, net::TcpStream};
 use tokio_rustls::TlsConnector;
 
@@ -14,6 +21,14 @@ pub type ClientReader = tokio::io::BufReader<
 pub type ClientWriter =
     tokio::io::WriteHalf<tokio_rustls::client::TlsStream<tokio::net::TcpStream>>;
 
+/// The reader half of a plaintext client connection.
+#[cfg(feature = "plaintext")]
+pub type PlaintextReader = tokio::io::BufReader<tokio::io::ReadHalf<tokio::net::TcpStream>>;
+
+/// The writer half of a plaintext client connection.
+#[cfg(feature = "plaintext")]
+pub type PlaintextWriter = tokio::io::WriteHalf<tokio::net::TcpStream>;
+
 /// Connects to the server at `addr` with TLS using a pinned cert verifier from the file at `path`,
 /// timing out after `timeout`. Immediately splits into reader and writer halves.
 ///
@@ -25,13 +40,188 @@ pub async fn connect(
     addr: &str,
     timeout: Duration,
 ) -> Result<(ClientReader, ClientWriter)> {
-    // Create a TLS client that validates against the pinned certificate
-    let connector = TlsConnector::from(Arc::new(
-        ClientConfig::builder()
+    connect_with_verifier(
+        Arc::new(PinnedCertVerifier::from_file(path)?),
+        addr,
+        timeout,
+    )
+    .await
+}
+
+/// The delay before the first retry in `connect_with_retries`, which doubles after each attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+
+/// The maximum delay between attempts in `connect_with_retries`.
+const MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Connects to the server at `addr` like `connect`, but retries if the connection is refused.
+///
+/// Retries back off exponentially, which helps when the server is still starting up. At most
+/// `max_attempts` attempts are made, each timing out after `per_attempt_timeout`. Other errors,
+/// such as an invalid address or a certificate that doesn't match the pinned one, are returned
+/// immediately since retrying would not help.
+///
+/// # Errors
+///
+/// Returns `Err` if the file reading or TLS connection process fails with a non-retryable error,
+/// or if every attempt is refused or times out.
+pub async fn connect_with_retries(
+    path: &str,
+    addr: &str,
+    per_attempt_timeout: Duration,
+    max_attempts: u32,
+) -> Result<(ClientReader, ClientWriter)> {
+    let verifier = Arc::new(PinnedCertVerifier::from_file(path)?);
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+
+    loop {
+        match connect_with_verifier(verifier.clone(), addr, per_attempt_timeout).await {
+            Err(e) if attempt < max_attempts && is_connection_refused(&e) => {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+
+            result => {
+                break result
+                    .with_context(|| format!("Failed to connect after {attempt} attempt(s)"));
+            }
+        }
+    }
+}
+
+/// Whether `e` is due to the server refusing the TCP connection, which is worth retrying.
+fn is_connection_refused(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::ConnectionRefused)
+}
+
+/// Connects to the server at `addr` with TLS like `connect`, but accepts any certificate the server
+/// presents instead of validating against a pinned certificate.
+///
+/// This is only intended for development, e.g. connecting to a remote server without a local copy
+/// of its certificate, because it cannot detect an impersonated server.
+///
+/// # Errors
+///
+/// Returns `Err` if the TLS connection process fails or times out.
+pub async fn connect_insecure(
+    addr: &str,
+    timeout: Duration,
+) -> Result<(ClientReader, ClientWriter)> {
+    connect_with_verifier(Arc::new(InsecureCertVerifier), addr, timeout).await
+}
+
+/// Connects to the server at `addr` with TLS like `connect`, but validates the server's certificate
+/// chain and hostname against trusted certificate authorities instead of a pinned certificate.
+///
+/// The CAs are read from the PEM bundle at `ca_file` if provided, or the system's trusted roots
+/// otherwise.
+///
+/// This is the appropriate mode for servers with a CA-signed certificate, in which case `addr`
+/// must use a hostname covered by the certificate.
+///
+/// # Errors
+///
+/// Returns `Err` if no trusted CAs could be loaded, or if the TLS connection process fails
+/// (including when the certificate is not trusted or does not match the hostname) or times out.
+pub async fn connect_with_ca(
+    ca_file: Option<&str>,
+    addr: &str,
+    timeout: Duration,
+) -> Result<(ClientReader, ClientWriter)> {
+    let mut roots = RootCertStore::empty();
+
+    if let Some(path) = ca_file {
+        for pem in pem::parse_many(
+            fs::read_to_string(path)
+                .with_context(|| format!("Failed to read CA file at path {path}"))?,
+        )? {
+            roots.add(CertificateDer::from(pem.into_contents()))?;
+        }
+    } else {
+        roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+    }
+
+    if roots.is_empty() {
+        bail!("No trusted CA certificates found");
+    }
+
+    connect_with_config(
+        config_builder()?
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+        addr,
+        timeout,
+    )
+    .await
+}
+
+/// Connects to the server at `addr` over raw TCP without TLS, timing out after `timeout`.
+/// Immediately splits into reader and writer halves.
+///
+/// This only works with a server serving plaintext (i.e., built with its `plaintext` feature), and
+/// is only intended for local testing, because nothing sent over the connection is encrypted.
+///
+/// # Errors
+///
+/// Returns `Err` if the TCP connection fails or times out.
+#[cfg(feature = "plaintext")]
+pub async fn connect_plaintext(
+    addr: &str,
+    timeout: Duration,
+) -> Result<(PlaintextReader, PlaintextWriter)> {
+    let socket = tokio::time::timeout(timeout, TcpStream::connect(addr))
+        .await
+        .context("Timeout connecting to server")??;
+
+    let (reader, writer) = tokio::io::split(socket);
+
+    Ok((BufReader::new(reader), writer))
+}
+
+/// Starts building a `ClientConfig`, first installing aws-lc-rs as the process-wide default crypto
+/// provider for Rustls unless a provider is already installed (e.g., by an application embedding
+/// the client), so that connecting never depends on a provider having been set up elsewhere.
+fn config_builder() -> Result<ConfigBuilder<ClientConfig, WantsVerifier>> {
+    // Failing to install means a provider was already installed, possibly by another thread
+    if CryptoProvider::get_default().is_none()
+        && aws_lc_rs::default_provider().install_default().is_err()
+        && CryptoProvider::get_default().is_none()
+    {
+        bail!("Failed to install the default crypto provider");
+    }
+
+    Ok(ClientConfig::builder())
+}
+
+/// Connects to the server at `addr` with TLS using `verifier` to validate the server's
+/// certificate, timing out after `timeout`. Immediately splits into reader and writer halves.
+async fn connect_with_verifier(
+    verifier: Arc<dyn ServerCertVerifier>,
+    addr: &str,
+    timeout: Duration,
+) -> Result<(ClientReader, ClientWriter)> {
+    connect_with_config(
+        config_builder()?
             .dangerous()
-            .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier::from_file(path)?))
+            .with_custom_certificate_verifier(verifier)
             .with_no_client_auth(),
-    ));
+        addr,
+        timeout,
+    )
+    .await
+}
+
+/// Connects to the server at `addr` with TLS as configured by `config`, timing out after
+/// `timeout`. Immediately splits into reader and writer halves.
+async fn connect_with_config(
+    config: ClientConfig,
+    addr: &str,
+    timeout: Duration,
+) -> Result<(ClientReader, ClientWriter)> {
+    let connector = TlsConnector::from(Arc::new(config));
 
     // Connect to the server with a timeout
     let socket = tokio::time::timeout(timeout, TcpStream::connect(addr))
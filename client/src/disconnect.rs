@@ -0,0 +1,45 @@
+/// Printed to stderr when the server closes the connection after the user sent `/quit`.
+const QUIT_NOTICE: &str = "Disconnected from server.";
+
+/// Printed to stderr when the server closes the connection without the user having sent `/quit`,
+/// e.g. because it shut down or the network dropped.
+const LOST_NOTICE: &str = "Connection lost.";
+
+/// Whether `line` (as typed by the user) asks the server to disconnect them, i.e., `/quit` with or
+/// without a parting message.
+#[must_use]
+pub fn is_quit(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed == "/quit" || trimmed.starts_with("/quit ")
+}
+
+/// The notice to print when the connection ends, depending on whether the user sent `/quit`.
+#[must_use]
+pub const fn disconnect_notice(quit_sent: bool) -> &'static str {
+    if quit_sent { QUIT_NOTICE } else { LOST_NOTICE }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_quit_commands() {
+        for line in ["/quit", "  /quit  ", "/quit gone fishing"] {
+            assert!(is_quit(line), "expected quit for {line:?}");
+        }
+    }
+
+    #[test]
+    fn ignores_other_lines() {
+        for line in ["/quitter", "quit", "/help", "please /quit", ""] {
+            assert!(!is_quit(line), "expected no quit for {line:?}");
+        }
+    }
+
+    #[test]
+    fn notices_distinguish_quitting_from_lost_connections() {
+        assert_eq!(disconnect_notice(true), "Disconnected from server.");
+        assert_eq!(disconnect_notice(false), "Connection lost.");
+    }
+}
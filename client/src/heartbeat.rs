@@ -0,0 +1,31 @@
+/// The line a server sends periodically (if configured) to keep the connection alive. Starts with
+/// the ENQ control character so that it cannot be confused with chat messages.
+const HEARTBEAT_LINE: &str = "\x05HEARTBEAT";
+
+/// Whether `line` (as received from the server) is a heartbeat, which should be ignored rather
+/// than displayed.
+#[must_use]
+pub fn is_heartbeat(line: &str) -> bool { line.trim_end() == HEARTBEAT_LINE }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_heartbeats() {
+        assert!(is_heartbeat("\x05HEARTBEAT\n"));
+        assert!(is_heartbeat("\x05HEARTBEAT\r\n"));
+    }
+
+    #[test]
+    fn ignores_regular_lines() {
+        for line in [
+            "HEARTBEAT\n",
+            "alice: \x05HEARTBEAT\n",
+            "\x05HEARTBEAT now\n",
+            "\n",
+        ] {
+            assert!(!is_heartbeat(line), "expected no heartbeat for {line:?}");
+        }
+    }
+}
@@ -1,4 +1,25 @@
-pub use client_connection::{ClientReader, ClientWriter, connect};
+pub use client_connection::{
+    ClientReader, ClientWriter, connect, connect_insecure, connect_with_ca, connect_with_retries,
+};
+#[cfg(feature = "plaintext")]
+pub use client_connection::{PlaintextReader, PlaintextWriter, connect_plaintext};
+pub use disconnect::{disconnect_notice, is_quit};
+pub use heartbeat::is_heartbeat;
+pub use line_input::{read_edited_lines, read_plain_lines};
+pub use name_colors::NameColors;
+pub use ping::pong_reply;
+pub use redirect::redirect_addr;
+pub use self_echo::{SelfEchoFilter, own_username};
+pub use transcript::{SaveCommand, Transcript};
 
 mod client_connection;
+mod disconnect;
+mod heartbeat;
+mod insecure_cert_verifier;
+mod line_input;
+mod name_colors;
+mod ping;
 mod pinned_cert_verifier;
+mod redirect;
+mod self_echo;
+mod transcript;
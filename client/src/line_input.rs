@@ -0,0 +1,130 @@
+use rustyline::{DefaultEditor, error::ReadlineError};
+use std::io::BufRead;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// The line sent on the user's behalf when they press Ctrl+C or Ctrl+D in the line editor, so that
+/// they leave the server cleanly instead of leaving the connection hanging.
+const QUIT_LINE: &str = "/quit";
+
+/// Reads lines typed by the user with a line editor, sending each completed line to `tx`.
+///
+/// The editor supports basic editing and recalling earlier lines with the up arrow. Pressing
+/// Ctrl+C or Ctrl+D sends `/quit` and stops reading.
+///
+/// Blocks until reading stops, so it should be run on a dedicated thread.
+///
+/// # Errors
+///
+/// Returns `Err` if the line editor cannot be created, in which case nothing has been read and
+/// `read_plain_lines` can be used instead. Errors reading lines are printed, but not returned.
+pub fn read_edited_lines(tx: &UnboundedSender<String>) -> Result<(), ReadlineError> {
+    let mut editor = DefaultEditor::new()?;
+
+    loop {
+        let input = editor.readline("");
+
+        if let Ok(line) = &input
+            && !line.trim().is_empty()
+        {
+            // History is a convenience, so failing to record a line is not worth reporting
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        if !forward_edited_line(input, tx) {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads lines from `reader` (e.g., piped stdin) without any line editing, sending each line to
+/// `tx` until reaching EOF. Used when stdin is not a terminal or line editing is turned off.
+///
+/// Blocks until reading stops, so it should be run on a dedicated thread.
+pub fn read_plain_lines(reader: impl BufRead, tx: &UnboundedSender<String>) {
+    for line_result in reader.lines() {
+        match line_result {
+            Err(e) => {
+                eprintln!("Error reading line from stdin: {e}");
+                break;
+            }
+
+            Ok(line) => {
+                if let Err(e) = tx.send(line) {
+                    eprintln!("Error sending line to stdin channel: {e}");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Sends the result of reading a line from the line editor to `tx`, sending `/quit` in place of
+/// Ctrl+C and Ctrl+D. Returns whether to keep reading.
+fn forward_edited_line(input: Result<String, ReadlineError>, tx: &UnboundedSender<String>) -> bool {
+    let (line, keep_reading) = match input {
+        Ok(line) => (line, true),
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => (String::from(QUIT_LINE), false),
+
+        Err(e) => {
+            eprintln!("Error reading line from stdin: {e}");
+            return false;
+        }
+    };
+
+    if let Err(e) = tx.send(line) {
+        eprintln!("Error sending line to stdin channel: {e}");
+        return false;
+    }
+
+    keep_reading
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn edited_lines_are_forwarded() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        assert!(forward_edited_line(Ok(String::from("hello")), &tx));
+        assert!(forward_edited_line(Ok(String::new()), &tx));
+
+        assert_eq!(rx.try_recv().ok().as_deref(), Some("hello"));
+        assert_eq!(rx.try_recv().ok().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn ctrl_c_and_ctrl_d_send_quit_and_stop_reading() {
+        for input in [ReadlineError::Interrupted, ReadlineError::Eof] {
+            let (tx, mut rx) = mpsc::unbounded_channel();
+
+            assert!(!forward_edited_line(Err(input), &tx));
+            assert_eq!(rx.try_recv().ok().as_deref(), Some("/quit"));
+        }
+    }
+
+    #[test]
+    fn closed_channel_stops_reading() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        drop(rx);
+
+        assert!(!forward_edited_line(Ok(String::from("hello")), &tx));
+    }
+
+    #[test]
+    fn plain_lines_are_forwarded_until_eof() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        read_plain_lines(Cursor::new("alice\nhello\r\n/quit\n"), &tx);
+        drop(tx);
+
+        let mut lines = Vec::new();
+        while let Some(line) = rx.blocking_recv() {
+            lines.push(line);
+        }
+        assert_eq!(lines, ["alice", "hello", "/quit"]);
+    }
+}
@@ -1,6 +1,14 @@
-use anyhow::{Context, Result};
-use std::{env, io::BufRead, time::Duration};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use anyhow::{Context, Result, bail};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering::Relaxed},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt},
+    sync::{Mutex, mpsc::UnboundedReceiver},
+};
 
 /// The amount of time to wait when connecting to the server.
 const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
@@ -14,19 +22,61 @@ fn main() -> Result<()> {
 }
 
 /// Connects to the server and writes to/reads from it using stdin/stdout until mutual
-/// `close_notify` (initiated by a "/quit" command).
+/// `close_notify` (initiated by a "/quit" command). If the server redirects clients to a new
+/// address while shutting down, reconnects there instead of exiting.
+///
+/// Before exiting, prints `Disconnected from server.` to stderr if the user sent "/quit", or
+/// `Connection lost.` if the connection ended without it (e.g., because the server shut down), so
+/// that stdout only ever contains chat.
 ///
 /// # Optional Environment Variable Configuration
 ///
 /// - `CERT_PATH` - Specify a file path other than `server.crt` for reading the server's
 ///   certificate.
 /// - `BIND_ADDR` - Specify an address other than `127.0.0.1:8000` for connecting to the server.
+/// - `PRATTLE_CA_FILE` - Validate the server's certificate and hostname against the certificate
+///   authorities in this PEM bundle instead of pinning the certificate at `CERT_PATH`.
+/// - `PRATTLE_SYSTEM_ROOTS` - Set to `1` to validate the server's certificate and hostname against
+///   the system's trusted certificate authorities instead of pinning the certificate at
+///   `CERT_PATH`.
+/// - `PRATTLE_INSECURE` - Set to `1` to accept any server certificate instead of the one at
+///   `CERT_PATH`. Only for development, since the server cannot be authenticated.
+///
+/// # Command Line Options
+///
+/// - `--no-self-echo` - Don't print your own messages when the server echoes them back, since you
+///   already see them as you type them.
+/// - `--no-line-editor` - Read stdin line by line without editing or history, as is always done
+///   when stdin is not a terminal (e.g., when input is piped in by a script).
+///
+/// # Local Commands
+///
+/// - `/save <path>` - Save the lines received from the server so far to a file instead of sending
+///   anything to the server.
 async fn async_main() -> Result<()> {
+    let mut no_self_echo = false;
+    let mut line_editor = io::stdin().is_terminal();
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--no-self-echo" => no_self_echo = true,
+            "--no-line-editor" => line_editor = false,
+            _ => bail!("Unknown argument: {arg}"),
+        }
+    }
+
     let cert_path = env::var("CERT_PATH").unwrap_or_else(|_| String::from("server.crt"));
-    let addr = env::var("BIND_ADDR").unwrap_or_else(|_| String::from("127.0.0.1:8000"));
+    let mut addr = env::var("BIND_ADDR").unwrap_or_else(|_| String::from("127.0.0.1:8000"));
+    let insecure = env_flag("PRATTLE_INSECURE");
+    let ca_file = env::var("PRATTLE_CA_FILE").ok();
+    let system_roots = env_flag("PRATTLE_SYSTEM_ROOTS");
 
-    let (mut reader, mut writer) =
-        prattle_client::connect(&cert_path, &addr, CONNECTION_TIMEOUT).await?;
+    if insecure {
+        eprintln!(
+            "Warning: PRATTLE_INSECURE is set, so the server's certificate is not verified and \
+            the connection may be intercepted"
+        );
+    }
 
     // Channel to send stdin lines from OS thread (unbounded because human input is small and much
     // slower than network writes, MPSC for simplicity given Tokio's API even though it's SPSC)
@@ -37,26 +87,67 @@ async fn async_main() -> Result<()> {
     // rather than waiting for the blocking `read` syscall to complete. Since the only resource
     // this thread holds is stdin, the OS cleans up properly when the process exits.
     std::thread::spawn(move || {
-        for line_result in std::io::stdin().lock().lines() {
-            match line_result {
-                Err(e) => {
-                    eprintln!("Error reading line from stdin: {e}");
-                    break;
-                }
-
-                Ok(line) => {
-                    if let Err(e) = stdin_tx.send(line) {
-                        eprintln!("Error sending line to stdin channel: {e}");
-                        break;
-                    }
-                }
+        if line_editor {
+            match prattle_client::read_edited_lines(&stdin_tx) {
+                Ok(()) => return,
+                Err(e) => eprintln!("Line editing unavailable, reading plain lines instead: {e}"),
             }
         }
+
+        prattle_client::read_plain_lines(io::stdin().lock(), &stdin_tx);
     });
 
+    // Kept across redirects so that `/save` includes every connection
+    let transcript = Mutex::new(prattle_client::Transcript::new());
+
+    loop {
+        let (reader, writer) = if insecure {
+            prattle_client::connect_insecure(&addr, CONNECTION_TIMEOUT).await?
+        } else if ca_file.is_some() || system_roots {
+            prattle_client::connect_with_ca(ca_file.as_deref(), &addr, CONNECTION_TIMEOUT).await?
+        } else {
+            prattle_client::connect(&cert_path, &addr, CONNECTION_TIMEOUT).await?
+        };
+
+        match run_session(reader, writer, &mut stdin_rx, &transcript, no_self_echo).await? {
+            None => break Ok(()),
+
+            Some(redirect_addr) => {
+                println!("Reconnecting to {redirect_addr}...");
+                addr = redirect_addr;
+            }
+        }
+    }
+}
+
+/// Checks whether the environment variable `name` is set to `1`, `true`, or `yes`.
+fn env_flag(name: &str) -> bool { matches!(env::var(name).as_deref(), Ok("1" | "true" | "yes")) }
+
+/// Relays lines between the server and stdin/stdout for a single connection until the server
+/// closes it, returning the address the server redirected the client to, if any. Lines printed
+/// from the server are recorded in `transcript`. If the client is not redirected, a notice of how
+/// the connection ended is printed to stderr.
+async fn run_session(
+    mut reader: prattle_client::ClientReader,
+    mut writer: prattle_client::ClientWriter,
+    stdin_rx: &mut UnboundedReceiver<String>,
+    transcript: &Mutex<prattle_client::Transcript>,
+    no_self_echo: bool,
+) -> Result<Option<String>> {
+    // Channel to send automatic replies to latency probes from the reading future to the writing
+    // future, kept separate so that it does not prevent the stdin channel from closing
+    let (pong_tx, mut pong_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    // Whether the user has sent "/quit", so that the end of the connection is expected
+    let quit_sent = AtomicBool::new(false);
+
+    let mut self_echo_filter = no_self_echo.then(prattle_client::SelfEchoFilter::new);
+    let mut name_colors = prattle_client::NameColors::new();
+
     // Future that reads from the server and prints to stdout
     let server_to_stdout = async {
         let mut line = String::new();
+        let mut redirect_addr = None;
 
         loop {
             match reader.read_line(&mut line).await {
@@ -71,13 +162,35 @@ async fn async_main() -> Result<()> {
                         break;
                     }
 
-                    // Print to stdout (line already includes newline)
-                    print!("{line}");
+                    // Answer latency probes, remember redirects, name colors, and the user's own
+                    // username, and skip heartbeats without showing them, otherwise print to stdout
+                    // (line already includes newline)
+                    if prattle_client::is_heartbeat(&line) || name_colors.update(&line) {
+                        // Nothing else to do
+                    } else if let Some(reply) = prattle_client::pong_reply(&line) {
+                        if pong_tx.send(reply).is_err() {
+                            break;
+                        }
+                    } else if let Some(addr) = prattle_client::redirect_addr(&line) {
+                        redirect_addr = Some(addr.to_string());
+                    } else if let Some(username) = prattle_client::own_username(&line) {
+                        if let Some(filter) = self_echo_filter.as_mut() {
+                            filter.set_username(username);
+                        }
+                    } else if self_echo_filter
+                        .as_ref()
+                        .is_none_or(|filter| filter.should_print(&line))
+                    {
+                        print!("{}", name_colors.paint(&line));
+                        transcript.lock().await.push(&line);
+                    }
                 }
             }
 
             line.clear();
         }
+
+        redirect_addr
     };
 
     // Future that reads from stdin and writes to the server
@@ -86,9 +199,39 @@ async fn async_main() -> Result<()> {
         // two-way TLS `close_notify` initiated by the server. Therefore, it is an error/misuse of
         // the CLI for reading from stdin (this future) to finish first.
         loop {
-            let line = stdin_rx.recv().await.context("stdin channel closed")?;
+            let line = tokio::select! {
+                stdin_line = stdin_rx.recv() => stdin_line.context("stdin channel closed")?,
+                Some(reply) = pong_rx.recv() => reply,
+            };
+
+            // Handle `/save` locally without sending anything to the server
+            match prattle_client::SaveCommand::parse(&line) {
+                Some(prattle_client::SaveCommand::Path(path)) => {
+                    let save_result = transcript.lock().await.save(path);
+
+                    match save_result {
+                        Ok(()) => eprintln!("Saved transcript to {path}"),
+                        Err(e) => eprintln!("Error saving transcript to {path}: {e}"),
+                    }
+                    continue;
+                }
+
+                Some(prattle_client::SaveCommand::MissingPath) => {
+                    eprintln!("Usage: /save <path>");
+                    continue;
+                }
+
+                None => {}
+            }
+
             writer.write_all(line.as_bytes()).await?;
             writer.write_all(b"\n").await?;
+            // TLS streams can hold the end of a write until the next write or flush
+            writer.flush().await?;
+
+            if prattle_client::is_quit(&line) {
+                quit_sent.store(true, Relaxed);
+            }
         }
     };
 
@@ -96,8 +239,16 @@ async fn async_main() -> Result<()> {
         // This future only finishes first under error/misuse conditions
         result = stdin_to_server => result,
 
-        // Normal path: client sent "/quit" -> server sent `close_notify` -> now client sends
-        // `close_notify` and exits
-        () = server_to_stdout => writer.shutdown().await.map_err(Into::into),
+        // Normal path: client sent "/quit" (or the server is shutting down) -> server sent
+        // `close_notify` -> now client sends `close_notify` and exits or follows the redirect
+        redirect_addr = server_to_stdout => {
+            writer.shutdown().await?;
+
+            if redirect_addr.is_none() {
+                eprintln!("{}", prattle_client::disconnect_notice(quit_sent.load(Relaxed)));
+            }
+
+            Ok(redirect_addr)
+        }
     }
 }
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+/// The prefix of the line a server sends when a user chooses a color for their name, followed by
+/// the username and the color. Starts with the ENQ control character so that it cannot be confused
+/// with chat messages.
+const COLOR_PREFIX: &str = "\x05COLOR ";
+
+/// The ANSI escape sequence that resets the terminal color.
+const RESET: &str = "\x1b[0m";
+
+/// Tracks the colors users chose for their names and renders their messages and actions with
+/// their names in those colors.
+#[derive(Debug, Default)]
+pub struct NameColors {
+    colors: HashMap<String, &'static str>,
+}
+
+impl NameColors {
+    /// Creates a tracker that does not know of any colors yet.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Records the color if `line` (as received from the server) announces a user's color,
+    /// returning whether it did, in which case it should not be displayed.
+    pub fn update(&mut self, line: &str) -> bool {
+        let Some((username, color)) = line
+            .trim_end()
+            .strip_prefix(COLOR_PREFIX)
+            .and_then(|rest| rest.rsplit_once(' '))
+        else {
+            return false;
+        };
+
+        // Colors this client doesn't know about are shown in the default color
+        match ansi_code(color) {
+            Some(code) => self.colors.insert(username.to_string(), code),
+            None => self.colors.remove(username),
+        };
+
+        true
+    }
+
+    /// Renders `line` with the sender's name in their color if it is a message (`username: ...`)
+    /// or action (`* username ...`) from a user who chose a color.
+    #[must_use]
+    pub fn paint(&self, line: &str) -> String {
+        for (username, code) in &self.colors {
+            if let Some(rest) = line
+                .strip_prefix(username.as_str())
+                .filter(|rest| rest.starts_with(": "))
+            {
+                return format!("{code}{username}{RESET}{rest}");
+            }
+
+            if let Some(rest) = line
+                .strip_prefix("* ")
+                .and_then(|rest| rest.strip_prefix(username.as_str()))
+                .filter(|rest| rest.starts_with(' '))
+            {
+                return format!("* {code}{username}{RESET}{rest}");
+            }
+        }
+
+        line.to_string()
+    }
+}
+
+/// The ANSI escape sequence for `color`, which is one of the colors servers let users choose.
+fn ansi_code(color: &str) -> Option<&'static str> {
+    match color {
+        "red" => Some("\x1b[31m"),
+        "green" => Some("\x1b[32m"),
+        "yellow" => Some("\x1b[33m"),
+        "blue" => Some("\x1b[34m"),
+        "magenta" => Some("\x1b[35m"),
+        "cyan" => Some("\x1b[36m"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_color_lines() {
+        let mut colors = NameColors::new();
+        assert!(colors.update("\x05COLOR alice red\n"));
+        assert!(colors.update("\x05COLOR bob chartreuse\r\n"));
+
+        for line in ["COLOR alice red\n", "alice: \x05COLOR alice red\n", "\n"] {
+            assert!(!colors.update(line), "expected no color line for {line:?}");
+        }
+    }
+
+    #[test]
+    fn paints_messages_and_actions_from_colored_users() {
+        let mut colors = NameColors::new();
+        colors.update("\x05COLOR alice red\n");
+
+        assert_eq!(colors.paint("alice: hi\n"), "\x1b[31malice\x1b[0m: hi\n");
+        assert_eq!(
+            colors.paint("* alice waves\n"),
+            "* \x1b[31malice\x1b[0m waves\n"
+        );
+
+        // Other users, notices, and lookalike names are left alone
+        for line in [
+            "bob: hi\n",
+            "alicent: hi\n",
+            "* bob waves\n",
+            "Server is shutting down\n",
+        ] {
+            assert_eq!(colors.paint(line), line);
+        }
+    }
+
+    #[test]
+    fn unknown_colors_reset_to_the_default() {
+        let mut colors = NameColors::new();
+        colors.update("\x05COLOR alice red\n");
+        colors.update("\x05COLOR alice chartreuse\n");
+
+        assert_eq!(colors.paint("alice: hi\n"), "alice: hi\n");
+    }
+}
@@ -0,0 +1,35 @@
+/// Returns the reply to send if `line` (as received from the server) is a latency probe from
+/// `/ping-all`, or `None` if it is a regular line that should be displayed.
+#[must_use]
+pub fn pong_reply(line: &str) -> Option<String> {
+    line.trim_end()
+        .strip_prefix("/ping ")
+        .filter(|token| !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit()))
+        .map(|token| format!("/pong {token}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_to_probes() {
+        assert_eq!(
+            pong_reply("/ping 1760600000000\n").as_deref(),
+            Some("/pong 1760600000000")
+        );
+    }
+
+    #[test]
+    fn ignores_regular_lines() {
+        for line in [
+            "alice: /ping 123\n",
+            "/ping\n",
+            "/ping \n",
+            "/ping 12: hi\n",
+            "* bob left\n",
+        ] {
+            assert_eq!(pong_reply(line), None, "expected no reply for {line:?}");
+        }
+    }
+}
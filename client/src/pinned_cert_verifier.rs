@@ -60,16 +60,7 @@ impl ServerCertVerifier for PinnedCertVerifier {
         cert: &CertificateDer<'_>,
         dss: &DigitallySignedStruct,
     ) -> Result<HandshakeSignatureValid, rustls::Error> {
-        rustls::crypto::verify_tls12_signature(
-            message,
-            cert,
-            dss,
-            &rustls::crypto::CryptoProvider::get_default()
-                .ok_or(rustls::Error::General(String::from(
-                    "No default crypto provider",
-                )))?
-                .signature_verification_algorithms,
-        )
+        verify_tls12_signature(message, cert, dss)
     }
 
     fn verify_tls13_signature(
@@ -78,25 +69,55 @@ impl ServerCertVerifier for PinnedCertVerifier {
         cert: &CertificateDer<'_>,
         dss: &DigitallySignedStruct,
     ) -> Result<HandshakeSignatureValid, rustls::Error> {
-        rustls::crypto::verify_tls13_signature(
-            message,
-            cert,
-            dss,
-            &rustls::crypto::CryptoProvider::get_default()
-                .ok_or(rustls::Error::General(String::from(
-                    "No default crypto provider",
-                )))?
-                .signature_verification_algorithms,
-        )
+        verify_tls13_signature(message, cert, dss)
     }
 
-    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
-        rustls::crypto::CryptoProvider::get_default()
-            .map(|provider| {
-                provider
-                    .signature_verification_algorithms
-                    .supported_schemes()
-            })
-            .unwrap_or_default()
-    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> { supported_verify_schemes() }
+}
+
+/// Verifies a TLS 1.2 handshake signature using the default crypto provider's algorithms.
+pub fn verify_tls12_signature(
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+) -> Result<HandshakeSignatureValid, rustls::Error> {
+    rustls::crypto::verify_tls12_signature(
+        message,
+        cert,
+        dss,
+        &rustls::crypto::CryptoProvider::get_default()
+            .ok_or(rustls::Error::General(String::from(
+                "No default crypto provider",
+            )))?
+            .signature_verification_algorithms,
+    )
+}
+
+/// Verifies a TLS 1.3 handshake signature using the default crypto provider's algorithms.
+pub fn verify_tls13_signature(
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+) -> Result<HandshakeSignatureValid, rustls::Error> {
+    rustls::crypto::verify_tls13_signature(
+        message,
+        cert,
+        dss,
+        &rustls::crypto::CryptoProvider::get_default()
+            .ok_or(rustls::Error::General(String::from(
+                "No default crypto provider",
+            )))?
+            .signature_verification_algorithms,
+    )
+}
+
+/// The signature schemes supported by the default crypto provider.
+pub fn supported_verify_schemes() -> Vec<SignatureScheme> {
+    rustls::crypto::CryptoProvider::get_default()
+        .map(|provider| {
+            provider
+                .signature_verification_algorithms
+                .supported_schemes()
+        })
+        .unwrap_or_default()
 }
@@ -0,0 +1,46 @@
+/// The prefix of the line a server sends while shutting down to redirect clients to a new address.
+/// Starts with the ENQ control character so that it cannot be confused with chat messages.
+const REDIRECT_PREFIX: &str = "\x05REDIRECT ";
+
+/// Returns the address to reconnect to if `line` (as received from the server) is a redirect, or
+/// `None` if it is a regular line that should be displayed.
+#[must_use]
+pub fn redirect_addr(line: &str) -> Option<&str> {
+    line.trim_end()
+        .strip_prefix(REDIRECT_PREFIX)
+        .filter(|addr| !addr.is_empty() && !addr.contains(char::is_whitespace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_redirects() {
+        assert_eq!(
+            redirect_addr("\x05REDIRECT chat.example.com:8000\n"),
+            Some("chat.example.com:8000")
+        );
+        assert_eq!(
+            redirect_addr("\x05REDIRECT 10.0.0.2:9000\r\n"),
+            Some("10.0.0.2:9000")
+        );
+    }
+
+    #[test]
+    fn ignores_regular_lines() {
+        for line in [
+            "REDIRECT 10.0.0.2:9000\n",
+            "alice: \x05REDIRECT 10.0.0.2:9000\n",
+            "\x05REDIRECT \n",
+            "\x05REDIRECT a b\n",
+            "Server is shutting down\n",
+        ] {
+            assert_eq!(
+                redirect_addr(line),
+                None,
+                "expected no redirect for {line:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,104 @@
+/// The prefix of the line a server sends after username selection to tell the client the username
+/// it joined with, followed by the username. Starts with the ENQ control character so that it
+/// cannot be confused with chat messages.
+const USERNAME_PREFIX: &str = "\x05USERNAME ";
+
+/// Returns the username the user joined with if `line` (as received from the server) announces it,
+/// or `None` if it is a regular line that should be displayed.
+#[must_use]
+pub fn own_username(line: &str) -> Option<&str> {
+    line.trim_end()
+        .strip_prefix(USERNAME_PREFIX)
+        .filter(|username| !username.is_empty())
+}
+
+/// Suppresses the server's echo of the user's own messages, which they already see as they type
+/// them. The user's username is learned from the line identified by `own_username`.
+#[derive(Debug, Default)]
+pub struct SelfEchoFilter {
+    username: Option<String>,
+}
+
+impl SelfEchoFilter {
+    /// Creates a filter that does not know the user's username yet.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Records the username the user joined with, so that their messages are recognized.
+    pub fn set_username(&mut self, username: &str) { self.username = Some(username.to_string()); }
+
+    /// Returns whether `line` (as received from the server) should be printed, which is the case
+    /// for everything except the user's own messages.
+    #[must_use]
+    pub fn should_print(&self, line: &str) -> bool {
+        self.username
+            .as_deref()
+            .is_none_or(|username| !is_own_message(line, username))
+    }
+}
+
+/// Whether `line` is a message sent by `username`, i.e., of the form `username: message`.
+fn is_own_message(line: &str, username: &str) -> bool {
+    line.strip_prefix(username)
+        .is_some_and(|rest| rest.starts_with(": "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn own_messages_are_detected() {
+        assert!(is_own_message("alice: hi\n", "alice"));
+        assert!(is_own_message("alice: \n", "alice"));
+    }
+
+    #[test]
+    fn other_lines_are_not_own_messages() {
+        for line in [
+            "bob: hi\n",
+            "alicia: hi\n",
+            "alice2: hi\n",
+            "* alice waves\n",
+            "* alice joined the server\n",
+            "bob: alice: hi\n",
+            "alice\n",
+            "Hi alice, welcome to Prattle! (Send /help for help)\n",
+        ] {
+            assert!(
+                !is_own_message(line, "alice"),
+                "expected {line:?} not to match"
+            );
+        }
+    }
+
+    #[test]
+    fn username_lines_are_recognized() {
+        assert_eq!(own_username("\x05USERNAME alice\n"), Some("alice"));
+        assert_eq!(own_username("\x05USERNAME alice\r\n"), Some("alice"));
+
+        for line in [
+            "USERNAME alice\n",
+            "\x05USERNAME \n",
+            "bob: \x05USERNAME alice\n",
+            "Hi alice, welcome to Prattle! (Send /help for help)\n",
+        ] {
+            assert_eq!(own_username(line), None, "expected {line:?} not to match");
+        }
+    }
+
+    #[test]
+    fn filter_suppresses_own_messages_once_the_username_is_known() {
+        let mut filter = SelfEchoFilter::new();
+
+        // Nothing is suppressed before the username is known, whatever the welcome says
+        assert!(filter.should_print("Choose a username:\n"));
+        assert!(filter.should_print("alice: hi\n"));
+        assert!(filter.should_print("Greetings. Welcome to Acme Chat.\n"));
+
+        filter.set_username("alice");
+        assert!(filter.should_print("* alice joined the server\n"));
+        assert!(!filter.should_print("alice: hi\n"));
+        assert!(filter.should_print("bob: hi alice\n"));
+    }
+}
@@ -0,0 +1,98 @@
+use std::{fs, io, path::Path};
+
+/// A `/save` command typed by the user, which is handled by the CLI instead of being sent to the
+/// server.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SaveCommand<'a> {
+    /// Save the transcript to the contained path.
+    Path(&'a str),
+
+    /// `/save` was typed without a path.
+    MissingPath,
+}
+
+impl<'a> SaveCommand<'a> {
+    /// Parses a `/save` command from a line typed by the user, or returns `None` if it is any
+    /// other line that should be sent to the server.
+    #[must_use]
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let trimmed = line.trim();
+
+        if trimmed == "/save" {
+            Some(Self::MissingPath)
+        } else {
+            trimmed
+                .strip_prefix("/save ")
+                .map(|path| Self::Path(path.trim_start()))
+        }
+    }
+}
+
+/// The lines displayed from the server so far, which can be saved to a file with `/save <path>`.
+#[derive(Debug, Default)]
+pub struct Transcript {
+    lines: Vec<String>,
+}
+
+impl Transcript {
+    /// Creates an empty transcript.
+    #[must_use]
+    pub const fn new() -> Self { Self { lines: Vec::new() } }
+
+    /// Appends a line as received from the server, including its newline.
+    pub fn push(&mut self, line: &str) { self.lines.push(line.to_string()); }
+
+    /// Writes the transcript to `path`, replacing the file if it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.lines.concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_save_commands() {
+        assert_eq!(
+            SaveCommand::parse("/save chat.log"),
+            Some(SaveCommand::Path("chat.log"))
+        );
+        assert_eq!(
+            SaveCommand::parse("  /save   logs/chat.txt  "),
+            Some(SaveCommand::Path("logs/chat.txt"))
+        );
+        assert_eq!(SaveCommand::parse("/save"), Some(SaveCommand::MissingPath));
+        assert_eq!(
+            SaveCommand::parse(" /save \n"),
+            Some(SaveCommand::MissingPath)
+        );
+    }
+
+    #[test]
+    fn ignores_other_lines() {
+        for line in ["hello", "/saved", "/savefile x", "I will /save it", "/who"] {
+            assert_eq!(SaveCommand::parse(line), None, "expected None for {line:?}");
+        }
+    }
+
+    #[test]
+    fn saves_lines_in_order() -> io::Result<()> {
+        let path = std::env::temp_dir().join(format!("prattle-transcript-{}", std::process::id()));
+
+        let mut transcript = Transcript::new();
+        transcript.push("Hi alice, welcome to Prattle!\n");
+        transcript.push("bob: hello\n");
+        transcript.save(&path)?;
+
+        let saved = fs::read_to_string(&path);
+        fs::remove_file(&path)?;
+        assert_eq!(saved?, "Hi alice, welcome to Prattle!\nbob: hello\n");
+
+        Ok(())
+    }
+}
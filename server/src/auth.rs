@@ -0,0 +1,35 @@
+/// Compares two byte strings in time that depends only on their lengths, not their contents, to
+/// avoid leaking how much of a secret was guessed correctly through timing.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_inputs_match() {
+        for input in ["", "a", "secret", "パスワード"] {
+            assert!(
+                constant_time_eq(input.as_bytes(), input.as_bytes()),
+                "expected {input} to match itself"
+            );
+        }
+    }
+
+    #[test]
+    fn different_inputs_do_not_match() {
+        for (a, b) in [
+            ("secret", "secreT"),
+            ("secret", "secret "),
+            ("", "a"),
+            ("abc", "xbc"),
+        ] {
+            assert!(
+                !constant_time_eq(a.as_bytes(), b.as_bytes()),
+                "expected {a} not to match {b}"
+            );
+        }
+    }
+}
@@ -1,29 +1,93 @@
 use crate::{
-    command::{COMMAND_HELP, Command},
-    server::GLOBAL_SHUTDOWN_TIMEOUT,
+    auth,
+    command::{
+        COLORS, COMMAND_HELP, COMMAND_LIST, COMMAND_MANIFEST, Command, Dice, MAX_DICE,
+        MAX_LOG_LINES, MAX_SIDES, MAX_SLOW_MODE_SECS, WhoFormat, command_detail,
+    },
+    config::{Config, ConfirmUsername, MAX_USERNAME_LEN, UNKNOWN_USERNAME},
+    event::ChatEvent,
+    fanout::Subscription,
+    history::MAX_REPLAY_BYTES,
+    line_reader::{LineRead, LineReader},
+    mailbox::OfflineMsg,
+    sanitize::sanitize,
+    server::PendingGuard,
+    state::{ControlMessage, PingProbe, SharedState},
+    user::UserInfo,
 };
 use anyhow::{Result, anyhow};
-use std::{collections::HashSet, sync::Arc, time::Duration};
+use rand::Rng;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    future,
+    net::SocketAddr,
+    sync::{Arc, OnceLock, atomic::Ordering::SeqCst},
+    time::{Duration, Instant, SystemTime},
+};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
     sync::{
-        Mutex,
-        broadcast::{Receiver, Sender, error::RecvError},
+        broadcast::{Receiver, error::RecvError},
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
     },
+    time::{self, MissedTickBehavior},
 };
-use tracing::{error, info, warn};
+use tracing::{Instrument, Span, error, info, warn};
+
+/// The time to wait for clients to answer a `/ping-all` probe before reporting the results.
+const PING_ALL_WINDOW: Duration = Duration::from_secs(2);
+
+/// The line sent to clients periodically if heartbeats are enabled. Starts with the ENQ control
+/// character (like the redirect line sent on shutdown) so that clients can recognize and hide it.
+const HEARTBEAT_LINE: &[u8] = b"\x05HEARTBEAT\n";
+
+/// The prefix of the line telling clients which color a user chose for their name, followed by the
+/// username and the color. Starts with the ENQ control character so that clients can recognize
+/// and hide it.
+const COLOR_PREFIX: &str = "\x05COLOR ";
+
+/// The prefix of the line telling a joining client the username it joined with, followed by the
+/// username. Lets clients recognize their own messages without depending on the welcome message.
+const USERNAME_PREFIX: &str = "\x05USERNAME ";
+
+/// The longest the handler spends writing messages that were still queued when the server started
+/// shutting down, so that a slow reader can't delay shutdown.
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The number of wrong passwords a client can send before being disconnected.
+const MAX_PASSWORD_ATTEMPTS: usize = 3;
 
-/// The time to wait for a client to close their connection before forcefully disconnecting.
-const CLIENT_DISCONNECT_TIMEOUT: Duration =
-    GLOBAL_SHUTDOWN_TIMEOUT.saturating_sub(Duration::from_secs(1));
+/// The maximum length of a signature set with `/sig`, in characters.
+const MAX_SIG_LENGTH: usize = 32;
 
-/// The placeholder username to use if a client has not yet chosen a username.
-const UNKNOWN_USERNAME: &str = "[unknown]";
+/// The reply to a line that exceeds the length limit.
+const INPUT_TOO_LONG: &[u8] = b"Input too long\n";
 
-type Users = Arc<Mutex<HashSet<String>>>;
+/// The reply sent before disconnecting a client that has used up their username attempts.
+const TOO_MANY_ATTEMPTS: &[u8] = b"Too many invalid attempts, disconnecting\n";
 
-/// Handles an individual client, prompting them for a username and then entering the main
-/// read/write command loop. Gracefully disconnects when the client quits or the server shuts down.
+/// The reply to `/version`.
+const VERSION_LINE: &str = concat!("Prattle server v", env!("CARGO_PKG_VERSION"), "\n");
+
+/// The user a client handler joined as, for cleaning up after the handler if it panics.
+pub struct Session {
+    /// The username the client chose.
+    pub username: String,
+
+    /// The sender half of the handler's control channel, which identifies the session's entry in
+    /// `users` even if another session has since taken over the username.
+    pub control_tx: UnboundedSender<ControlMessage>,
+}
+
+/// Handles an individual client, prompting them for the server password (if one is configured) and
+/// a username and then entering the main read/write command loop. Gracefully disconnects when the
+/// client quits or the server shuts down.
+///
+/// The `pending_guard` is held until a username is chosen, releasing the client's slot among the
+/// connections allowed to be in username selection at once. The chosen username is then recorded
+/// in `session_slot` so that the server can clean up after the client if this handler panics.
+/// `client_addr` is recorded with the user's state for `/whois`.
 ///
 /// # Errors
 ///
@@ -31,10 +95,11 @@ type Users = Arc<Mutex<HashSet<String>>>;
 /// errors.
 pub async fn handle_client<S>(
     socket: S,
-    tx: Sender<String>,
-    rx: Receiver<String>,
+    state: Arc<SharedState>,
     mut shutdown_rx: Receiver<()>,
-    users: Users,
+    pending_guard: PendingGuard,
+    session_slot: Arc<OnceLock<Session>>,
+    client_addr: SocketAddr,
 ) -> Result<()>
 where
     S: AsyncRead + AsyncWrite + Unpin,
@@ -42,61 +107,382 @@ where
     let (inner_reader, mut writer) = tokio::io::split(socket);
     let mut reader = BufReader::new(inner_reader);
 
-    let mut line = String::new();
+    let mut line_reader = LineReader::default();
+    let (control_tx, control_rx) = mpsc::unbounded_channel();
+
+    if let Some(banner) = state.config.messages.banner_text() {
+        write_timed(&mut writer, banner.as_bytes(), &state.config).await?;
+    }
+
+    if !check_password(
+        &mut reader,
+        &mut writer,
+        &mut line_reader,
+        &mut shutdown_rx,
+        &state.config,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    // Usernames sent before the prompt (e.g., by scripts) stay buffered until they are read, so
+    // they are handled the same as usernames sent in response to it
+    let mut send_prompt = true;
+    let mut attempts = 0;
+
+    let (username, username_changed, claim, quiet) = loop {
+        let prompt = send_prompt.then_some(state.config.messages.prompt.as_str());
+        send_prompt = !state.config.prompt_once;
 
-    let username = loop {
         tokio::select! {
             shutdown_result = shutdown_rx.recv() => {
-                if let Err(e) = shutdown_result {
-                    error!("Error receiving shutdown signal during username selection: {e}");
-                }
-
-                // Attempt graceful disconnect regardless of the write result, but still report
-                // write errors to the main server loop
-                let write_res = writer.write_all(b"\nServer is shutting down\n").await;
-                graceful_disconnect(&mut reader, &mut writer, UNKNOWN_USERNAME).await;
-                return write_res.map_err(Into::into);
+                return disconnect_for_shutdown(
+                    &mut reader,
+                    &mut writer,
+                    shutdown_result,
+                    &state.config,
+                    "username selection",
+                )
+                .await;
             }
 
-            read_result = async {
-                writer.write_all(b"Choose a username:\n").await?;
-                reader.read_line(&mut line).await
-            } => {
-                read_result?;
-                let read_username = line.trim().to_string();
-                line.clear();
-
-                if read_username.is_empty() {
-                    writer.write_all(b"Username cannot be empty\n").await?;
-                } else if read_username == UNKNOWN_USERNAME {
-                    writer.write_all(b"Invalid username\n").await?;
-                } else {
-                    let mut users_guard = users.lock().await;
-
-                    if users_guard.contains(&read_username) {
-                        drop(users_guard);
-                        writer.write_all(b"Username taken\n").await?;
-                    } else {
-                        users_guard.insert(read_username.clone());
-                        drop(users_guard);
-                        break read_username;
+            read_result = prompt_and_read_line(&mut reader, &mut writer, &mut line_reader, prompt, &state.config) => {
+                let line = match read_result? {
+                    LineRead::Line(line) => line,
+
+                    LineRead::TooLong => {
+                        write_timed(&mut writer, INPUT_TOO_LONG, &state.config).await?;
+                        continue;
+                    }
+
+                    LineRead::Eof => {
+                        info!("Client disconnected during username selection");
+                        return Ok(());
+                    }
+                };
+
+                let (requested, quiet) = split_quiet_marker(line.trim());
+
+                match claim_username(&state, requested, &control_tx, client_addr).await {
+                    Ok(claim) => {
+                        let username = claim.username.clone();
+                        session_slot.get_or_init(|| Session {
+                            username: username.clone(),
+                            control_tx: control_tx.clone(),
+                        });
+                        Span::current().record("username", username.as_str());
+                        let changed = line.trim_end_matches(['\r', '\n']) != username;
+                        break (username, changed, claim, quiet);
+                    }
+
+                    Err(rejection) => {
+                        write_timed(&mut writer, rejection.as_bytes(), &state.config).await?;
+                        attempts += 1;
+
+                        if state.config.max_username_attempts.is_some_and(|max| attempts >= max) {
+                            warn!("Disconnecting client after {attempts} rejected usernames");
+                            write_timed(&mut writer, TOO_MANY_ATTEMPTS, &state.config).await?;
+                            graceful_disconnect(&mut reader, &mut writer, UNKNOWN_USERNAME, &state.config)
+                                .await;
+                            return Ok(());
+                        }
                     }
                 }
             }
         }
     };
 
-    ClientHandler { reader, writer, tx, rx, shutdown_rx, username, users }
-        .run()
+    drop(pending_guard);
+
+    let greeting = Greeting::choose(&state.config, quiet, username_changed);
+
+    let (rx, backlog) = subscribe_with_backlog(&state).await;
+
+    ClientHandler {
+        reader,
+        line_reader,
+        writer,
+        rx,
+        prefs: Preferences::new(&state.config),
+        state,
+        control_rx,
+        shutdown_rx,
+        username,
+        pending_ping: None,
+        sig: None,
+        taken_over: false,
+        last_msg: None,
+        last_post: None,
+    }
+    .run(greeting, &claim, &backlog)
+    .await
+}
+
+/// A username successfully claimed by a client.
+struct Claim {
+    /// The claimed username, which differs from the requested one if a reconnect token was given.
+    username: String,
+
+    /// Whether an existing session with the same username was taken over.
+    took_over: bool,
+
+    /// The number of users online right after the username was claimed.
+    online: usize,
+}
+
+/// The rejection sent for a reconnect token that is unknown, expired, or already used.
+const INVALID_TOKEN_MESSAGE: &str = "Invalid or expired token\n";
+
+/// Adds the `requested` username to `users` for a client connecting from `client_addr` whose
+/// handler listens on `control_tx`, returning the claim or the message explaining why the username
+/// was rejected. A request of the form `/token <token>` claims the username that the reconnect
+/// token was issued for instead, as long as no one is using it.
+async fn claim_username(
+    state: &SharedState,
+    requested: &str,
+    control_tx: &UnboundedSender<ControlMessage>,
+    client_addr: SocketAddr,
+) -> Result<Claim, String> {
+    let token = requested.strip_prefix("/token ").map(str::trim_start);
+
+    let username = match token {
+        Some(token) => state
+            .reconnect_tokens
+            .lock()
+            .await
+            .lookup(token, Instant::now())
+            .ok_or_else(|| String::from(INVALID_TOKEN_MESSAGE))?,
+
+        None => requested.to_string(),
+    };
+    let username = username.as_str();
+
+    check_username(&state.config, username)?;
+
+    let mut users_guard = state.users.lock().await;
+
+    // Tokens only restore a username that is free, even if takeovers are allowed, so that a
+    // leaked token can't be used to kick its owner
+    if users_guard.contains_key(username) {
+        if token.is_some() {
+            return Err(String::from(
+                "That username is still in use; your token will work once it is free\n",
+            ));
+        }
+
+        if !state.config.allow_takeover {
+            return Err(taken_message(&suggest_usernames(
+                username,
+                &users_guard,
+                &state.config,
+            )));
+        }
+    }
+
+    // Only used up once the username is known to be free, so that a rejected attempt can be
+    // retried with the same token
+    if let Some(token) = token
+        && state
+            .reconnect_tokens
+            .lock()
+            .await
+            .redeem(token, Instant::now())
+            .is_none()
+    {
+        return Err(String::from(INVALID_TOKEN_MESSAGE));
+    }
+
+    let reconnect_token = if state.config.reconnect_window.is_some() {
+        Some(
+            state
+                .reconnect_tokens
+                .lock()
+                .await
+                .issue(username, Instant::now()),
+        )
+    } else {
+        None
+    };
+
+    let replaced = users_guard.insert(
+        username.to_string(),
+        UserInfo { reconnect_token, ..UserInfo::new(control_tx.clone(), client_addr) },
+    );
+
+    // The old session is told while the lock is still held, so that it can tell whether it still
+    // owns the username when it exits
+    if let Some(replaced) = &replaced {
+        info!("{username} is taking over an existing session");
+        // An error means the old session is already exiting
+        let _ = replaced.control_tx.send(ControlMessage::TakenOver);
+
+        if let Some(token) = &replaced.reconnect_token {
+            state.reconnect_tokens.lock().await.revoke(token);
+        }
+    }
+
+    let online = users_guard.len();
+    drop(users_guard);
+
+    Ok(Claim { username: username.to_string(), took_over: replaced.is_some(), online })
+}
+
+/// Subscribes to broadcasts, returning the subscription along with the replay of recent history.
+/// Subscribing while the history is locked means that each line is either in the backlog or
+/// received from the subscription, never both or neither.
+async fn subscribe_with_backlog(state: &SharedState) -> (Subscription, String) {
+    let history = state.history.lock().await;
+    (state.fanout.subscribe(), history.replay(MAX_REPLAY_BYTES))
+}
+
+/// Sends `prompt` as its own line if given, then reads the client's next line. Everything written
+/// so far is flushed first, since TLS streams can hold the end of a write until the next write or
+/// flush, which would leave the client waiting for a prompt (or rejection) that never arrives. Both
+/// the prompt and the flush are subject to the write timeout.
+async fn prompt_and_read_line<R, W>(
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    line_reader: &mut LineReader,
+    prompt: Option<&str>,
+    config: &Config,
+) -> Result<LineRead>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    if let Some(prompt) = prompt {
+        write_timed(writer, format!("{prompt}\n").as_bytes(), config).await?;
+    }
+
+    tokio::time::timeout(config.write_timeout, writer.flush())
         .await
+        .map_err(|_| anyhow!("Timed out writing to client"))??;
+    Ok(line_reader.read_line(reader).await?)
+}
+
+/// Prompts the client for the server password if one is configured, allowing up to
+/// `MAX_PASSWORD_ATTEMPTS` attempts. Returns whether the client may proceed to username selection.
+/// If not, the client has already been disconnected.
+async fn check_password<R, W>(
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    line_reader: &mut LineReader,
+    shutdown_rx: &mut Receiver<()>,
+    config: &Config,
+) -> Result<bool>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let Some(password) = config.password.as_deref() else {
+        return Ok(true);
+    };
+
+    let mut attempts = 0;
+
+    loop {
+        tokio::select! {
+            shutdown_result = shutdown_rx.recv() => {
+                disconnect_for_shutdown(reader, writer, shutdown_result, config, "password entry")
+                    .await?;
+                return Ok(false);
+            }
+
+            read_result = prompt_and_read_line(reader, writer, line_reader, Some("Password:"), config) => {
+                let line = match read_result? {
+                    LineRead::Line(line) => line,
+
+                    LineRead::TooLong => {
+                        write_timed(writer, INPUT_TOO_LONG, config).await?;
+                        continue;
+                    }
+
+                    LineRead::Eof => {
+                        info!("Client disconnected during password entry");
+                        return Ok(false);
+                    }
+                };
+
+                if auth::constant_time_eq(
+                    line.trim_end_matches(['\r', '\n']).as_bytes(),
+                    password.as_bytes(),
+                ) {
+                    return Ok(true);
+                }
+
+                write_timed(writer, b"Invalid password\n", config).await?;
+                attempts += 1;
+
+                if attempts >= MAX_PASSWORD_ATTEMPTS {
+                    warn!("Disconnecting client after {attempts} invalid password attempts");
+                    graceful_disconnect(reader, writer, UNKNOWN_USERNAME, config).await;
+                    return Ok(false);
+                }
+            }
+        }
+    }
 }
 
-/// Shuts down the output stream and waits for the client to close the connection, timing out if
-/// they fail to disconnect gracefully. Logs any errors encountered instead of returning them.
-async fn graceful_disconnect<R, W>(reader: &mut BufReader<R>, writer: &mut W, username: &str)
+/// Creates the notice sent to clients when the server shuts down, which says how long they have
+/// before being forcefully disconnected. If a redirect address is configured, the notice includes a
+/// hint for people along with a line that capable clients use to reconnect automatically.
+fn shutdown_notice(config: &Config) -> String {
+    // Whichever timeout runs out first cuts the client off
+    let secs = config
+        .client_disconnect_timeout
+        .min(config.shutdown_timeout)
+        .as_millis()
+        .div_ceil(1000);
+    let plural = if secs == 1 { "" } else { "s" };
+    let notice = format!("Server is shutting down; you have {secs} second{plural} to finish up\n");
+
+    match &config.redirect_addr {
+        Some(addr) => format!("{notice}Please reconnect to {addr}\n\x05REDIRECT {addr}\n"),
+        None => notice,
+    }
+}
+
+/// Notifies a client that has not yet chosen a username that the server is shutting down and
+/// disconnects them. `phase` describes what the client was doing, for logging.
+async fn disconnect_for_shutdown<R, W>(
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    shutdown_result: Result<(), RecvError>,
+    config: &Config,
+    phase: &str,
+) -> Result<()>
 where
     R: AsyncRead + Unpin,
     W: AsyncWrite + Unpin,
+{
+    if let Err(e) = shutdown_result {
+        error!("Error receiving shutdown signal during {phase}: {e}");
+    }
+
+    // Attempt graceful disconnect regardless of the write result, but still report write errors to
+    // the main server loop
+    let write_res = write_timed(
+        writer,
+        format!("\n{}", shutdown_notice(config)).as_bytes(),
+        config,
+    )
+    .await;
+    graceful_disconnect(reader, writer, UNKNOWN_USERNAME, config).await;
+    write_res
+}
+
+/// Shuts down the output stream and waits for the client to close the connection, timing out after
+/// the configured client disconnect timeout if they fail to disconnect gracefully. Logs any errors
+/// encountered instead of returning them.
+async fn graceful_disconnect<R, W>(
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    username: &str,
+    config: &Config,
+) where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
 {
     // Close the write side
     if let Err(e) = writer.shutdown().await {
@@ -106,9 +492,12 @@ where
     let mut discard = Vec::new();
 
     // Wait for the read side to be closed by the client or time out
-    if tokio::time::timeout(CLIENT_DISCONNECT_TIMEOUT, reader.read_to_end(&mut discard))
-        .await
-        .is_ok_and(|read_res| read_res.is_ok())
+    if tokio::time::timeout(
+        config.client_disconnect_timeout,
+        reader.read_to_end(&mut discard),
+    )
+    .await
+    .is_ok_and(|read_res| read_res.is_ok())
     {
         info!("{username} closed connection gracefully");
     } else {
@@ -116,144 +505,1955 @@ where
     }
 }
 
-/// Internal struct for organizing the management of a client connection.
-struct ClientHandler<R, W> {
-    reader: BufReader<R>,
-    writer: W,
-    tx: Sender<String>,
-    rx: Receiver<String>,
-    shutdown_rx: Receiver<()>,
-    username: String,
-    users: Users,
+/// Writes `buf` to a client that has not finished logging in, treating the client as dead if it
+/// takes longer than the write timeout.
+async fn write_timed<W>(writer: &mut W, buf: &[u8], config: &Config) -> Result<()>
+where W: AsyncWrite + Unpin {
+    tokio::time::timeout(config.write_timeout, writer.write_all(buf))
+        .await
+        .map_err(|_| anyhow!("Timed out writing to client"))?
+        .map_err(Into::into)
 }
 
-impl<R, W> ClientHandler<R, W>
-where
-    R: AsyncRead + Unpin,
-    W: AsyncWrite + Unpin,
-{
-    /// Handles the client's entry to and exit from the server, running the main command loop in
-    /// between.
-    async fn run(&mut self) -> Result<()> {
-        self.writer
-            .write_all(
-                format!(
-                    "Hi {}, welcome to Prattle! (Send /help for help)\n",
-                    self.username
-                )
-                .as_bytes(),
-            )
-            .await?;
+/// Creates the color line for each online user who chose a color for their name.
+async fn color_lines(state: &SharedState) -> String {
+    state
+        .users
+        .lock()
+        .await
+        .iter()
+        .filter_map(|(username, user_state)| {
+            user_state
+                .color
+                .map(|color| format!("{COLOR_PREFIX}{username} {color}\n"))
+        })
+        .collect()
+}
 
-        self.tx
-            .send(format!("* {} joined the server\n", self.username))?;
+/// Creates the human-readable list of online users, including their away status.
+async fn who_listing(state: &SharedState) -> String {
+    let users_guard = state.users.lock().await;
+    let list = users_guard
+        .iter()
+        .map(|(username, user_state)| user_state.who_entry(username))
+        .collect::<Vec<_>>();
+    drop(users_guard);
 
-        let loop_res = self.command_loop().await;
+    format!("Currently online: {}\n", list.join(", "))
+}
 
-        self.users.lock().await.remove(&self.username);
+/// Creates a single line of JSON listing the usernames of online users in alphabetical order,
+/// e.g. `{"users":["alice","bob"]}`.
+async fn who_json(state: &SharedState) -> String {
+    let mut users = state.users.lock().await.keys().cloned().collect::<Vec<_>>();
+    users.sort_unstable();
 
-        if let Err(e) = self
-            .tx
-            .send(format!("* {} left the server\n", self.username))
-        {
-            warn!("Failed to broadcast that {} left: {e}", self.username);
-        }
+    format!("{}\n", serde_json::json!({ "users": users }))
+}
 
-        loop_res
+/// Formats `elapsed` in hours, minutes, and seconds, e.g. `1h 2m 3s`, omitting leading zero units.
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    if hours > 0 {
+        format!("{hours}h {mins}m {secs}s")
+    } else if mins > 0 {
+        format!("{mins}m {secs}s")
+    } else {
+        format!("{secs}s")
     }
+}
 
-    /// Runs the main command/message loop, reading and writing until the client quits, the server
-    /// shuts down, or an unexpected error occurs.
-    async fn command_loop(&mut self) -> Result<()> {
-        let mut line = String::new();
+/// Formats how long ago something happened from the `elapsed` time since, in the largest whole
+/// unit, e.g. `3 minutes ago`.
+fn format_ago(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
 
-        loop {
-            tokio::select! {
-                received_val_result = self.rx.recv() => {
-                    match received_val_result {
-                        Ok(msg) => self.writer.write_all(msg.as_bytes()).await?,
+    let (count, unit) = match secs {
+        0..60 => return String::from("less than a minute ago"),
+        60..3600 => (secs / 60, "minute"),
+        3600..86_400 => (secs / 3600, "hour"),
+        _ => (secs / 86_400, "day"),
+    };
 
-                        Err(RecvError::Closed) => {
-                            break Err(anyhow!("Broadcast channel closed ({})", self.username));
-                        }
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{count} {unit}{plural} ago")
+}
 
-                        Err(RecvError::Lagged(n)) => {
-                            warn!("{} lagged behind and missed {n} messages", self.username);
+/// Formats `time` as a UTC date and time, e.g. `2025-01-02 03:04:05 UTC`.
+fn format_utc(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let days = secs / 86_400;
 
-                            // Warn slow readers when they lag behind the broadcast channel
-                            // capacity, allowing them to stay connected
-                            self.writer
-                                .write_all(
-                                    format!("You fell behind and missed {n} messages\n").as_bytes(),
-                                )
-                                .await?;
-                        }
-                    }
-                }
+    // Convert days since the epoch to a civil date, following
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let shifted = days + 719_468;
+    let (era, day_of_era) = (shifted / 146_097, shifted % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
 
-                bytes_read_result = self.reader.read_line(&mut line) => {
-                    if bytes_read_result? == 0 {
-                        warn!("Received EOF from {} without proper disconnection", self.username);
-                        break Ok(());
-                    }
+    format!(
+        "{year:04}-{month:02}-{day:02} {} UTC",
+        format_utc_time_of_day(time)
+    )
+}
 
-                    // Run the command, perform graceful disconnect if necessary, then handle the
-                    // result of running the command
-                    let command = Command::parse(&line);
-                    let cmd_res = self.run_command(&command).await;
+/// Formats the time of day of `time` in UTC, e.g. `03:04:05`.
+fn format_utc_time_of_day(time: SystemTime) -> String {
+    let secs_of_day = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        % 86_400;
 
-                    if command == Command::Quit {
-                        graceful_disconnect(&mut self.reader, &mut self.writer, &self.username)
-                            .await;
-                        break cmd_res;
-                    }
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
 
-                    cmd_res?;
-                    line.clear();
-                }
+/// The text to write for `event`, prefixed with the time it was sent if `timestamps` is on.
+/// Control lines (e.g., color announcements) are never prefixed, so that clients still recognize
+/// them.
+fn render(event: &ChatEvent, timestamps: bool) -> Cow<'_, str> {
+    if timestamps && !event.line.starts_with('\x05') {
+        Cow::Owned(format!(
+            "[{}] {}",
+            format_utc_time_of_day(event.sent_at),
+            event.line
+        ))
+    } else {
+        Cow::Borrowed(&event.line)
+    }
+}
 
-                shutdown_result = self.shutdown_rx.recv() => {
-                    if let Err(e) = shutdown_result {
-                        error!("Error receiving shutdown signal for {}: {e}", self.username);
-                    }
+/// Whether `username` is an operator.
+async fn is_admin(state: &SharedState, username: &str) -> bool {
+    state
+        .users
+        .lock()
+        .await
+        .get(username)
+        .is_some_and(|user_state| user_state.is_admin)
+}
 
-                    // Attempt graceful disconnect regardless of the write result, but still report
-                    // write errors to the main server loop
-                    let write_res = self.writer.write_all(b"Server is shutting down\n").await;
-                    graceful_disconnect(&mut self.reader, &mut self.writer, &self.username).await;
-                    break write_res.map_err(Into::into);
-                }
+/// Records that `username` sent a message or action at `now`, returning whether they were away.
+async fn record_activity(state: &SharedState, username: &str, now: Instant) -> bool {
+    state
+        .users
+        .lock()
+        .await
+        .get_mut(username)
+        .is_some_and(|state| state.record_activity(now))
+}
+
+/// Clears `username`'s away status, returning whether they were away.
+async fn clear_away(state: &SharedState, username: &str) -> bool {
+    state
+        .users
+        .lock()
+        .await
+        .get_mut(username)
+        .is_some_and(|state| state.away.take().is_some())
+}
+
+/// Broadcasts that `username` left the server because of `departure`, leaving `online` users,
+/// deferring the notice by the configured leave grace period so that it can be cancelled if they
+/// reconnect.
+async fn announce_leave(
+    state: &Arc<SharedState>,
+    username: &str,
+    departure: Departure,
+    online: usize,
+) {
+    let grace = state.config.leave_grace;
+
+    if grace.is_zero() {
+        broadcast_leave(state, username, &departure, online);
+        return;
+    }
+
+    // Hold the lock while spawning so that the deferred notice cannot look for its entry before
+    // it has been inserted
+    let mut pending_leaves = state.pending_leaves.lock().await;
+    let task_state = Arc::clone(state);
+    let task_username = username.to_string();
+
+    let deferred = tokio::spawn(
+        async move {
+            tokio::time::sleep(grace).await;
+
+            let pending_leave = task_state
+                .pending_leaves
+                .lock()
+                .await
+                .remove(&task_username);
+
+            if pending_leave.is_some() {
+                broadcast_leave(&task_state, &task_username, &departure, online);
             }
         }
+        .in_current_span(),
+    );
+
+    pending_leaves.insert(username.to_string(), deferred.abort_handle());
+}
+
+/// Summarizes the results of a latency probe sent to the `probed` users, of which those in
+/// `responses` answered with the accompanying round-trip times.
+fn ping_summary(probed: &[String], responses: &[(String, Duration)]) -> String {
+    let slowest = responses
+        .iter()
+        .max_by_key(|(_, rtt)| *rtt)
+        .map(|(username, rtt)| format!(", slowest {username} ({}ms)", rtt.as_millis()))
+        .unwrap_or_default();
+
+    let responded = responses
+        .iter()
+        .map(|(username, _)| username)
+        .collect::<HashSet<_>>();
+    let mut stalled = probed
+        .iter()
+        .filter(|username| !responded.contains(username))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    stalled.sort_unstable();
+
+    let stalled = if stalled.is_empty() {
+        String::new()
+    } else {
+        format!("; possibly stalled: {}", stalled.join(", "))
+    };
+
+    format!(
+        "{}/{} responded{slowest}{stalled}\n",
+        responses.len(),
+        probed.len()
+    )
+}
+
+/// Broadcasts that `username` left the server because of `departure`, leaving `online` users,
+/// logging instead of returning any error.
+fn broadcast_leave(state: &SharedState, username: &str, departure: &Departure, online: usize) {
+    broadcast(
+        state,
+        &ChatEvent::leave(
+            username,
+            presence_notice(&state.config, &departure.notice(username), online),
+        ),
+    );
+}
+
+/// Appends the number of users `online` to a join or leave `notice` if configured to, e.g.
+/// `* alice joined the server (3 online)`.
+pub fn presence_notice(config: &Config, notice: &str, online: usize) -> String {
+    if config.online_count {
+        format!("{} ({online} online)\n", notice.trim_end())
+    } else {
+        notice.to_string()
+    }
+}
+
+/// How a client's session ended, which determines the leave notice seen by everyone else.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Departure {
+    /// The client quit (with an optional parting message) or was kicked.
+    Left(Option<String>),
+
+    /// The connection ended unexpectedly, e.g. with EOF before quitting or an I/O error.
+    LostConnection,
+
+    /// The client was disconnected because the server is shutting down, in which case no leave
+    /// notice is broadcast.
+    Shutdown,
+}
+
+impl Departure {
+    /// The leave notice for `username`.
+    pub fn notice(&self, username: &str) -> String {
+        match self {
+            Self::Left(None) => format!("* {username} left\n"),
+            Self::Left(Some(parting)) => format!("* {username} left ({parting})\n"),
+            Self::LostConnection => format!("* {username} lost connection\n"),
+            Self::Shutdown => format!("* {username} was disconnected (server shutdown)\n"),
+        }
     }
+}
 
-    /// Runs the specified command or sends the specified message.
-    async fn run_command(&mut self, command: &Command<'_>) -> Result<()> {
-        match command {
-            Command::Empty => {}
+/// Broadcasts `event` to all clients. Having no receivers is harmless (e.g., if the last client
+/// left at the same moment), so it is only logged.
+fn broadcast(state: &SharedState, event: &ChatEvent) {
+    if state.fanout.send(event) == 0 {
+        warn!("No clients to receive broadcast: {}", event.line.trim_end());
+    }
+}
 
-            // Actually quitting is handled in the main loop
-            Command::Quit => self.writer.write_all(b"Goodbye for now!\n").await?,
+/// The marker that clients such as bots can append to their username (after whitespace) to skip
+/// the welcome message and anything else sent only to the joining client.
+const QUIET_MARKER: &str = "+quiet";
 
-            Command::Help => self.writer.write_all(COMMAND_HELP).await?,
+/// Splits the quiet marker off of a trimmed username, returning the username and whether the
+/// marker was present.
+fn split_quiet_marker(username: &str) -> (&str, bool) {
+    match username.strip_suffix(QUIET_MARKER) {
+        Some(name) if name.ends_with(char::is_whitespace) => (name.trim_end(), true),
+        _ => (username, false),
+    }
+}
 
-            Command::Who => {
-                let users_guard = self.users.lock().await;
-                let list = users_guard.iter().map(String::as_str).collect::<Vec<_>>();
-                let msg = format!("Currently online: {}\n", list.join(", "));
-                drop(users_guard);
-                self.writer.write_all(msg.as_bytes()).await?;
-            }
+/// The number of alternatives suggested when a username is taken.
+const MAX_SUGGESTIONS: usize = 3;
 
-            Command::Action(action) => {
-                self.tx.send(format!("* {} {action}\n", self.username))?;
-            }
+/// Checks the rules every username must follow regardless of who is online, returning the message
+/// explaining why `username` was rejected if it breaks one.
+fn check_username(config: &Config, username: &str) -> Result<(), String> {
+    // Checked first, so that an oversized username is never stored or broadcast
+    if username.len() > MAX_USERNAME_LEN {
+        return Err(format!("Username too long (max {MAX_USERNAME_LEN})\n"));
+    }
 
-            Command::Msg(msg) => {
-                self.tx.send(format!("{}: {msg}\n", self.username))?;
-            }
+    if username.is_empty() {
+        return Err(String::from("Username cannot be empty\n"));
+    }
+
+    if config.is_reserved_name(username) {
+        return Err(String::from("That username is reserved\n"));
+    }
+
+    Ok(())
+}
+
+/// Suggests up to `MAX_SUGGESTIONS` variations of `desired` that are not keys of `users` and
+/// that `config` allows, e.g. `alice2`, `alice_`, and `alice3` for `alice`.
+fn suggest_usernames<V>(desired: &str, users: &HashMap<String, V>, config: &Config) -> Vec<String> {
+    [format!("{desired}2"), format!("{desired}_")]
+        .into_iter()
+        .chain((3..100).map(|n| format!("{desired}{n}")))
+        .filter(|candidate| {
+            check_username(config, candidate).is_ok() && !users.contains_key(candidate)
+        })
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+/// The reply to a client who chose a username that is already taken, listing `suggestions` if
+/// there are any.
+fn taken_message(suggestions: &[String]) -> String {
+    match suggestions {
+        [] => String::from("Username taken\n"),
+        [only] => format!("Username taken (try {only})\n"),
+        [first, second] => format!("Username taken (try {first} or {second})\n"),
+        [rest @ .., last] => format!("Username taken (try {}, or {last})\n", rest.join(", ")),
+    }
+}
+
+/// What to send a client right after they choose a username, before the join notice.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Greeting {
+    /// Nothing, because the client joined with the quiet marker.
+    Silent,
+
+    /// The welcome message (and the list of online users if configured).
+    Welcome,
+
+    /// The final form of the username, followed by the same as `Welcome`.
+    ConfirmAndWelcome,
+}
+
+impl Greeting {
+    /// Chooses the greeting for a client based on whether they joined with the quiet marker and
+    /// whether their stored username differs from what they typed.
+    const fn choose(config: &Config, quiet: bool, username_changed: bool) -> Self {
+        if quiet {
+            Self::Silent
+        } else if match config.confirm_username {
+            ConfirmUsername::Never => false,
+            ConfirmUsername::IfChanged => username_changed,
+            ConfirmUsername::Always => true,
+        } {
+            Self::ConfirmAndWelcome
+        } else {
+            Self::Welcome
         }
+    }
+}
 
-        Ok(())
+/// The settings each client can change for themselves, which decide which broadcasts they are
+/// shown and how.
+#[allow(clippy::struct_excessive_bools)] // The settings are independent of each other
+#[derive(Clone, Copy, Debug)]
+struct Preferences {
+    /// Whether the client receives their own messages and actions.
+    echo: bool,
+
+    /// Whether do not disturb is on, in which case broadcasts are received but discarded (so the
+    /// queue doesn't fill up) instead of being sent to the client.
+    dnd: bool,
+
+    /// Whether to show the time each broadcast line was sent before it.
+    timestamps: bool,
+
+    /// Whether join and leave notices are hidden.
+    quiet_joins: bool,
+}
+
+impl Preferences {
+    /// Creates the preferences a client starts out with on a server with the options in `config`.
+    const fn new(config: &Config) -> Self {
+        Self { echo: true, dnd: false, timestamps: config.timestamps, quiet_joins: false }
+    }
+}
+
+/// Internal struct for organizing the management of a client connection. Only created once the
+/// client has chosen a valid username and been added to `users`, so join and leave notices are
+/// never broadcast for clients that disconnect before then.
+struct ClientHandler<R, W> {
+    reader: BufReader<R>,
+    line_reader: LineReader,
+    writer: W,
+    state: Arc<SharedState>,
+    rx: Subscription,
+    control_rx: UnboundedReceiver<ControlMessage>,
+    shutdown_rx: Receiver<()>,
+    username: String,
+    pending_ping: Option<PingProbe>,
+    prefs: Preferences,
+
+    /// The signature appended to the user's messages (but not actions or emotes), if any.
+    sig: Option<String>,
+
+    /// Whether another connection has taken over this user's session, in which case the username
+    /// belongs to the new session and this one must leave without cleaning up after it.
+    taken_over: bool,
+
+    /// The user's most recently broadcast message and when it was sent, for ignoring accidental
+    /// double-sends if `Config::dedup_window` is set.
+    last_msg: Option<(String, Instant)>,
+
+    /// When the user last broadcast a message or action, for enforcing slow mode.
+    last_post: Option<Instant>,
+}
+
+impl<R, W> ClientHandler<R, W>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    /// Handles the client's entry to and exit from the server, running the main command loop in
+    /// between, starting with `greeting` followed by the `backlog` of recent messages.
+    /// If the `claim` took over an existing session with the same username, a reconnection notice
+    /// is broadcast instead of the join notice.
+    async fn run(&mut self, greeting: Greeting, claim: &Claim, backlog: &str) -> Result<()> {
+        if greeting == Greeting::ConfirmAndWelcome {
+            self.writer
+                .write_all(format!("You are now known as {}\n", self.username).as_bytes())
+                .await?;
+        }
+
+        if greeting != Greeting::Silent {
+            self.writer
+                .write_all(
+                    format!(
+                        "{}\n",
+                        self.state.config.messages.welcome_for(&self.username)
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+        }
+
+        if self.state.config.names_on_join && greeting != Greeting::Silent {
+            self.writer
+                .write_all(who_listing(&self.state).await.as_bytes())
+                .await?;
+        }
+
+        // Clients learn about colors chosen before they joined from these, and about later ones
+        // from broadcasts
+        if greeting != Greeting::Silent {
+            self.writer
+                .write_all(format!("{USERNAME_PREFIX}{}\n", self.username).as_bytes())
+                .await?;
+            self.writer
+                .write_all(color_lines(&self.state).await.as_bytes())
+                .await?;
+            self.writer.write_all(backlog.as_bytes()).await?;
+
+            // Written directly rather than broadcast, so only this client receives it
+            if let Some(bot_welcome) = self.state.config.bot_welcome_for(&self.username) {
+                self.writer.write_all(bot_welcome.as_bytes()).await?;
+            }
+        }
+
+        self.deliver_offline_msgs().await?;
+
+        // Rejoining within the leave grace period silently takes the place of the old connection
+        let pending_leave = self
+            .state
+            .pending_leaves
+            .lock()
+            .await
+            .remove(&self.username);
+
+        if claim.took_over {
+            broadcast(
+                &self.state,
+                &ChatEvent::notice(presence_notice(
+                    &self.state.config,
+                    &format!("* {} reconnected\n", self.username),
+                    claim.online,
+                )),
+            );
+        } else if let Some(pending_leave) = pending_leave {
+            pending_leave.abort();
+            info!(
+                "{} reconnected within the leave grace period",
+                self.username
+            );
+        } else {
+            broadcast(
+                &self.state,
+                &ChatEvent::join(
+                    &self.username,
+                    presence_notice(
+                        &self.state.config,
+                        &format!("* {} joined the server\n", self.username),
+                        claim.online,
+                    ),
+                ),
+            );
+        }
+
+        // Errors (e.g., failing to write a broadcast) are returned only after the cleanup below, so
+        // that the user is always removed and their departure announced
+        let (departure, loop_res) = match self.command_loop().await {
+            Ok(departure) => (departure, Ok(())),
+            Err(e) => (Departure::LostConnection, Err(e)),
+        };
+
+        let mut users_guard = self.state.users.lock().await;
+
+        // The session may have been taken over after the loop ended but before the lock was taken
+        while let Ok(control_msg) = self.control_rx.try_recv() {
+            if matches!(control_msg, ControlMessage::TakenOver) {
+                self.taken_over = true;
+            }
+        }
+
+        if !self.taken_over {
+            // Recorded before the user is removed so that `/seen` always finds them in one or the
+            // other
+            self.state
+                .seen
+                .lock()
+                .await
+                .record(&self.username, Instant::now());
+            if let Some(user) = users_guard.remove(&self.username) {
+                self.state.expire_reconnect_token(&user).await;
+            }
+            let online = users_guard.len();
+            drop(users_guard);
+
+            // Everyone is disconnected at once during shutdown, so leave notices would only bury
+            // the shutdown notice
+            if departure != Departure::Shutdown {
+                announce_leave(&self.state, &self.username, departure, online).await;
+            }
+        }
+
+        loop_res
+    }
+
+    /// Runs the main command/message loop, reading and writing until the client quits, is kicked,
+    /// the server shuts down, or an unexpected error occurs. Returns how the session ended unless
+    /// it was by an error, which means the connection was lost.
+    async fn command_loop(&mut self) -> Result<Departure> {
+        let mut heartbeat = self.state.config.heartbeat_interval.map(|period| {
+            let mut heartbeat = time::interval_at(time::Instant::now() + period, period);
+            heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            heartbeat
+        });
+
+        loop {
+            // Lines written by the previous iteration are flushed together, since TLS streams can
+            // hold the end of a write until the next write or flush
+            self.flush_with_timeout().await?;
+
+            tokio::select! {
+                () = async {
+                    match heartbeat.as_mut() {
+                        Some(heartbeat) => _ = heartbeat.tick().await,
+                        None => future::pending().await,
+                    }
+                } => {
+                    self.write_with_timeout(HEARTBEAT_LINE).await?;
+                }
+
+                received = self.rx.recv() => {
+                    match received {
+                        Some(event) if !self.rx.is_evicted() => {
+                            if self.shows(&event) {
+                                let line = render(&event, self.prefs.timestamps);
+                                self.write_with_timeout(line.as_bytes()).await?;
+                            }
+                        }
+
+                        // Slow readers are only evicted under the disconnect overflow policy, in
+                        // which case anything still queued is dropped since they are already too
+                        // far behind
+                        _ => {
+                            warn!("{} was too slow and was disconnected", self.username);
+                            break self
+                                .disconnect_with(b"You were too slow and were disconnected\n")
+                                .await
+                                .map(|()| Departure::LostConnection);
+                        }
+                    }
+                }
+
+                Some(control_msg) = self.control_rx.recv() => {
+                    if let Some(disconnect_res) = self.handle_control_message(control_msg).await? {
+                        break disconnect_res.map(|()| Departure::Left(None));
+                    }
+                }
+
+                read_result = self
+                    .line_reader
+                    .read_line_within(&mut self.reader, self.state.config.read_timeout) =>
+                {
+                    let Ok(read_result) = read_result else {
+                        warn!(
+                            "{} stalled partway through a line and was disconnected",
+                            self.username
+                        );
+                        break self
+                            .disconnect_with(
+                                b"You took too long to finish a line and were disconnected\n",
+                            )
+                            .await
+                            .map(|()| Departure::LostConnection);
+                    };
+
+                    let line = match read_result? {
+                        LineRead::Line(line) => line,
+
+                        LineRead::TooLong => {
+                            self.write_with_timeout(INPUT_TOO_LONG).await?;
+                            continue;
+                        }
+
+                        LineRead::Eof => {
+                            warn!(
+                                "Received EOF from {} without proper disconnection",
+                                self.username
+                            );
+                            break Ok(Departure::LostConnection);
+                        }
+                    };
+
+                    // Run the command, perform graceful disconnect if necessary, then handle the
+                    // result of running the command
+                    let command = Command::parse(&line);
+                    let cmd_res = self.run_command(&command).await;
+
+                    if let Command::Quit(parting) = command {
+                        graceful_disconnect(&mut self.reader, &mut self.writer, &self.username, &self.state.config)
+                            .await;
+
+                        // Sanitized like other text, with an empty parting message ignored
+                        let parting = parting.and_then(sanitize).filter(|parting| !parting.is_empty());
+                        break cmd_res.map(|()| Departure::Left(parting));
+                    }
+
+                    cmd_res?;
+                }
+
+                shutdown_result = self.shutdown_rx.recv() => {
+                    if let Err(e) = shutdown_result {
+                        error!("Error receiving shutdown signal for {}: {e}", self.username);
+                    }
+
+                    // Deliver messages sent just before shutdown, then attempt graceful disconnect
+                    // regardless of the write result, but still report write errors to the main
+                    // server loop
+                    self.drain_broadcasts().await;
+                    let write_res = self
+                        .writer
+                        .write_all(shutdown_notice(&self.state.config).as_bytes())
+                        .await;
+                    graceful_disconnect(&mut self.reader, &mut self.writer, &self.username, &self.state.config).await;
+                    break write_res.map(|()| Departure::Shutdown).map_err(Into::into);
+                }
+            }
+        }
+    }
+
+    /// Whether a broadcast event should be written to this client. Skips everything during do not
+    /// disturb, the client's own messages if they turned echo off, and join and leave notices if
+    /// they turned those off.
+    fn shows(&self, event: &ChatEvent) -> bool {
+        !self.prefs.dnd
+            && (self.prefs.echo || !event.is_from(&self.username))
+            && (!self.prefs.quiet_joins || !event.is_presence())
+    }
+
+    /// Writes the events already queued for the client without waiting for new ones. Bounded by
+    /// the queue capacity and `SHUTDOWN_DRAIN_TIMEOUT` so that a large backlog or a
+    /// slow reader can't hold up shutdown.
+    async fn drain_broadcasts(&mut self) {
+        let drain = async {
+            for _ in 0..self.state.config.client_queue_cap {
+                let Ok(event) = self.rx.try_recv() else { break };
+
+                if self.shows(&event) {
+                    let line = render(&event, self.prefs.timestamps);
+                    self.writer.write_all(line.as_bytes()).await?;
+                }
+            }
+
+            anyhow::Ok(())
+        };
+
+        match time::timeout(SHUTDOWN_DRAIN_TIMEOUT, drain).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!(
+                "Failed to deliver queued messages to {}: {e}",
+                self.username
+            ),
+            Err(_) => warn!("Timed out delivering queued messages to {}", self.username),
+        }
+    }
+
+    /// Handles an instruction addressed to this client's handler. Returns `Some` with the result to
+    /// end the command loop with if the client was disconnected, or `None` to keep going.
+    async fn handle_control_message(
+        &mut self,
+        control_msg: ControlMessage,
+    ) -> Result<Option<Result<()>>> {
+        let farewell = match control_msg {
+            ControlMessage::Kick(kicker) => {
+                info!("{} was kicked by {kicker}", self.username);
+                format!("You were kicked by {kicker}\n")
+            }
+
+            ControlMessage::TakenOver => {
+                info!("{}'s session was taken over", self.username);
+                self.taken_over = true;
+                String::from("Your session was taken over by a new connection\n")
+            }
+
+            ControlMessage::Ping(probe) => {
+                self.write_with_timeout(format!("/ping {}\n", probe.token).as_bytes())
+                    .await?;
+
+                // Only the latest probe is answered, so any earlier one is abandoned
+                self.pending_ping = Some(probe);
+                return Ok(None);
+            }
+
+            ControlMessage::Notice(notice) => {
+                self.write_with_timeout(notice.as_bytes()).await?;
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(self.disconnect_with(farewell.as_bytes()).await))
+    }
+
+    /// Writes `farewell` to the client and disconnects them gracefully. The disconnect is attempted
+    /// regardless of the write result, but write errors are still returned so they can be reported
+    /// to the main server loop.
+    async fn disconnect_with(&mut self, farewell: &[u8]) -> Result<()> {
+        let write_res = self.write_with_timeout(farewell).await;
+        graceful_disconnect(
+            &mut self.reader,
+            &mut self.writer,
+            &self.username,
+            &self.state.config,
+        )
+        .await;
+        write_res
+    }
+
+    /// Writes `buf` to the client, returning `Err` if the client does not accept it within the
+    /// configured write timeout so that a client that stops reading is disconnected rather than
+    /// stalling its handler indefinitely.
+    async fn write_with_timeout(&mut self, buf: &[u8]) -> Result<()> {
+        tokio::time::timeout(self.state.config.write_timeout, self.writer.write_all(buf))
+            .await
+            .map_err(|_| anyhow!("Timed out writing to {}", self.username))?
+            .map_err(Into::into)
+    }
+
+    /// Flushes anything buffered in the writer, treating the client as dead if it takes longer
+    /// than the write timeout.
+    async fn flush_with_timeout(&mut self) -> Result<()> {
+        tokio::time::timeout(self.state.config.write_timeout, self.writer.flush())
+            .await
+            .map_err(|_| anyhow!("Timed out flushing writes to {}", self.username))?
+            .map_err(Into::into)
+    }
+
+    /// Marks the client as away with `away_msg`, or as back if they were already away and no
+    /// message was given.
+    async fn set_away(&mut self, away_msg: Option<&str>) -> Result<()> {
+        // Sanitized like messages, since it is shown to others in `/who` and auto-replies
+        let away_msg = match away_msg {
+            Some(away_msg) => match self.sanitized(away_msg).await? {
+                Some(sanitized) => Some(sanitized),
+                None => return Ok(()),
+            },
+            None => None,
+        };
+
+        if clear_away(&self.state, &self.username).await && away_msg.is_none() {
+            broadcast(
+                &self.state,
+                &ChatEvent::notice(format!("* {} is back\n", self.username)),
+            );
+        } else {
+            if let Some(user_state) = self.state.users.lock().await.get_mut(&self.username) {
+                user_state.away = Some(away_msg.unwrap_or_default());
+            }
+
+            self.writer
+                .write_all(b"You are now marked as away\n")
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Makes the client an operator if `token` matches the configured admin token.
+    async fn authenticate_admin(&mut self, token: &str) -> Result<()> {
+        let authenticated = self
+            .state
+            .config
+            .admin_token
+            .as_ref()
+            .is_some_and(|admin_token| {
+                auth::constant_time_eq(token.as_bytes(), admin_token.as_bytes())
+            });
+
+        if authenticated {
+            if let Some(user_state) = self.state.users.lock().await.get_mut(&self.username) {
+                user_state.is_admin = true;
+            }
+
+            info!("{} authenticated as an operator", self.username);
+            self.writer.write_all(b"You are now an operator\n").await?;
+        } else {
+            warn!("{} failed to authenticate as an operator", self.username);
+            self.writer.write_all(b"Invalid admin token\n").await?;
+        }
+
+        Ok(())
+    }
+
+    /// Grants (if `grant` is true) or revokes operator status for `target`, broadcasting the change
+    /// if the client is an operator and the change is valid, or replying privately otherwise.
+    async fn set_operator(&mut self, target: &str, grant: bool) -> Result<()> {
+        let mut users_guard = self.state.users.lock().await;
+
+        let reply = if !users_guard
+            .get(&self.username)
+            .is_some_and(|user_state| user_state.is_admin)
+        {
+            Some(String::from("Permission denied\n"))
+        } else if let Some(target_state) = users_guard.get_mut(target) {
+            if target_state.is_admin == grant {
+                Some(format!(
+                    "{target} {} an operator\n",
+                    if grant { "is already" } else { "is not" }
+                ))
+            } else {
+                target_state.is_admin = grant;
+                None
+            }
+        } else {
+            Some(String::from("No such user\n"))
+        };
+
+        drop(users_guard);
+
+        if let Some(reply) = reply {
+            self.writer.write_all(reply.as_bytes()).await?;
+        } else {
+            info!(
+                "{} {} operator status for {target}",
+                self.username,
+                if grant { "granted" } else { "revoked" }
+            );
+
+            broadcast(
+                &self.state,
+                &ChatEvent::notice(format!(
+                    "* {target} {} an operator\n",
+                    if grant { "is now" } else { "is no longer" }
+                )),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Disconnects `target` if the user is an operator, otherwise replies with an error. The leave
+    /// notice is broadcast by the target's handler once it has disconnected.
+    async fn kick(&mut self, target: &str) -> Result<()> {
+        let users_guard = self.state.users.lock().await;
+
+        let reply = if !users_guard
+            .get(&self.username)
+            .is_some_and(|user_state| user_state.is_admin)
+        {
+            Some("Permission denied\n")
+        } else if target == self.username {
+            Some("You cannot kick yourself\n")
+        } else if let Some(target_state) = users_guard.get(target) {
+            // A send error means the target's handler is already exiting anyway
+            if let Err(e) = target_state
+                .control_tx
+                .send(ControlMessage::Kick(self.username.clone()))
+            {
+                warn!("Failed to kick {target}: {e}");
+            }
+
+            None
+        } else {
+            Some("No such user\n")
+        };
+
+        drop(users_guard);
+
+        if let Some(reply) = reply {
+            self.writer.write_all(reply.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Mutes or unmutes `target` if the user is an operator, otherwise replies with an error. Only
+    /// online users can be muted, but any muted name can be unmuted, since mutes outlast the
+    /// connection.
+    async fn set_muted(&mut self, target: &str, mute: bool) -> Result<()> {
+        let users_guard = self.state.users.lock().await;
+        let mut muted_guard = self.state.muted.lock().await;
+
+        let reply = if !users_guard
+            .get(&self.username)
+            .is_some_and(|user_state| user_state.is_admin)
+        {
+            String::from("Permission denied\n")
+        } else if mute && target == self.username {
+            String::from("You cannot mute yourself\n")
+        } else if mute && !users_guard.contains_key(target) {
+            String::from("No such user\n")
+        } else if mute {
+            if muted_guard.insert(target.to_string()) {
+                info!("{} muted {target}", self.username);
+
+                if let Some(target_state) = users_guard.get(target) {
+                    // A send error means the target's handler is already exiting anyway
+                    let _ = target_state
+                        .control_tx
+                        .send(ControlMessage::Notice(String::from(
+                            "You have been muted by an operator\n",
+                        )));
+                }
+
+                format!("{target} is now muted\n")
+            } else {
+                format!("{target} is already muted\n")
+            }
+        } else if muted_guard.remove(target) {
+            info!("{} unmuted {target}", self.username);
+
+            if let Some(target_state) = users_guard.get(target) {
+                let _ = target_state
+                    .control_tx
+                    .send(ControlMessage::Notice(String::from(
+                        "You are no longer muted\n",
+                    )));
+            }
+
+            format!("{target} is no longer muted\n")
+        } else {
+            format!("{target} is not muted\n")
+        };
+
+        drop(muted_guard);
+        drop(users_guard);
+
+        self.writer.write_all(reply.as_bytes()).await?;
+
+        Ok(())
+    }
+
+    /// Sends a latency probe to every other user if the user is an operator, otherwise replies with
+    /// an error. The summary is delivered through the user's own control channel once every probed
+    /// client has responded or the response window has passed, so the user is not blocked waiting.
+    async fn ping_all(&mut self) -> Result<()> {
+        let users_guard = self.state.users.lock().await;
+
+        let Some(own_control_tx) = users_guard
+            .get(&self.username)
+            .filter(|user_state| user_state.is_admin)
+            .map(|user_state| user_state.control_tx.clone())
+        else {
+            drop(users_guard);
+            self.writer.write_all(b"Permission denied\n").await?;
+            return Ok(());
+        };
+
+        let (responses_tx, mut responses_rx) = mpsc::unbounded_channel();
+        let probe = PingProbe {
+            token: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis()
+                .to_string(),
+            sent_at: Instant::now(),
+            responses: responses_tx,
+        };
+
+        let probed = users_guard
+            .iter()
+            .filter(|(username, user_state)| {
+                **username != self.username
+                    && user_state
+                        .control_tx
+                        .send(ControlMessage::Ping(probe.clone()))
+                        .is_ok()
+            })
+            .map(|(username, _)| username.clone())
+            .collect::<Vec<_>>();
+
+        drop(users_guard);
+
+        // Drop the original probe so that the responses channel closes as soon as every probed
+        // client has either responded or dropped its copy
+        drop(probe);
+
+        info!(
+            "{} sent a latency probe to {} users",
+            self.username,
+            probed.len()
+        );
+
+        tokio::spawn(
+            async move {
+                let mut responses = Vec::new();
+
+                // Timing out just means that some clients did not respond
+                let _ = tokio::time::timeout(PING_ALL_WINDOW, async {
+                    while let Some(response) = responses_rx.recv().await {
+                        responses.push(response);
+                    }
+                })
+                .await;
+
+                if let Err(e) =
+                    own_control_tx.send(ControlMessage::Notice(ping_summary(&probed, &responses)))
+                {
+                    warn!("Failed to deliver latency probe summary: {e}");
+                }
+            }
+            .in_current_span(),
+        );
+
+        Ok(())
+    }
+
+    /// Reports the round-trip time of the pending latency probe if `token` matches it. Stale or
+    /// unsolicited responses are ignored.
+    fn answer_ping(&mut self, token: &str) {
+        if let Some(probe) = self.pending_ping.take_if(|probe| probe.token == token) {
+            // An error means the probe's results were already reported
+            let _ = probe
+                .responses
+                .send((self.username.clone(), probe.sent_at.elapsed()));
+        }
+    }
+
+    /// Replies with how long `target` has been online, including the address they connected from
+    /// if the client is an operator.
+    async fn whois(&mut self, target: &str) -> Result<()> {
+        let users_guard = self.state.users.lock().await;
+        let requester_is_admin = users_guard
+            .get(&self.username)
+            .is_some_and(|user_state| user_state.is_admin);
+
+        let reply = users_guard.get(target).map_or_else(
+            || String::from("No such user\n"),
+            |user_state| {
+                let addr = if requester_is_admin {
+                    format!(", connected from {}", user_state.addr)
+                } else {
+                    String::new()
+                };
+
+                format!(
+                    "{target}: joined {} ago, last active {} ago{addr}\n",
+                    format_elapsed(user_state.joined_at.elapsed()),
+                    format_elapsed(user_state.last_seen.elapsed())
+                )
+            },
+        );
+        drop(users_guard);
+
+        self.writer.write_all(reply.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Replies with whether `target` is online, or else when they were last active.
+    async fn seen(&mut self, target: &str) -> Result<()> {
+        let online = self.state.users.lock().await.contains_key(target);
+        let last_seen = self.state.seen.lock().await.get(target);
+
+        let reply = if online {
+            format!("{target} is online now\n")
+        } else if let Some(last_seen) = last_seen {
+            format!(
+                "{target} was last seen {}\n",
+                format_ago(last_seen.elapsed())
+            )
+        } else {
+            format!("I've never seen anyone named {target}\n")
+        };
+
+        self.writer.write_all(reply.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Resets the activity counters if the user is an operator, otherwise replies with an error.
+    async fn reset_stats(&mut self) -> Result<()> {
+        if is_admin(&self.state, &self.username).await {
+            self.state.metrics.reset();
+            info!("{} reset the statistics", self.username);
+            self.writer.write_all(b"Statistics reset\n").await?;
+        } else {
+            self.writer.write_all(b"Permission denied\n").await?;
+        }
+
+        Ok(())
+    }
+
+    /// Broadcasts `announcement` with distinctive formatting if the user is an operator, otherwise
+    /// replies with an error. Announcements left empty after sanitizing are ignored.
+    async fn announce(&mut self, announcement: &str) -> Result<()> {
+        if !is_admin(&self.state, &self.username).await {
+            self.writer.write_all(b"Permission denied\n").await?;
+        } else if let Some(announcement) = self.sanitized(announcement).await?
+            && !announcement.is_empty()
+        {
+            info!("{} made an announcement", self.username);
+            broadcast(
+                &self.state,
+                &ChatEvent::notice(format!("*** ANNOUNCEMENT: {announcement} ***\n")),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Locks or unlocks the server if the user is an operator, otherwise replies with an error.
+    async fn set_locked(&mut self, locked: bool) -> Result<()> {
+        if !is_admin(&self.state, &self.username).await {
+            self.writer.write_all(b"Permission denied\n").await?;
+        } else if self.state.locked.swap(locked, SeqCst) == locked {
+            self.writer
+                .write_all(if locked {
+                    b"The server is already read-only\n"
+                } else {
+                    b"The server is not read-only\n"
+                })
+                .await?;
+        } else {
+            info!(
+                "{} {} the server",
+                self.username,
+                if locked { "locked" } else { "unlocked" }
+            );
+
+            broadcast(
+                &self.state,
+                &ChatEvent::notice(String::from(if locked {
+                    "** server is now read-only **\n"
+                } else {
+                    "** server is no longer read-only **\n"
+                })),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns how much longer slow mode requires the user to wait as of `now` before they can
+    /// send again (unless they are an operator), or `None` if they can send now.
+    fn slow_mode_wait(&self, now: Instant) -> Option<Duration> {
+        let interval = Duration::from_secs(self.state.slow_mode_secs.load(SeqCst));
+        let wait = interval.checked_sub(now.duration_since(self.last_post?))?;
+
+        (!wait.is_zero()).then_some(wait)
+    }
+
+    /// Requires everyone but operators to wait `secs` seconds between messages and actions, or
+    /// turns slow mode off if `secs` is 0. Operator only.
+    async fn set_slow_mode(&mut self, secs: Option<u64>) -> Result<()> {
+        if !is_admin(&self.state, &self.username).await {
+            self.writer.write_all(b"Permission denied\n").await?;
+            return Ok(());
+        }
+
+        let Some(secs) = secs else {
+            self.writer
+                .write_all(
+                    format!(
+                        "Usage: /slowmode <seconds>, with 0 to turn it off (max \
+                        {MAX_SLOW_MODE_SECS})\n"
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+            return Ok(());
+        };
+
+        self.state.slow_mode_secs.store(secs, SeqCst);
+        info!("{} set slow mode to {secs} seconds", self.username);
+
+        let notice = if secs == 0 {
+            String::from("** slow mode is now off **\n")
+        } else {
+            let plural = if secs == 1 { "" } else { "s" };
+            format!("** slow mode is now on: one message every {secs} second{plural} **\n")
+        };
+        broadcast(&self.state, &ChatEvent::notice(notice));
+
+        Ok(())
+    }
+
+    /// Sets the signature appended to the user's messages to `sig`, or clears it if `sig` is
+    /// `None` or empty after sanitizing. Signatures over `MAX_SIG_LENGTH` characters are rejected.
+    async fn set_sig(&mut self, sig: Option<&str>) -> Result<()> {
+        let sig = match sig {
+            Some(sig) => match self.sanitized(sig).await? {
+                Some(sig) => sig,
+                None => return Ok(()),
+            },
+            None => String::new(),
+        };
+
+        if sig.is_empty() {
+            self.sig = None;
+            self.writer.write_all(b"Signature cleared\n").await?;
+        } else if sig.chars().count() > MAX_SIG_LENGTH {
+            self.writer
+                .write_all(
+                    format!("Signature too long (max {MAX_SIG_LENGTH} characters)\n").as_bytes(),
+                )
+                .await?;
+        } else {
+            self.writer
+                .write_all(format!("Signature set to: {sig}\n").as_bytes())
+                .await?;
+            self.sig = Some(sig);
+        }
+
+        Ok(())
+    }
+
+    /// Sanitizes client-provided `text` for broadcasting, replying that the input is too long and
+    /// returning `None` if it exceeds the limit after sanitizing.
+    async fn sanitized(&mut self, text: &str) -> Result<Option<String>> {
+        let sanitized = sanitize(text);
+
+        if sanitized.is_none() {
+            self.write_with_timeout(INPUT_TOO_LONG).await?;
+        }
+
+        Ok(sanitized)
+    }
+
+    /// Checks whether `msg` repeats the user's last message within `Config::dedup_window`,
+    /// recording it as the last message if not.
+    fn is_duplicate(&mut self, msg: &str) -> bool {
+        let Some(window) = self.state.config.dedup_window else {
+            return false;
+        };
+
+        let now = Instant::now();
+
+        if let Some((last, sent_at)) = &self.last_msg
+            && last == msg
+            && now.duration_since(*sent_at) < window
+        {
+            return true;
+        }
+
+        self.last_msg = Some((msg.to_string(), now));
+        false
+    }
+
+    /// Turns receiving the user's own messages and actions on or off.
+    async fn set_echo(&mut self, echo: bool) -> Result<()> {
+        self.prefs.echo = echo;
+        self.writer
+            .write_all(if echo { b"Echo is now on\n" } else { b"Echo is now off\n" })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Turns do not disturb on or off. Broadcasts discarded while it is on are not replayed when it
+    /// is turned off.
+    async fn set_dnd(&mut self, dnd: bool) -> Result<()> {
+        self.prefs.dnd = dnd;
+        self.writer
+            .write_all(if dnd {
+                b"Do not disturb is now on (messages sent meanwhile will not be shown later)\n"
+            } else {
+                b"Do not disturb is now off\n"
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Turns showing the time before each broadcast line on or off.
+    async fn set_timestamps(&mut self, timestamps: bool) -> Result<()> {
+        self.prefs.timestamps = timestamps;
+        self.writer
+            .write_all(if timestamps {
+                b"Timestamps are now on\n"
+            } else {
+                b"Timestamps are now off\n"
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Turns hiding join and leave notices on or off. Other notices (e.g., reconnections) are still
+    /// shown.
+    async fn set_quiet_joins(&mut self, quiet_joins: bool) -> Result<()> {
+        self.prefs.quiet_joins = quiet_joins;
+        self.writer
+            .write_all(if quiet_joins {
+                b"Join and leave notices are now hidden\n"
+            } else {
+                b"Join and leave notices are now shown\n"
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sets the color of the user's name to `color` (ignoring case) and tells everyone, or replies
+    /// with the available colors if it is not one of them.
+    async fn set_color(&mut self, color: &str) -> Result<()> {
+        let Some(&color) = COLORS
+            .iter()
+            .find(|known| known.eq_ignore_ascii_case(color))
+        else {
+            let reply = format!(
+                "Unknown color: {color} (choose from {})\n",
+                COLORS.join(", ")
+            );
+            return Ok(self.writer.write_all(reply.as_bytes()).await?);
+        };
+
+        if let Some(user_state) = self.state.users.lock().await.get_mut(&self.username) {
+            user_state.color = Some(color);
+        }
+
+        broadcast(
+            &self.state,
+            &ChatEvent::notice(format!("{COLOR_PREFIX}{} {color}\n", self.username)),
+        );
+
+        self.writer
+            .write_all(format!("Your name is now shown in {color}\n").as_bytes())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Broadcasts `line` as a message or action from the user, marking them as back if they were
+    /// away. Only operators can send while the server is read-only, and muted users can't send.
+    async fn send_chat(&mut self, line: String) -> Result<()> {
+        if self.state.locked.load(SeqCst) && !is_admin(&self.state, &self.username).await {
+            self.writer
+                .write_all(b"The server is in read-only mode\n")
+                .await?;
+            return Ok(());
+        }
+
+        if self.state.muted.lock().await.contains(&self.username) {
+            self.writer.write_all(b"You are muted\n").await?;
+            return Ok(());
+        }
+
+        let now = Instant::now();
+
+        if let Some(wait) = self.slow_mode_wait(now)
+            && !is_admin(&self.state, &self.username).await
+        {
+            let secs = wait.as_millis().div_ceil(1000);
+            let plural = if secs == 1 { "" } else { "s" };
+            self.writer
+                .write_all(format!("Slow mode: wait {secs} more second{plural}\n").as_bytes())
+                .await?;
+            return Ok(());
+        }
+
+        self.last_post = Some(now);
+
+        if record_activity(&self.state, &self.username, now).await {
+            broadcast(
+                &self.state,
+                &ChatEvent::notice(format!("* {} is back\n", self.username)),
+            );
+        }
+
+        self.state.metrics.record_message(line.len());
+        self.state
+            .seen
+            .lock()
+            .await
+            .record(&self.username, Instant::now());
+
+        let mut history = self.state.history.lock().await;
+        history.record(line.clone());
+        broadcast(&self.state, &ChatEvent::from_user(&self.username, line));
+        drop(history);
+
+        Ok(())
+    }
+
+    /// Sends `text` privately to `to`, or queues it until they join if they are offline and offline
+    /// messages are enabled. Muted users can't send private messages either.
+    async fn send_private(&mut self, to: &str, text: &str) -> Result<()> {
+        let Some(text) = self.sanitized(text).await? else { return Ok(()) };
+
+        let reply = if text.is_empty() {
+            String::from("Usage: /msg <user> <message>\n")
+        } else if self.state.muted.lock().await.contains(&self.username) {
+            String::from("You are muted\n")
+        } else {
+            let users_guard = self.state.users.lock().await;
+
+            if let Some(target_state) = users_guard.get(to) {
+                // A send error means the target's handler is already exiting anyway
+                let _ = target_state.control_tx.send(ControlMessage::Notice(format!(
+                    "{} (private): {text}\n",
+                    self.username
+                )));
+
+                // The message is still delivered, but the sender learns not to expect a reply soon
+                let away_reply = target_state.away_reply(to).unwrap_or_default();
+
+                format!("To {to} (private): {text}\n{away_reply}")
+            } else if self.state.config.offline_msgs
+                && to.len() <= MAX_USERNAME_LEN
+                && !self.state.config.is_reserved_name(to)
+            {
+                // Queued while `users` is still locked, so the recipient can't join in between
+                // and miss it
+                let queued = self.state.mailbox.lock().await.push(
+                    to,
+                    OfflineMsg {
+                        from: self.username.clone(),
+                        body: text,
+                        sent_at: SystemTime::now(),
+                    },
+                );
+
+                if queued {
+                    format!("{to} is offline; your message will be delivered when they join\n")
+                } else {
+                    format!("{to} is offline and the mailbox for offline users is full\n")
+                }
+            } else {
+                String::from("No such user\n")
+            }
+        };
+
+        self.writer.write_all(reply.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Writes the private messages queued for the user while they were offline, if any.
+    async fn deliver_offline_msgs(&mut self) -> Result<()> {
+        let offline_msgs = self.state.mailbox.lock().await.take(&self.username);
+
+        if offline_msgs.is_empty() {
+            return Ok(());
+        }
+
+        let plural = if offline_msgs.len() == 1 { "" } else { "s" };
+        let lines = offline_msgs
+            .iter()
+            .map(|msg| {
+                format!(
+                    "[{}] {} (private): {}\n",
+                    format_utc(msg.sent_at),
+                    msg.from,
+                    msg.body
+                )
+            })
+            .collect::<Vec<_>>()
+            .concat();
+
+        self.writer
+            .write_all(
+                format!(
+                    "You have {} offline message{plural}\n{lines}",
+                    offline_msgs.len()
+                )
+                .as_bytes(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Replies with the number of users online.
+    async fn show_count(&mut self) -> Result<()> {
+        let online = self.state.users.lock().await.len();
+        let plural = if online == 1 { "" } else { "s" };
+
+        self.writer
+            .write_all(format!("{online} user{plural} online\n").as_bytes())
+            .await?;
+        Ok(())
+    }
+
+    /// Replies with the user's reconnect token and how long it works for after they leave.
+    async fn show_token(&mut self) -> Result<()> {
+        let token = self
+            .state
+            .users
+            .lock()
+            .await
+            .get(&self.username)
+            .and_then(|user| user.reconnect_token.clone());
+
+        let reply = match (token, self.state.config.reconnect_window) {
+            (Some(token), Some(window)) => {
+                let secs = window.as_secs();
+                let plural = if secs == 1 { "" } else { "s" };
+                format!(
+                    "Your reconnect token is {token} (send /token {token} instead of a username \
+                    within {secs} second{plural} of leaving to reclaim {})\n",
+                    self.username
+                )
+            }
+
+            _ => String::from("Reconnect tokens are disabled\n"),
+        };
+
+        self.writer.write_all(reply.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Replies with the server's current time and how long it has been up.
+    async fn show_time(&mut self) -> Result<()> {
+        let reply = format!(
+            "Server time: {}, up {} (since {})\n",
+            format_utc(SystemTime::now()),
+            format_elapsed(self.state.started_at.elapsed()),
+            format_utc(self.state.started_wall),
+        );
+
+        self.writer.write_all(reply.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Replies with up to `count` recent messages from the history, or with usage information if
+    /// the count was invalid.
+    async fn show_log(&mut self, count: Option<usize>) -> Result<()> {
+        let Some(count) = count else {
+            self.writer
+                .write_all(
+                    format!("Usage: /log [count], with a count from 1 to {MAX_LOG_LINES}\n")
+                        .as_bytes(),
+                )
+                .await?;
+            return Ok(());
+        };
+
+        let (shown, lines) = self.state.history.lock().await.recent(count);
+
+        let reply = if shown == 0 {
+            String::from("No recent messages\n")
+        } else {
+            format!(
+                "Last {shown} message{}:\n{lines}",
+                if shown == 1 { "" } else { "s" }
+            )
+        };
+        self.writer.write_all(reply.as_bytes()).await?;
+
+        Ok(())
+    }
+
+    /// Broadcasts the results of rolling the dice described by `spec`, or replies with usage
+    /// information if it is invalid.
+    async fn roll(&mut self, spec: &str) -> Result<()> {
+        let Some(Dice { count, sides }) = Dice::parse(spec) else {
+            self.writer
+                .write_all(
+                    format!(
+                        "Usage: /roll NdM (e.g. /roll 2d6), with up to {MAX_DICE} dice and \
+                        {MAX_SIDES} sides\n"
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+            return Ok(());
+        };
+
+        let rolls = {
+            let mut rng = rand::rng();
+            (0..count)
+                .map(|_| rng.random_range(1..=sides))
+                .collect::<Vec<_>>()
+        };
+
+        let results = rolls
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        self.send_chat(format!(
+            "* {} rolls {count}d{sides}: {results} (total {})\n",
+            self.username,
+            rolls.iter().sum::<u32>()
+        ))
+        .await
+    }
+
+    /// Runs the specified command or sends the specified message.
+    async fn run_command(&mut self, command: &Command<'_>) -> Result<()> {
+        match command {
+            Command::Empty => {}
+
+            // Actually quitting is handled in the main loop
+            Command::Quit(_) => self.writer.write_all(b"Goodbye for now!\n").await?,
+
+            Command::Help => self.writer.write_all(COMMAND_HELP.as_bytes()).await?,
+
+            Command::HelpFor(name) => {
+                self.writer
+                    .write_all(command_detail(name).as_bytes())
+                    .await?;
+            }
+
+            Command::Commands => self.writer.write_all(COMMAND_LIST.as_bytes()).await?,
+
+            Command::CommandsDetail => self.writer.write_all(COMMAND_MANIFEST.as_bytes()).await?,
+
+            Command::Who(format) => {
+                let listing = match format {
+                    WhoFormat::Text => who_listing(&self.state).await,
+                    WhoFormat::Json => who_json(&self.state).await,
+                };
+                self.writer.write_all(listing.as_bytes()).await?;
+            }
+
+            Command::Away(away_msg) => self.set_away(*away_msg).await?,
+
+            Command::Admin(token) => self.authenticate_admin(token).await?,
+
+            Command::Op(target) => self.set_operator(target, true).await?,
+
+            Command::Deop(target) => self.set_operator(target, false).await?,
+
+            Command::Kick(target) => self.kick(target).await?,
+
+            Command::Mute(target) => self.set_muted(target, true).await?,
+
+            Command::Unmute(target) => self.set_muted(target, false).await?,
+
+            Command::Announce(announcement) => self.announce(announcement).await?,
+
+            Command::PingAll => self.ping_all().await?,
+
+            Command::Echo(echo) => self.set_echo(*echo).await?,
+
+            Command::Color(color) => self.set_color(color).await?,
+
+            Command::Sig(sig) => self.set_sig(*sig).await?,
+
+            Command::Dnd(dnd) => self.set_dnd(*dnd).await?,
+
+            Command::Timestamps(timestamps) => self.set_timestamps(*timestamps).await?,
+
+            Command::QuietJoins(quiet_joins) => self.set_quiet_joins(*quiet_joins).await?,
+
+            Command::Pong(token) => self.answer_ping(token),
+
+            Command::Whois(target) => self.whois(target).await?,
+
+            Command::Count => self.show_count().await?,
+
+            Command::Whoami => {
+                self.writer
+                    .write_all(format!("You are {}\n", self.username).as_bytes())
+                    .await?;
+            }
+
+            Command::Version => self.writer.write_all(VERSION_LINE.as_bytes()).await?,
+
+            Command::Token => self.show_token().await?,
+
+            Command::Seen(target) => self.seen(target).await?,
+
+            Command::Time => self.show_time().await?,
+
+            Command::Log(count) => self.show_log(*count).await?,
+
+            Command::Stats => {
+                let online = self.state.users.lock().await.len();
+                self.writer
+                    .write_all(self.state.metrics.summary(online).as_bytes())
+                    .await?;
+            }
+
+            Command::StatsReset => self.reset_stats().await?,
+
+            Command::Lock => self.set_locked(true).await?,
+
+            Command::Unlock => self.set_locked(false).await?,
+
+            Command::SlowMode(secs) => self.set_slow_mode(*secs).await?,
+
+            Command::Roll(spec) => self.roll(spec).await?,
+
+            Command::Emote(msg, emote) => {
+                let Some(msg) = self.sanitized(msg).await? else { return Ok(()) };
+
+                let line = if msg.is_empty() {
+                    format!("{}: {emote}\n", self.username)
+                } else {
+                    format!("{}: {msg} {emote}\n", self.username)
+                };
+                self.send_chat(line).await?;
+            }
+
+            // Text left empty after sanitizing is ignored like an empty message
+            Command::Action(action) => {
+                if let Some(action) = self.sanitized(action).await?
+                    && !action.is_empty()
+                {
+                    self.send_chat(format!("* {} {action}\n", self.username))
+                        .await?;
+                }
+            }
+
+            Command::PrivateMsg(to, text) => self.send_private(to, text).await?,
+
+            Command::Msg(msg) => {
+                if let Some(msg) = self.sanitized(msg).await?
+                    && !msg.is_empty()
+                {
+                    if self.is_duplicate(&msg) {
+                        self.writer.write_all(b"(duplicate ignored)\n").await?;
+                        return Ok(());
+                    }
+
+                    let line = match &self.sig {
+                        Some(sig) => format!("{}: {msg} -- {sig}\n", self.username),
+                        None => format!("{}: {msg}\n", self.username),
+                    };
+                    self.send_chat(line).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_utc_times() {
+        for (secs, expected) in [
+            (0, "1970-01-01 00:00:00 UTC"),
+            (951_782_400, "2000-02-29 00:00:00 UTC"),
+            (1_735_787_045, "2025-01-02 03:04:05 UTC"),
+            (4_107_542_399, "2100-02-28 23:59:59 UTC"),
+        ] {
+            assert_eq!(
+                format_utc(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn timestamps_are_only_prefixed_when_on() {
+        let mut event = ChatEvent::from_user("alice", String::from("alice: hi\n"));
+        event.sent_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_735_787_045);
+
+        assert_eq!(render(&event, false), "alice: hi\n");
+        assert_eq!(render(&event, true), "[03:04:05] alice: hi\n");
+    }
+
+    #[test]
+    fn control_lines_are_never_timestamped() {
+        let event = ChatEvent::notice(format!("{COLOR_PREFIX}alice blue\n"));
+        assert_eq!(render(&event, true), event.line);
+    }
+
+    #[test]
+    fn shutdown_notices_give_the_shorter_timeout() {
+        for (client_disconnect_ms, shutdown_ms, expected) in [
+            (4000, 5000, "you have 4 seconds"),
+            (4000, 2000, "you have 2 seconds"),
+            // Partial seconds are rounded up
+            (1000, 5000, "you have 1 second "),
+            (300, 5000, "you have 1 second "),
+        ] {
+            let config = Config {
+                client_disconnect_timeout: Duration::from_millis(client_disconnect_ms),
+                shutdown_timeout: Duration::from_millis(shutdown_ms),
+                ..Config::default()
+            };
+            let notice = shutdown_notice(&config);
+            assert!(
+                notice.contains(expected),
+                "expected {expected:?} in {notice:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn formats_how_long_ago() {
+        for (secs, expected) in [
+            (0, "less than a minute ago"),
+            (59, "less than a minute ago"),
+            (60, "1 minute ago"),
+            (3 * 60 + 30, "3 minutes ago"),
+            (3600, "1 hour ago"),
+            (23 * 3600 + 3599, "23 hours ago"),
+            (2 * 86_400, "2 days ago"),
+        ] {
+            assert_eq!(format_ago(Duration::from_secs(secs)), expected);
+        }
+    }
+
+    #[test]
+    fn leave_notices_describe_the_departure() {
+        assert_eq!(Departure::Left(None).notice("alice"), "* alice left\n");
+        assert_eq!(
+            Departure::Left(Some(String::from("gone fishing"))).notice("alice"),
+            "* alice left (gone fishing)\n"
+        );
+        assert_eq!(
+            Departure::LostConnection.notice("alice"),
+            "* alice lost connection\n"
+        );
+        assert_eq!(
+            Departure::Shutdown.notice("alice"),
+            "* alice was disconnected (server shutdown)\n"
+        );
+    }
+
+    /// Builds a map of online users from `names`, with placeholder values.
+    fn users(names: &[&str]) -> HashMap<String, usize> {
+        names.iter().map(|name| (name.to_string(), 0)).collect()
+    }
+
+    #[test]
+    fn suggests_free_variations_of_a_taken_username() {
+        assert_eq!(
+            suggest_usernames("alice", &users(&["alice"]), &Config::default()),
+            ["alice2", "alice_", "alice3"]
+        );
+    }
+
+    #[test]
+    fn suggestions_skip_names_that_are_also_taken() {
+        assert_eq!(
+            suggest_usernames(
+                "alice",
+                &users(&["alice", "alice2", "alice_"]),
+                &Config::default()
+            ),
+            ["alice3", "alice4", "alice5"]
+        );
+        assert_eq!(
+            suggest_usernames(
+                "bob",
+                &users(&["bob", "bob_", "bob3", "bob4"]),
+                &Config::default()
+            ),
+            ["bob2", "bob5", "bob6"]
+        );
+    }
+
+    #[test]
+    fn suggestions_ignore_unrelated_users() {
+        assert_eq!(
+            suggest_usernames(
+                "alice",
+                &users(&["alice", "bob2", "Alice2"]),
+                &Config::default()
+            ),
+            ["alice2", "alice_", "alice3"]
+        );
+    }
+
+    #[test]
+    fn suggestions_skip_names_that_are_too_long_or_reserved() {
+        let longest = "a".repeat(MAX_USERNAME_LEN);
+        assert!(suggest_usernames(&longest, &users(&[&longest]), &Config::default()).is_empty());
+
+        let config = Config {
+            reserved_names: vec![String::from("alice2"), String::from("ALICE_")],
+            ..Config::default()
+        };
+        assert_eq!(
+            suggest_usernames("alice", &users(&["alice"]), &config),
+            ["alice3", "alice4", "alice5"]
+        );
+    }
+
+    #[test]
+    fn suggestions_run_out_when_every_variation_is_taken() {
+        let mut taken = (2..100).map(|n| format!("carol{n}")).collect::<Vec<_>>();
+        taken.push(String::from("carol_"));
+        let taken = taken.iter().map(String::as_str).collect::<Vec<_>>();
+
+        assert!(suggest_usernames("carol", &users(&taken), &Config::default()).is_empty());
+        assert_eq!(taken_message(&[]), "Username taken\n");
+    }
+
+    #[test]
+    fn taken_message_lists_suggestions() {
+        assert_eq!(
+            taken_message(&[String::from("alice2")]),
+            "Username taken (try alice2)\n"
+        );
+        assert_eq!(
+            taken_message(&["alice2", "alice_", "alice3"].map(String::from)),
+            "Username taken (try alice2, alice_, or alice3)\n"
+        );
     }
 }
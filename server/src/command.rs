@@ -1,13 +1,337 @@
+use std::sync::LazyLock;
+
+/// Metadata describing a command, used to generate both the help message and the command
+/// manifest so they stay in sync.
+#[derive(Clone, Copy)]
+pub struct CommandInfo {
+    /// The name of the command, including the leading slash.
+    pub name: &'static str,
+
+    /// The argument signature, or an empty string if the command takes no arguments.
+    pub args: &'static str,
+
+    /// A one-line description of the command.
+    pub desc: &'static str,
+
+    /// An example of using the command, or an empty string if it takes no arguments and so would
+    /// be used exactly as named.
+    pub example: &'static str,
+}
+
+/// All available commands other than emotes, in the order they appear in the help message.
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "/quit",
+        args: "[message]",
+        desc: "Leave the server, optionally with a parting message",
+        example: "/quit see you tomorrow",
+    },
+    CommandInfo {
+        name: "/help",
+        args: "[command]",
+        desc: "Show this message, or details about one command",
+        example: "/help who",
+    },
+    CommandInfo {
+        name: "/who",
+        args: "[json]",
+        desc: "List online users, optionally as JSON",
+        example: "/who json",
+    },
+    CommandInfo { name: "/list", args: "[json]", desc: "Same as /who", example: "/list json" },
+    CommandInfo { name: "/count", args: "", desc: "Show how many users are online", example: "" },
+    CommandInfo {
+        name: "/whois",
+        args: "<user>",
+        desc: "Show how long a user has been online and since they last posted (and their address for operators)",
+        example: "/whois alice",
+    },
+    CommandInfo { name: "/whoami", args: "", desc: "Show your own username", example: "" },
+    CommandInfo { name: "/version", args: "", desc: "Show the server's version", example: "" },
+    CommandInfo {
+        name: "/token",
+        args: "",
+        desc: "Show your token for reclaiming your username after reconnecting",
+        example: "",
+    },
+    CommandInfo {
+        name: "/seen",
+        args: "<user>",
+        desc: "Show when a user was last active",
+        example: "/seen alice",
+    },
+    CommandInfo {
+        name: "/time",
+        args: "",
+        desc: "Show the server's current time and uptime",
+        example: "",
+    },
+    CommandInfo {
+        name: "/log",
+        args: "[count]",
+        desc: "Show the most recent messages (20 unless given, up to 100)",
+        example: "/log 5",
+    },
+    CommandInfo {
+        name: "/away",
+        args: "[message]",
+        desc: "Mark yourself as away, or back if already away",
+        example: "/away out for lunch",
+    },
+    CommandInfo {
+        name: "/echo",
+        args: "<on|off>",
+        desc: "Choose whether to receive your own messages",
+        example: "/echo off",
+    },
+    CommandInfo {
+        name: "/dnd",
+        args: "<on|off>",
+        desc: "Stop or resume receiving everyone's messages",
+        example: "/dnd on",
+    },
+    CommandInfo {
+        name: "/timestamps",
+        args: "<on|off>",
+        desc: "Choose whether to show the time before each message",
+        example: "/timestamps on",
+    },
+    CommandInfo {
+        name: "/quiet",
+        args: "joins <on|off>",
+        desc: "Hide or show join and leave notices",
+        example: "/quiet joins on",
+    },
+    CommandInfo {
+        name: "/color",
+        args: "<color>",
+        desc: "Set the color others see your name in, e.g. /color blue",
+        example: "/color blue",
+    },
+    CommandInfo {
+        name: "/sig",
+        args: "[text]",
+        desc: "Set a signature shown after your messages, or clear it",
+        example: "/sig sent from my terminal",
+    },
+    CommandInfo {
+        name: "/roll",
+        args: "<NdM>",
+        desc: "Roll N dice with M sides, e.g. /roll 2d6",
+        example: "/roll 2d6",
+    },
+    CommandInfo {
+        name: "/action",
+        args: "<action>",
+        desc: "Broadcast an action, e.g. /action waves",
+        example: "/action waves",
+    },
+    CommandInfo {
+        name: "/msg",
+        args: "<user> <message>",
+        desc: "Send a private message to a user",
+        example: "/msg alice see you at lunch?",
+    },
+    CommandInfo {
+        name: "/admin",
+        args: "<token>",
+        desc: "Become an operator using the admin token",
+        example: "/admin s3cret",
+    },
+    CommandInfo {
+        name: "/op",
+        args: "<user>",
+        desc: "Make a user an operator (operators only)",
+        example: "/op alice",
+    },
+    CommandInfo {
+        name: "/deop",
+        args: "<user>",
+        desc: "Revoke a user's operator status (operators only)",
+        example: "/deop alice",
+    },
+    CommandInfo {
+        name: "/kick",
+        args: "<user>",
+        desc: "Disconnect a user (operators only)",
+        example: "/kick alice",
+    },
+    CommandInfo {
+        name: "/mute",
+        args: "<user>",
+        desc: "Stop a user's messages from being sent to anyone (operators only)",
+        example: "/mute alice",
+    },
+    CommandInfo {
+        name: "/unmute",
+        args: "<user>",
+        desc: "Lift a user's mute (operators only)",
+        example: "/unmute alice",
+    },
+    CommandInfo {
+        name: "/announce",
+        args: "<text>",
+        desc: "Broadcast a highlighted announcement (operators only)",
+        example: "/announce Maintenance at noon",
+    },
+    CommandInfo {
+        name: "/ping-all",
+        args: "",
+        desc: "Measure how quickly each user responds (operators only)",
+        example: "",
+    },
+    CommandInfo {
+        name: "/stats",
+        args: "",
+        desc: "Show connection and message counts since the last reset",
+        example: "",
+    },
+    CommandInfo {
+        name: "/stats-reset",
+        args: "",
+        desc: "Reset the connection and message counts (operators only)",
+        example: "",
+    },
+    CommandInfo {
+        name: "/lock",
+        args: "",
+        desc: "Make the server read-only (operators only)",
+        example: "",
+    },
+    CommandInfo {
+        name: "/unlock",
+        args: "",
+        desc: "Let everyone send messages again (operators only)",
+        example: "",
+    },
+    CommandInfo {
+        name: "/slowmode",
+        args: "<seconds>",
+        desc: "Make everyone wait between messages, or 0 to stop (operators only)",
+        example: "/slowmode 5",
+    },
+    CommandInfo {
+        name: "/commands",
+        args: "",
+        desc: "Show a machine-readable list of command names",
+        example: "",
+    },
+    CommandInfo {
+        name: "/commands-detail",
+        args: "",
+        desc: "Show a machine-readable (JSON) list of commands",
+        example: "",
+    },
+];
+
+/// Quick commands that send a message with an emote appended, e.g. `/shrug oh well`, or just the
+/// emote if no message is given. Adding an entry here is all that is needed to add an emote.
+pub const EMOTES: &[(&str, &str)] = &[
+    ("/shrug", r"¯\_(ツ)_/¯"),
+    ("/tableflip", "(╯°□°)╯︵ ┻━┻"),
+    ("/unflip", "┬─┬ノ( º _ ºノ)"),
+    ("/lenny", "( ͡° ͜ʖ ͡°)"),
+];
+
+/// Every command in the order they appear in the help message: `COMMANDS` followed by an entry
+/// for each of the `EMOTES`.
+pub static ALL_COMMANDS: LazyLock<Vec<CommandInfo>> = LazyLock::new(|| {
+    COMMANDS
+        .iter()
+        .copied()
+        .chain(EMOTES.iter().map(|&(name, emote)| CommandInfo {
+            name,
+            args: "[message]",
+            // Built once for the lifetime of the process, so leaking is harmless
+            desc: format!("Send a message ending in {emote}, or just the emote").leak(),
+            example: "",
+        }))
+        .collect()
+});
+
+/// The colors users can choose for their name with `/color`. Clients map these to their own
+/// rendering (e.g., ANSI colors in a terminal).
+pub const COLORS: &[&str] = &["red", "green", "yellow", "blue", "magenta", "cyan"];
+
 /// The help message explaining available commands.
-pub const COMMAND_HELP: &[u8] = b"
-/quit             Leave the server
-/help             Show this message
-/who              List online users
-/action <action>  Broadcast an action, e.g. /action waves
+pub static COMMAND_HELP: LazyLock<String> = LazyLock::new(|| {
+    let usages = ALL_COMMANDS
+        .iter()
+        .map(|info| {
+            if info.args.is_empty() {
+                info.name.to_string()
+            } else {
+                format!("{} {}", info.name, info.args)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let width = usages.iter().map(String::len).max().unwrap_or_default() + 2;
+
+    let lines = usages
+        .iter()
+        .zip(ALL_COMMANDS.iter())
+        .map(|(usage, info)| format!("{usage:<width$}{}", info.desc))
+        .collect::<Vec<_>>();
+
+    format!(
+        "\n{}\n\n{:<width$}Send a regular message\n\n",
+        lines.join("\n"),
+        "[anything else]"
+    )
+});
 
-[anything else]   Send a regular message
+/// Creates the detailed help for the command called `name` (with or without the leading slash),
+/// showing its usage, description, and an example.
+pub fn command_detail(name: &str) -> String {
+    let Some(info) = ALL_COMMANDS
+        .iter()
+        .find(|info| info.name.trim_start_matches('/') == name.trim_start_matches('/'))
+    else {
+        return format!("No such command: {name}; try /help\n");
+    };
+
+    let usage = if info.args.is_empty() {
+        info.name.to_string()
+    } else {
+        format!("{} {}", info.name, info.args)
+    };
+
+    let example = if info.example.is_empty() { info.name } else { info.example };
+
+    format!("Usage: {usage}\n{}\nExample: {example}\n", info.desc)
+}
 
-";
+/// A single line listing each command's name without the leading slash, e.g.
+/// `commands: quit,help,who`.
+pub static COMMAND_LIST: LazyLock<String> = LazyLock::new(|| {
+    let names = ALL_COMMANDS
+        .iter()
+        .map(|info| info.name.trim_start_matches('/'))
+        .collect::<Vec<_>>();
+
+    format!("commands: {}\n", names.join(","))
+});
+
+/// A single line of JSON listing each command's name, argument signature, and description.
+pub static COMMAND_MANIFEST: LazyLock<String> = LazyLock::new(|| {
+    let manifest = ALL_COMMANDS
+        .iter()
+        .map(|info| serde_json::json!({ "name": info.name, "args": info.args, "desc": info.desc }))
+        .collect::<Vec<_>>();
+
+    format!("{}\n", serde_json::Value::Array(manifest))
+});
+
+/// The output format for the list of online users.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhoFormat {
+    /// A human-readable sentence including away statuses.
+    Text,
+
+    /// A single line of JSON with the usernames, for scripts and bots.
+    Json,
+}
 
 /// The set of valid commands, including arbitrary messages and the empty (no-op) command.
 #[derive(PartialEq, Eq)]
@@ -15,18 +339,125 @@ pub enum Command<'a> {
     /// The no-op command.
     Empty,
 
-    /// Disconnects from the server.
-    Quit,
+    /// Disconnects from the server, with an optional parting message for everyone else.
+    Quit(Option<&'a str>),
 
     /// Retrieves the help message.
     Help,
 
-    /// Lists online users.
-    Who,
+    /// Retrieves the detailed help for the named command, which may not exist.
+    HelpFor(&'a str),
+
+    /// Lists online users in the given format.
+    Who(WhoFormat),
+
+    /// Shows the number of online users.
+    Count,
+
+    /// Shows information about a user.
+    Whois(&'a str),
+
+    /// Shows the user's own username.
+    Whoami,
+
+    /// Shows the server's version.
+    Version,
+
+    /// Shows the user's reconnect token.
+    Token,
+
+    /// Shows when a user last sent a message or left.
+    Seen(&'a str),
+
+    /// Marks the user as away with an optional message, or as back if already away and no message
+    /// is given.
+    Away(Option<&'a str>),
+
+    /// Authenticates as an operator with the admin token.
+    Admin(&'a str),
+
+    /// Grants operator status to a user.
+    Op(&'a str),
+
+    /// Revokes a user's operator status.
+    Deop(&'a str),
+
+    /// Disconnects a user.
+    Kick(&'a str),
+
+    /// Stops a user's messages and actions from being broadcast.
+    Mute(&'a str),
+
+    /// Lets a muted user's messages and actions be broadcast again.
+    Unmute(&'a str),
+
+    /// Broadcasts a highlighted announcement.
+    Announce(&'a str),
+
+    /// Sends a latency probe to every other user.
+    PingAll,
+
+    /// Shows the server's current time and uptime.
+    Time,
+
+    /// Shows up to the given number of recent messages, or `None` if the given count was invalid.
+    Log(Option<usize>),
+
+    /// Shows the activity counters.
+    Stats,
+
+    /// Resets the activity counters.
+    StatsReset,
+
+    /// Makes the server read-only for everyone but operators.
+    Lock,
+
+    /// Lifts read-only mode.
+    Unlock,
+
+    /// Sets the number of seconds everyone but operators must wait between messages and actions,
+    /// turning slow mode off if 0, or `None` if the given number was invalid.
+    SlowMode(Option<u64>),
+
+    /// Answers a latency probe. Sent automatically by clients rather than typed by users.
+    Pong(&'a str),
+
+    /// Turns receiving the user's own messages and actions on or off.
+    Echo(bool),
+
+    /// Turns do not disturb on or off, which stops the user from receiving broadcasts.
+    Dnd(bool),
+
+    /// Turns showing the time before each broadcast line on or off.
+    Timestamps(bool),
+
+    /// Turns hiding join and leave notices on or off.
+    QuietJoins(bool),
+
+    /// Sets the color of the user's name, which may not be in `COLORS`.
+    Color(&'a str),
+
+    /// Sets the signature appended to the user's messages, or clears it if none is given.
+    Sig(Option<&'a str>),
+
+    /// Retrieves the machine-readable list of command names.
+    Commands,
+
+    /// Retrieves the machine-readable command manifest.
+    CommandsDetail,
+
+    /// Rolls dice as described by a spec in `NdM` notation, which may be invalid.
+    Roll(&'a str),
+
+    /// Broadcasts a message (which may be empty) with an emote from `EMOTES` appended.
+    Emote(&'a str, &'static str),
 
     /// Broadcasts an action.
     Action(&'a str),
 
+    /// Sends a private message (which may be empty) to the named user.
+    PrivateMsg(&'a str, &'a str),
+
     /// Broadcasts a message.
     Msg(&'a str),
 }
@@ -41,17 +472,187 @@ impl<'a> Command<'a> {
         if trimmed.is_empty() {
             Self::Empty
         } else if trimmed == "/quit" {
-            Self::Quit
+            Self::Quit(None)
+        } else if let Some(parting) = trimmed.strip_prefix("/quit ") {
+            Self::Quit(Some(parting.trim_start()))
         } else if trimmed == "/help" {
             Self::Help
-        } else if trimmed == "/who" {
-            Self::Who
+        } else if let Some(name) = trimmed.strip_prefix("/help ") {
+            Self::HelpFor(name.trim_start())
+        } else if trimmed == "/who" || trimmed == "/list" {
+            Self::Who(WhoFormat::Text)
+        } else if trimmed == "/who json" || trimmed == "/list json" {
+            Self::Who(WhoFormat::Json)
+        } else if trimmed == "/count" {
+            Self::Count
+        } else if let Some(target) = trimmed.strip_prefix("/whois ") {
+            Self::Whois(target.trim_start())
+        } else if trimmed == "/whoami" {
+            Self::Whoami
+        } else if trimmed == "/version" {
+            Self::Version
+        } else if trimmed == "/token" {
+            Self::Token
+        } else if let Some(target) = trimmed.strip_prefix("/seen ") {
+            Self::Seen(target.trim_start())
+        } else if trimmed == "/away" {
+            Self::Away(None)
+        } else if let Some(away_msg) = trimmed.strip_prefix("/away ") {
+            Self::Away(Some(away_msg.trim_start()))
+        } else if let Some(token) = trimmed.strip_prefix("/admin ") {
+            Self::Admin(token.trim_start())
+        } else if let Some(target) = trimmed.strip_prefix("/op ") {
+            Self::Op(target.trim_start())
+        } else if let Some(target) = trimmed.strip_prefix("/deop ") {
+            Self::Deop(target.trim_start())
+        } else if let Some(target) = trimmed.strip_prefix("/kick ") {
+            Self::Kick(target.trim_start())
+        } else if let Some(target) = trimmed.strip_prefix("/mute ") {
+            Self::Mute(target.trim_start())
+        } else if let Some(target) = trimmed.strip_prefix("/unmute ") {
+            Self::Unmute(target.trim_start())
+        } else if let Some(announcement) = trimmed.strip_prefix("/announce ") {
+            Self::Announce(announcement.trim_start())
+        } else if trimmed == "/time" {
+            Self::Time
+        } else if trimmed == "/log" {
+            Self::Log(Some(DEFAULT_LOG_LINES))
+        } else if let Some(count) = trimmed.strip_prefix("/log ") {
+            Self::Log(
+                count
+                    .trim_start()
+                    .parse()
+                    .ok()
+                    .filter(|&count| count > 0)
+                    .map(|count: usize| count.min(MAX_LOG_LINES)),
+            )
+        } else if trimmed == "/stats" {
+            Self::Stats
+        } else if trimmed == "/stats-reset" {
+            Self::StatsReset
+        } else if trimmed == "/lock" {
+            Self::Lock
+        } else if trimmed == "/unlock" {
+            Self::Unlock
+        } else if let Some(secs) = trimmed.strip_prefix("/slowmode")
+            && (secs.is_empty() || secs.starts_with(' '))
+        {
+            Self::parse_slow_mode(secs.trim_start())
+        } else if trimmed == "/ping-all" {
+            Self::PingAll
+        } else if let Some(token) = trimmed.strip_prefix("/pong ") {
+            Self::Pong(token.trim_start())
+        } else if let Some(toggle) = Self::parse_toggle(trimmed) {
+            toggle
+        } else if let Some(color) = trimmed.strip_prefix("/color ") {
+            Self::Color(color.trim_start())
+        } else if trimmed == "/sig" {
+            Self::Sig(None)
+        } else if let Some(sig) = trimmed.strip_prefix("/sig ") {
+            Self::Sig(Some(sig.trim_start()))
+        } else if trimmed == "/commands" {
+            Self::Commands
+        } else if trimmed == "/commands-detail" {
+            Self::CommandsDetail
+        } else if let Some(spec) = trimmed.strip_prefix("/roll ") {
+            Self::Roll(spec.trim_start())
         } else if let Some(action) = trimmed.strip_prefix("/action ") {
             Self::Action(action)
+        } else if let Some(rest) = trimmed.strip_prefix("/msg ") {
+            Self::parse_private_msg(rest.trim_start())
+        } else if let Some(emote) = Self::parse_emote(trimmed) {
+            emote
         } else {
             Self::Msg(trimmed)
         }
     }
+
+    /// Parses a command that turns a setting on or off, e.g. `/echo on`.
+    fn parse_toggle(trimmed: &str) -> Option<Self> {
+        let (name, setting) = trimmed.rsplit_once(' ')?;
+
+        let on = match setting {
+            "on" => true,
+            "off" => false,
+            _ => return None,
+        };
+
+        match name {
+            "/echo" => Some(Self::Echo(on)),
+            "/dnd" => Some(Self::Dnd(on)),
+            "/timestamps" => Some(Self::Timestamps(on)),
+            "/quiet joins" => Some(Self::QuietJoins(on)),
+            _ => None,
+        }
+    }
+
+    /// Parses the recipient and message of a private message from the text following `/msg `. The
+    /// message is empty if only a recipient is given.
+    fn parse_private_msg(rest: &'a str) -> Self {
+        match rest.split_once(' ') {
+            Some((to, text)) => Self::PrivateMsg(to, text.trim_start()),
+            None => Self::PrivateMsg(rest, ""),
+        }
+    }
+
+    /// Parses the number of seconds following `/slowmode`, which is invalid if missing or over
+    /// `MAX_SLOW_MODE_SECS`.
+    fn parse_slow_mode(secs: &str) -> Self {
+        Self::SlowMode(secs.parse().ok().filter(|&secs| secs <= MAX_SLOW_MODE_SECS))
+    }
+
+    /// Parses an emote command from `EMOTES`, which must be followed by either nothing or a space
+    /// and a message.
+    fn parse_emote(trimmed: &'a str) -> Option<Self> {
+        EMOTES.iter().find_map(|&(name, emote)| {
+            let rest = trimmed.strip_prefix(name)?;
+
+            if rest.is_empty() {
+                Some(Self::Emote("", emote))
+            } else {
+                rest.strip_prefix(' ')
+                    .map(|msg| Self::Emote(msg.trim_start(), emote))
+            }
+        })
+    }
+}
+
+/// The number of recent messages shown by `/log` if no count is given.
+pub const DEFAULT_LOG_LINES: usize = 20;
+
+/// The maximum number of recent messages shown by `/log`, which larger counts are capped to.
+pub const MAX_LOG_LINES: usize = 100;
+
+/// The longest wait between messages that `/slowmode` can require, in seconds.
+pub const MAX_SLOW_MODE_SECS: u64 = 3600;
+
+/// The maximum number of dice that can be rolled at once.
+pub const MAX_DICE: u32 = 100;
+
+/// The maximum number of sides a die can have.
+pub const MAX_SIDES: u32 = 1000;
+
+/// A set of dice to roll, parsed from `NdM` notation (e.g., `2d6` for two six-sided dice).
+#[derive(Debug, PartialEq, Eq)]
+pub struct Dice {
+    /// The number of dice.
+    pub count: u32,
+
+    /// The number of sides on each die.
+    pub sides: u32,
+}
+
+impl Dice {
+    /// Parses `NdM` notation, where `N` defaults to 1 if omitted. Returns `None` if the spec is
+    /// malformed or outside the limits of `MAX_DICE` dice and `MAX_SIDES` sides.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (count, sides) = spec.split_once(['d', 'D'])?;
+        let count = if count.is_empty() { 1 } else { count.parse().ok()? };
+        let sides = sides.parse().ok()?;
+
+        ((1..=MAX_DICE).contains(&count) && (1..=MAX_SIDES).contains(&sides))
+            .then_some(Self { count, sides })
+    }
 }
 
 #[cfg(test)]
@@ -72,12 +673,24 @@ mod tests {
     fn parses_quit_command() {
         for input in ["/quit", "  /quit  ", "/quit\n"] {
             assert!(
-                matches!(Command::parse(input), Command::Quit),
+                matches!(Command::parse(input), Command::Quit(None)),
                 "expected Quit command for {input}"
             );
         }
     }
 
+    #[test]
+    fn parses_quit_command_with_parting_message() {
+        assert!(matches!(
+            Command::parse("/quit gone fishing"),
+            Command::Quit(Some("gone fishing"))
+        ));
+        assert!(matches!(
+            Command::parse("  /quit   back soon  \n"),
+            Command::Quit(Some("back soon"))
+        ));
+    }
+
     #[test]
     fn parses_help_command() {
         for input in ["/help", "  /help  ", "/help\n"] {
@@ -90,10 +703,479 @@ mod tests {
 
     #[test]
     fn parses_who_command() {
-        for input in ["/who", "  /who  ", "/who\n"] {
+        for input in ["/who", "  /who  ", "/who\n", "/list", " /list\n"] {
+            assert!(
+                matches!(Command::parse(input), Command::Who(WhoFormat::Text)),
+                "expected Who(Text) command for {input}"
+            );
+        }
+
+        for input in ["/who json", " /who json\n", "/list json"] {
+            assert!(
+                matches!(Command::parse(input), Command::Who(WhoFormat::Json)),
+                "expected Who(Json) command for {input}"
+            );
+        }
+
+        assert!(matches!(Command::parse("/who xml"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_away_command() {
+        for (input, expected) in [
+            ("/away", None),
+            ("  /away  ", None),
+            ("/away\n", None),
+            ("/away lunch", Some("lunch")),
+            ("/away  back in 5  ", Some("back in 5")),
+        ] {
+            assert!(
+                matches!(Command::parse(input), Command::Away(away_msg) if away_msg == expected),
+                "expected Away({expected:?}) for {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_admin_commands() {
+        assert!(matches!(
+            Command::parse("/admin secret"),
+            Command::Admin("secret")
+        ));
+        assert!(matches!(
+            Command::parse("  /admin  secret \n"),
+            Command::Admin("secret")
+        ));
+        assert!(matches!(Command::parse("/op bob"), Command::Op("bob")));
+        assert!(matches!(Command::parse("/op   bob"), Command::Op("bob")));
+        assert!(matches!(Command::parse("/deop bob"), Command::Deop("bob")));
+        assert!(matches!(Command::parse("/kick bob"), Command::Kick("bob")));
+        assert!(matches!(Command::parse("/mute bob"), Command::Mute("bob")));
+        assert!(matches!(
+            Command::parse("/unmute  bob"),
+            Command::Unmute("bob")
+        ));
+        assert!(matches!(
+            Command::parse("/announce  back in 5"),
+            Command::Announce("back in 5")
+        ));
+        assert!(matches!(
+            Command::parse("/whois  bob"),
+            Command::Whois("bob")
+        ));
+        assert!(matches!(
+            Command::parse("/seen  bob "),
+            Command::Seen("bob")
+        ));
+
+        // Without an argument, these are treated as regular messages
+        for input in [
+            "/admin",
+            "/op",
+            "/deop ",
+            "/kick",
+            "/announce ",
+            "/whois",
+            "/seen",
+        ] {
             assert!(
-                matches!(Command::parse(input), Command::Who),
-                "expected Who command for {input}"
+                matches!(Command::parse(input), Command::Msg(_)),
+                "expected Msg for {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_ping_commands() {
+        assert!(matches!(Command::parse("/ping-all"), Command::PingAll));
+        assert!(matches!(Command::parse(" /ping-all \n"), Command::PingAll));
+        assert!(matches!(
+            Command::parse("/pong 123\n"),
+            Command::Pong("123")
+        ));
+        assert!(matches!(Command::parse("/ping-all now"), Command::Msg(_)));
+        assert!(matches!(Command::parse("/pong"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_log_command() {
+        assert!(matches!(
+            Command::parse("/log"),
+            Command::Log(Some(DEFAULT_LOG_LINES))
+        ));
+        assert!(matches!(
+            Command::parse(" /log  5 \n"),
+            Command::Log(Some(5))
+        ));
+        assert!(matches!(
+            Command::parse("/log 1000"),
+            Command::Log(Some(MAX_LOG_LINES))
+        ));
+        for input in ["/log abc", "/log 0", "/log -3"] {
+            assert!(
+                matches!(Command::parse(input), Command::Log(None)),
+                "expected invalid Log command for {input}"
+            );
+        }
+        assert!(matches!(Command::parse("/logs"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_roll_command() {
+        assert!(matches!(Command::parse("/roll 2d6"), Command::Roll("2d6")));
+        assert!(matches!(
+            Command::parse(" /roll  d20 \n"),
+            Command::Roll("d20")
+        ));
+        assert!(matches!(Command::parse("/roll"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_valid_dice() {
+        assert_eq!(Dice::parse("2d6"), Some(Dice { count: 2, sides: 6 }));
+        assert_eq!(Dice::parse("d20"), Some(Dice { count: 1, sides: 20 }));
+        assert_eq!(Dice::parse("3D8"), Some(Dice { count: 3, sides: 8 }));
+        assert_eq!(
+            Dice::parse("100d1000"),
+            Some(Dice { count: MAX_DICE, sides: MAX_SIDES })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_dice() {
+        for spec in [
+            "0d6", "2d0", "d", "2d", "6", "", "garbage", "2d6d6", "-1d6", "2d-6", "101d6",
+            "1d1001", "1.5d6", "2 d6",
+        ] {
+            assert_eq!(Dice::parse(spec), None, "expected None for {spec:?}");
+        }
+    }
+
+    #[test]
+    fn parses_time_command() {
+        for input in ["/time", "  /time  ", "/time\n"] {
+            assert!(
+                matches!(Command::parse(input), Command::Time),
+                "expected Time command for {input}"
+            );
+        }
+
+        assert!(matches!(Command::parse("/time now"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_count_command() {
+        for input in ["/count", "  /count  ", "/count\n"] {
+            assert!(
+                matches!(Command::parse(input), Command::Count),
+                "expected Count command for {input}"
+            );
+        }
+
+        assert!(matches!(Command::parse("/count me in"), Command::Msg(_)));
+        assert!(matches!(Command::parse("/counting"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_whoami_command() {
+        for input in ["/whoami", "  /whoami  ", "/whoami\n"] {
+            assert!(
+                matches!(Command::parse(input), Command::Whoami),
+                "expected Whoami command for {input}"
+            );
+        }
+
+        assert!(matches!(Command::parse("/whoami bob"), Command::Msg(_)));
+        assert!(matches!(
+            Command::parse("/whois bob"),
+            Command::Whois("bob")
+        ));
+    }
+
+    #[test]
+    fn parses_version_command() {
+        for input in ["/version", "  /version  ", "/version\n"] {
+            assert!(
+                matches!(Command::parse(input), Command::Version),
+                "expected Version command for {input}"
+            );
+        }
+
+        assert!(matches!(Command::parse("/version 2"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_token_command() {
+        for input in ["/token", "  /token  ", "/token\n"] {
+            assert!(
+                matches!(Command::parse(input), Command::Token),
+                "expected Token command for {input}"
+            );
+        }
+
+        assert!(matches!(Command::parse("/token abc"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_stats_commands() {
+        assert!(matches!(Command::parse("/stats"), Command::Stats));
+        assert!(matches!(
+            Command::parse(" /stats-reset\n"),
+            Command::StatsReset
+        ));
+        assert!(matches!(Command::parse("/stats now"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_every_emote() {
+        for &(name, emote) in EMOTES {
+            assert!(
+                matches!(Command::parse(name), Command::Emote("", e) if e == emote),
+                "expected Emote for {name}"
+            );
+
+            let input = format!("  {name}  oh well \n");
+            assert!(
+                matches!(Command::parse(&input), Command::Emote("oh well", e) if e == emote),
+                "expected Emote with a message for {input}"
+            );
+
+            // The emote name must be a whole word
+            let input = format!("{name}s");
+            assert!(
+                matches!(Command::parse(&input), Command::Msg(_)),
+                "expected Msg for {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_lock_commands() {
+        assert!(matches!(Command::parse("/lock"), Command::Lock));
+        assert!(matches!(Command::parse(" /unlock\n"), Command::Unlock));
+        assert!(matches!(Command::parse("/lock now"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_slow_mode_command() {
+        assert!(matches!(
+            Command::parse("/slowmode 5"),
+            Command::SlowMode(Some(5))
+        ));
+        assert!(matches!(
+            Command::parse(" /slowmode  0\n"),
+            Command::SlowMode(Some(0))
+        ));
+
+        for input in [
+            "/slowmode",
+            "/slowmode -1",
+            "/slowmode soon",
+            "/slowmode 3601",
+        ] {
+            assert!(
+                matches!(Command::parse(input), Command::SlowMode(None)),
+                "expected an invalid SlowMode command for {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_echo_commands() {
+        assert!(matches!(Command::parse("/echo on"), Command::Echo(true)));
+        assert!(matches!(
+            Command::parse(" /echo off\n"),
+            Command::Echo(false)
+        ));
+        assert!(matches!(Command::parse("/echo"), Command::Msg(_)));
+        assert!(matches!(Command::parse("/echo maybe"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_dnd_commands() {
+        assert!(matches!(Command::parse("/dnd on"), Command::Dnd(true)));
+        assert!(matches!(Command::parse(" /dnd off\n"), Command::Dnd(false)));
+        assert!(matches!(Command::parse("/dnd"), Command::Msg(_)));
+        assert!(matches!(Command::parse("/dnd maybe"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_timestamps_commands() {
+        assert!(matches!(
+            Command::parse("/timestamps on"),
+            Command::Timestamps(true)
+        ));
+        assert!(matches!(
+            Command::parse(" /timestamps off\n"),
+            Command::Timestamps(false)
+        ));
+        assert!(matches!(Command::parse("/timestamps"), Command::Msg(_)));
+        assert!(matches!(
+            Command::parse("/timestamps maybe"),
+            Command::Msg(_)
+        ));
+    }
+
+    #[test]
+    fn parses_quiet_joins_commands() {
+        assert!(matches!(
+            Command::parse("/quiet joins on"),
+            Command::QuietJoins(true)
+        ));
+        assert!(matches!(
+            Command::parse(" /quiet joins off\n"),
+            Command::QuietJoins(false)
+        ));
+        for input in [
+            "/quiet joins",
+            "/quiet on",
+            "/quiet  joins on",
+            "/quiet leaves on",
+        ] {
+            assert!(
+                matches!(Command::parse(input), Command::Msg(_)),
+                "expected Msg for {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_color_command() {
+        assert!(matches!(
+            Command::parse("/color red"),
+            Command::Color("red")
+        ));
+        assert!(matches!(
+            Command::parse("  /color  Blue \n"),
+            Command::Color("Blue")
+        ));
+        assert!(matches!(Command::parse("/color"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_sig_commands() {
+        assert!(matches!(Command::parse("/sig"), Command::Sig(None)));
+        assert!(matches!(Command::parse(" /sig \n"), Command::Sig(None)));
+        assert!(matches!(
+            Command::parse("/sig  (she/her)"),
+            Command::Sig(Some("(she/her)"))
+        ));
+        assert!(matches!(Command::parse("/signal"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn parses_commands_command() {
+        for input in ["/commands", "  /commands  ", "/commands\n"] {
+            assert!(
+                matches!(Command::parse(input), Command::Commands),
+                "expected Commands command for {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn command_list_names_every_command_in_order() {
+        let names = COMMAND_LIST
+            .strip_prefix("commands: ")
+            .and_then(|list| list.strip_suffix('\n'))
+            .map(|list| list.split(',').collect::<Vec<_>>());
+
+        assert_eq!(
+            names,
+            Some(
+                ALL_COMMANDS
+                    .iter()
+                    .map(|info| &info.name[1..])
+                    .collect::<Vec<_>>()
+            )
+        );
+    }
+
+    #[test]
+    fn parses_commands_detail_command() {
+        for input in [
+            "/commands-detail",
+            "  /commands-detail  ",
+            "/commands-detail\n",
+        ] {
+            assert!(
+                matches!(Command::parse(input), Command::CommandsDetail),
+                "expected CommandsDetail command for {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn help_lists_every_command() {
+        for info in ALL_COMMANDS.iter() {
+            assert!(
+                COMMAND_HELP.contains(info.name) && COMMAND_HELP.contains(info.desc),
+                "expected help message to describe {}",
+                info.name
+            );
+        }
+    }
+
+    #[test]
+    fn parses_help_for_a_command() {
+        assert!(matches!(
+            Command::parse("/help who"),
+            Command::HelpFor("who")
+        ));
+        assert!(matches!(
+            Command::parse("  /help   /roll "),
+            Command::HelpFor("/roll")
+        ));
+        assert!(matches!(Command::parse("/help "), Command::Help));
+        assert!(matches!(Command::parse("/helpme"), Command::Msg(_)));
+    }
+
+    #[test]
+    fn command_detail_shows_usage_description_and_example() {
+        assert_eq!(
+            command_detail("who"),
+            "Usage: /who [json]\nList online users, optionally as JSON\nExample: /who json\n"
+        );
+        assert_eq!(
+            command_detail("/roll"),
+            "Usage: /roll <NdM>\nRoll N dice with M sides, e.g. /roll 2d6\nExample: /roll 2d6\n"
+        );
+
+        // Commands without arguments are used exactly as named
+        assert_eq!(
+            command_detail("time"),
+            "Usage: /time\nShow the server's current time and uptime\nExample: /time\n"
+        );
+    }
+
+    #[test]
+    fn command_detail_describes_emotes() {
+        assert_eq!(
+            command_detail("shrug"),
+            "Usage: /shrug [message]\nSend a message ending in ¯\\_(ツ)_/¯, or just the emote\n\
+            Example: /shrug\n"
+        );
+    }
+
+    #[test]
+    fn command_detail_rejects_unknown_commands() {
+        for name in ["foo", "/foo", "shrugs", "WHO"] {
+            assert_eq!(
+                command_detail(name),
+                format!("No such command: {name}; try /help\n")
+            );
+        }
+    }
+
+    #[test]
+    fn every_example_uses_its_command() {
+        for info in ALL_COMMANDS.iter() {
+            assert!(
+                info.example.is_empty()
+                    || info.example == info.name
+                    || info.example.starts_with(&format!("{} ", info.name)),
+                "expected the example for {} to use it",
+                info.name
             );
         }
     }
@@ -118,6 +1200,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_private_messages() {
+        for (input, expected_to, expected_text) in [
+            ("/msg bob hi there", "bob", "hi there"),
+            ("/msg   bob   hi  there", "bob", "hi  there"),
+            // The missing message is reported rather than broadcasting the command
+            ("/msg bob", "bob", ""),
+        ] {
+            assert!(
+                matches!(
+                    Command::parse(input),
+                    Command::PrivateMsg(to, text) if to == expected_to && text == expected_text
+                ),
+                "expected PrivateMsg(\"{expected_to}\", \"{expected_text}\") for {input}"
+            );
+        }
+    }
+
     #[test]
     fn parses_action_without_text_as_message() {
         // "/action" without trailing space and text is treated as a regular message
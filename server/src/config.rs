@@ -0,0 +1,738 @@
+use crate::ip_filter::{IpRange, parse_ip_ranges};
+use anyhow::{Context, Result, anyhow};
+use std::{env, fs, net::IpAddr, str::FromStr, time::Duration};
+
+/// The default maximum number of connections that can be in username selection at once.
+pub const MAX_PENDING_CONNECTIONS: usize = 64;
+
+/// The maximum length of a username in bytes, so that multibyte names are limited predictably.
+pub const MAX_USERNAME_LEN: usize = 32;
+
+/// The default number of rejected usernames a client can send before being disconnected.
+pub const MAX_USERNAME_ATTEMPTS: usize = 10;
+
+/// The default number of broadcast events that can be queued for each client before it is
+/// considered too slow and disconnected.
+pub const CLIENT_QUEUE_CAP: usize = 100;
+
+/// The default maximum number of connections a single IP address can open within
+/// `CONNECT_WINDOW`.
+pub const MAX_CONNECTS_PER_IP: usize = 30;
+
+/// The default window over which connections from each IP address are counted.
+pub const CONNECT_WINDOW: Duration = Duration::from_secs(10);
+
+/// The default time a connection can be idle before TCP keepalive probes are sent.
+pub const KEEPALIVE_IDLE: Duration = Duration::from_mins(1);
+
+/// The default time between TCP keepalive probes once a connection is idle.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The default time to wait for a client to accept a broadcast message before disconnecting it.
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default time a client has to finish a line once it starts sending it before disconnecting
+/// it.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The default time to wait for all clients to disconnect during graceful shutdown.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default time between checks for remaining clients during graceful shutdown, in case a
+/// disconnection is missed.
+pub const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The default time to wait for an individual client to close their connection before forcefully
+/// disconnecting them.
+pub const CLIENT_DISCONNECT_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// The placeholder username used (e.g., in logs) for clients that have not yet chosen a username.
+pub const UNKNOWN_USERNAME: &str = "[unknown]";
+
+/// The usernames that are always reserved, in addition to any configured ones.
+pub const DEFAULT_RESERVED_NAMES: &[&str] = &[UNKNOWN_USERNAME, "admin", "server", "system"];
+
+/// The default name of the pseudo-user that sends server-originated private messages.
+pub const DEFAULT_BOT_NAME: &str = "PrattleBot";
+
+/// The default prompt for choosing a username.
+pub const DEFAULT_PROMPT: &str = "Choose a username:";
+
+/// The placeholder in the welcome message that is replaced with the chosen username.
+pub const USERNAME_PLACEHOLDER: &str = "{username}";
+
+/// The default welcome message, where `{username}` is replaced with the chosen username.
+pub const DEFAULT_WELCOME: &str = "Hi {username}, welcome to Prattle! (Send /help for help)";
+
+/// Customizable text sent to clients, e.g. for white-labeling. Each message is sent as its own
+/// line, so it should not contain a newline.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Messages {
+    /// Text shown as soon as a client connects, before any password or username prompt, e.g. a
+    /// legal notice or the server's name and version. Unlike the other messages, it can span
+    /// multiple lines, and it is followed by a blank line to separate it from the prompt, so it
+    /// should not contain blank lines itself.
+    pub banner: Option<String>,
+
+    /// The prompt for choosing a username.
+    pub prompt: String,
+
+    /// The message sent after a username is chosen, where `{username}` is replaced with the
+    /// chosen username.
+    pub welcome: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            banner: None,
+            prompt: DEFAULT_PROMPT.to_string(),
+            welcome: DEFAULT_WELCOME.to_string(),
+        }
+    }
+}
+
+impl Messages {
+    /// Creates the text sent for the banner, if there is one, ending with the blank line that
+    /// separates it from the prompt.
+    #[must_use]
+    pub fn banner_text(&self) -> Option<String> {
+        self.banner
+            .as_deref()
+            .map(str::trim_end)
+            .filter(|banner| !banner.is_empty())
+            .map(|banner| format!("{banner}\n\n"))
+    }
+
+    /// Creates the welcome message for `username`.
+    #[must_use]
+    pub fn welcome_for(&self, username: &str) -> String {
+        self.welcome.replace(USERNAME_PLACEHOLDER, username)
+    }
+
+    /// Overrides the messages with the environment variables documented in `Config::from_env`.
+    fn override_from_env(&mut self) -> Result<()> {
+        if let Ok(banner) = env::var("PRATTLE_BANNER") {
+            self.banner = Some(banner);
+        } else if let Ok(path) = env::var("PRATTLE_BANNER_FILE") {
+            let banner = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read banner file {path}"))?;
+            self.banner = Some(banner);
+        }
+
+        if let Ok(prompt) = env::var("PRATTLE_PROMPT") {
+            self.prompt = prompt;
+        }
+
+        if let Ok(welcome) = env::var("PRATTLE_WELCOME") {
+            self.welcome = welcome;
+        }
+
+        Ok(())
+    }
+}
+
+/// When to tell a client the final form of the username they chose, right before the welcome.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfirmUsername {
+    /// Never send a confirmation.
+    #[default]
+    Never,
+
+    /// Only send a confirmation if the stored username differs from what the client typed (e.g.,
+    /// because surrounding whitespace was trimmed).
+    IfChanged,
+
+    /// Always send a confirmation.
+    Always,
+}
+
+/// What to do when a broadcast event arrives for a client whose queue is already full because
+/// they are reading too slowly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the oldest queued event to make room, so the client keeps up with the latest
+    /// messages like a scrollback.
+    #[default]
+    DropOldest,
+
+    /// Drop the new event, so the client still receives everything queued before it fell behind.
+    DropNewest,
+
+    /// Disconnect the client once it has received the events queued before it fell behind.
+    Disconnect,
+}
+
+/// Runtime options for the server. `Config::default()` preserves the standard behavior, while
+/// `Config::from_env()` allows overriding individual options with environment variables.
+#[allow(clippy::struct_excessive_bools)] // The options are independent of each other
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The maximum number of connections that have completed the TLS handshake but not yet chosen
+    /// a username. Further connections are told the server is busy and closed immediately.
+    pub max_pending_connections: usize,
+
+    /// The maximum number of connections (whether or not they have chosen a username), or `None`
+    /// for no limit. Further connections are told the server is full and closed immediately.
+    pub max_clients: Option<usize>,
+
+    /// The number of usernames a client can have rejected (e.g., for being empty, reserved, or
+    /// taken) before being disconnected, or `None` for no limit.
+    pub max_username_attempts: Option<usize>,
+
+    /// The number of broadcast events that can be queued for each client before it is considered
+    /// too slow and `overflow_policy` applies. Treated as 1 if set to 0.
+    pub client_queue_cap: usize,
+
+    /// What to do with further broadcast events once a client's queue is full.
+    pub overflow_policy: OverflowPolicy,
+
+    /// The maximum number of connections a single IP address can open within `connect_window`, or
+    /// `None` to disable the limit (e.g., behind a proxy that all clients connect through).
+    /// Further connections are closed immediately without a response.
+    pub max_connects_per_ip: Option<usize>,
+
+    /// The window over which connections from each IP address are counted for
+    /// `max_connects_per_ip`.
+    pub connect_window: Duration,
+
+    /// The IP ranges that clients can connect from. Connections from anywhere else are closed
+    /// immediately without a response. Clients can connect from anywhere if empty.
+    pub allow_ips: Vec<IpRange>,
+
+    /// The IP ranges that clients cannot connect from, which takes precedence over `allow_ips`.
+    /// Connections from these ranges are closed immediately without a response.
+    pub deny_ips: Vec<IpRange>,
+
+    /// The time to wait for a client to accept a broadcast message (or lag warning) before
+    /// treating it as dead and disconnecting it.
+    pub write_timeout: Duration,
+
+    /// The time a chatting client has to finish a line once its first bytes arrive, so that a
+    /// client that stalls partway through a line is disconnected. Waiting for a client to start a
+    /// line is not limited.
+    pub read_timeout: Duration,
+
+    /// How soon after a message an identical one from the same client is ignored as an accidental
+    /// double-send, replying `(duplicate ignored)` instead of broadcasting it. Only plain messages
+    /// are checked, not actions or emotes. Duplicates are broadcast as usual if `None`.
+    pub dedup_window: Option<Duration>,
+
+    /// Whether to automatically send joining clients the list of online users (as with `/who`)
+    /// right after the welcome message.
+    pub names_on_join: bool,
+
+    /// The number of recent messages and actions to replay to joining clients, as long as they fit
+    /// within `history::MAX_REPLAY_BYTES`. History is disabled if 0.
+    pub history_size: usize,
+
+    /// The token that clients can send with `/admin <token>` to become operators. Operator
+    /// commands are unavailable (except to users granted operator status) if `None`.
+    pub admin_token: Option<String>,
+
+    /// The shared password that clients must send before choosing a username. Anyone can join if
+    /// `None`.
+    pub password: Option<String>,
+
+    /// How long to defer a user's leave notice. If the same username joins again within this
+    /// window, neither the leave notice nor the join notice is broadcast, so a client that keeps
+    /// reconnecting is announced only once. Leave notices are sent immediately if zero.
+    pub leave_grace: Duration,
+
+    /// How long after a user leaves the reconnect token they were issued on joining (shown by
+    /// `/token`) still works. Sending `/token <token>` in place of a username restores the
+    /// username the token was issued for, if it is free. Tokens are kept in memory, so they do not
+    /// survive the server restarting. Reconnect tokens are disabled if `None` (the default).
+    pub reconnect_window: Option<Duration>,
+
+    /// The address to redirect clients to when the server shuts down, e.g. when migrating to a new
+    /// host. Clients are simply disconnected if `None`.
+    pub redirect_addr: Option<String>,
+
+    /// Whether clients start out with timestamps shown before each broadcast line, e.g.
+    /// `[12:34:56] alice: hi` (in UTC). Each client can change this with `/timestamps on|off`.
+    pub timestamps: bool,
+
+    /// Whether to append the resulting number of users online to join and leave notices, e.g.
+    /// `* alice joined the server (3 online)`.
+    pub online_count: bool,
+
+    /// Whether private messages sent with `/msg` to offline users are queued and delivered when
+    /// they next join, rather than rejected. Up to `mailbox::MAX_OFFLINE_MSGS` messages are kept
+    /// for each of up to `mailbox::MAX_MAILBOX_RECIPIENTS` offline users.
+    pub offline_msgs: bool,
+
+    /// Whether to send `You are now known as <username>` before the welcome message.
+    pub confirm_username: ConfirmUsername,
+
+    /// The prompt and welcome text sent to clients.
+    pub messages: Messages,
+
+    /// Whether to send the username prompt only once rather than again after each rejected
+    /// username, for scripted clients that send their username without waiting for the prompt.
+    pub prompt_once: bool,
+
+    /// Whether joining with a username that is already in use disconnects the existing session and
+    /// takes its place (e.g., after a client crashed but its connection is still half open).
+    /// Usernames that are already in use are rejected if `false`.
+    pub allow_takeover: bool,
+
+    /// The address to serve Prometheus metrics on over plain HTTP, e.g. `127.0.0.1:9000`. The
+    /// metrics endpoint is disabled if `None`.
+    pub metrics_addr: Option<String>,
+
+    /// The address to serve health checks on over plain HTTP, e.g. `127.0.0.1:8080`, where
+    /// `GET /healthz` responds with `OK` and the number of users online. The health check
+    /// endpoint is disabled if `None`.
+    pub health_addr: Option<String>,
+
+    /// The plain HTTP URL to post a JSON payload to for each join, leave, and message, e.g.
+    /// `http://127.0.0.1:9000/hooks/prattle`, for relaying chat to other services. Webhooks are
+    /// disabled if `None`.
+    pub webhook_url: Option<String>,
+
+    /// How often to send each client a heartbeat line (`\x05HEARTBEAT`) while they are online, to
+    /// keep NAT mappings alive and let clients detect a dead server. Clients should ignore the
+    /// line rather than display it. Heartbeats are disabled if `None`.
+    pub heartbeat_interval: Option<Duration>,
+
+    /// How long a connection can be idle before TCP keepalive probes are sent, so that clients
+    /// that vanish without closing the connection (e.g., due to a network drop) are eventually
+    /// detected and cleaned up. Keepalive is disabled if `None`.
+    pub keepalive_idle: Option<Duration>,
+
+    /// The time between TCP keepalive probes once a connection is idle. Only supported on some
+    /// platforms, including Linux, macOS, and Windows.
+    pub keepalive_interval: Duration,
+
+    /// The time to wait for all clients to disconnect during graceful shutdown before shutting
+    /// down anyway.
+    pub shutdown_timeout: Duration,
+
+    /// The time between checks for remaining clients during graceful shutdown. Shutdown proceeds
+    /// as soon as the last client disconnects regardless, so this is only a fallback.
+    pub shutdown_poll_interval: Duration,
+
+    /// The time to wait for an individual client to close their connection (e.g., after being
+    /// told the server is shutting down) before forcefully disconnecting them. Should not exceed
+    /// `shutdown_timeout`, and is clamped to it with a warning by `Server::bind` if it does.
+    pub client_disconnect_timeout: Duration,
+
+    /// Usernames that clients cannot choose, matched case-insensitively. Starts with
+    /// `DEFAULT_RESERVED_NAMES`, which should be kept when adding more.
+    pub reserved_names: Vec<String>,
+
+    /// The name of the pseudo-user that sends private messages on behalf of the server, such as
+    /// `bot_welcome`. Always reserved, so that real users cannot impersonate it.
+    pub bot_name: String,
+
+    /// A private message sent from `bot_name` to each joining client (but not to clients that
+    /// joined with `+quiet`), e.g. with tips for new users, where `{username}` is replaced with
+    /// the chosen username. No message is sent if `None`.
+    pub bot_welcome: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_pending_connections: MAX_PENDING_CONNECTIONS,
+            max_clients: None,
+            max_username_attempts: Some(MAX_USERNAME_ATTEMPTS),
+            client_queue_cap: CLIENT_QUEUE_CAP,
+            overflow_policy: OverflowPolicy::DropOldest,
+            max_connects_per_ip: Some(MAX_CONNECTS_PER_IP),
+            connect_window: CONNECT_WINDOW,
+            allow_ips: Vec::new(),
+            deny_ips: Vec::new(),
+            write_timeout: WRITE_TIMEOUT,
+            read_timeout: READ_TIMEOUT,
+            dedup_window: None,
+            names_on_join: false,
+            history_size: 0,
+            admin_token: None,
+            password: None,
+            leave_grace: Duration::ZERO,
+            reconnect_window: None,
+            redirect_addr: None,
+            timestamps: false,
+            online_count: false,
+            offline_msgs: false,
+            confirm_username: ConfirmUsername::Never,
+            messages: Messages::default(),
+            prompt_once: false,
+            allow_takeover: false,
+            metrics_addr: None,
+            health_addr: None,
+            webhook_url: None,
+            heartbeat_interval: None,
+            keepalive_idle: Some(KEEPALIVE_IDLE),
+            keepalive_interval: KEEPALIVE_INTERVAL,
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+            shutdown_poll_interval: SHUTDOWN_POLL_INTERVAL,
+            client_disconnect_timeout: CLIENT_DISCONNECT_TIMEOUT,
+            reserved_names: DEFAULT_RESERVED_NAMES
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            bot_name: DEFAULT_BOT_NAME.to_string(),
+            bot_welcome: None,
+        }
+    }
+}
+
+impl Config {
+    /// Creates a `Config` using the defaults, overridden by any of the following environment
+    /// variables that are set:
+    ///
+    /// - `PRATTLE_MAX_PENDING_CONNECTIONS` - The maximum number of connections in username
+    ///   selection at once.
+    /// - `PRATTLE_MAX_CLIENTS` - The maximum number of connections, or 0 for no limit.
+    /// - `PRATTLE_MAX_USERNAME_ATTEMPTS` - The number of usernames a client can have rejected
+    ///   before being disconnected, or 0 for no limit.
+    /// - `PRATTLE_CLIENT_QUEUE_CAP` - The number of broadcast events that can be queued for each
+    ///   client.
+    /// - `PRATTLE_OVERFLOW_POLICY` - What to do when a client's queue is full (`drop-oldest`,
+    ///   `drop-newest`, or `disconnect`).
+    /// - `PRATTLE_MAX_CONNECTS_PER_IP` - The maximum number of connections a single IP address can
+    ///   open within the connect window, or 0 to disable the limit.
+    /// - `PRATTLE_CONNECT_WINDOW_SECS` - The number of seconds over which connections from each IP
+    ///   address are counted.
+    /// - `PRATTLE_ALLOW_IPS` - Comma-separated IP addresses or CIDR ranges (e.g., `10.0.0.0/8`)
+    ///   that clients can connect from.
+    /// - `PRATTLE_DENY_IPS` - Comma-separated IP addresses or CIDR ranges that clients cannot
+    ///   connect from.
+    /// - `PRATTLE_WRITE_TIMEOUT_SECS` - The number of seconds to wait for a client to accept a
+    ///   broadcast message.
+    /// - `PRATTLE_READ_TIMEOUT_SECS` - The number of seconds a client has to finish a line once it
+    ///   starts sending it.
+    /// - `PRATTLE_DEDUP_WINDOW_MS` - The number of milliseconds within which an identical message
+    ///   from the same client is ignored, or 0 to broadcast duplicates.
+    /// - `PRATTLE_NAMES_ON_JOIN` - Whether to send joining clients the list of online users.
+    /// - `PRATTLE_HISTORY_SIZE` - The number of recent messages to replay to joining clients, or 0
+    ///   to disable history.
+    /// - `PRATTLE_ADMIN_TOKEN` - The token for becoming an operator with `/admin <token>`.
+    /// - `PRATTLE_PASSWORD` - The password clients must send before choosing a username.
+    /// - `PRATTLE_LEAVE_GRACE_SECS` - The number of seconds to defer leave notices in case the user
+    ///   reconnects.
+    /// - `PRATTLE_RECONNECT_WINDOW_SECS` - The number of seconds a user's reconnect token still
+    ///   works after they leave. Reconnect tokens are disabled if unset or 0.
+    /// - `PRATTLE_REDIRECT_ADDR` - The address to redirect clients to when shutting down.
+    /// - `PRATTLE_TIMESTAMPS` - Whether clients start out with timestamps shown.
+    /// - `PRATTLE_ONLINE_COUNT` - Whether to append the number of users online to join and leave
+    ///   notices.
+    /// - `PRATTLE_OFFLINE_MSGS` - Whether to queue private messages to offline users until they
+    ///   join.
+    /// - `PRATTLE_CONFIRM_USERNAME` - When to confirm the chosen username (`never`, `changed`, or
+    ///   `always`).
+    /// - `PRATTLE_BANNER` - The banner shown to clients as soon as they connect.
+    /// - `PRATTLE_BANNER_FILE` - The path to a file containing the banner, used if `PRATTLE_BANNER`
+    ///   is not set.
+    /// - `PRATTLE_PROMPT` - The prompt for choosing a username.
+    /// - `PRATTLE_WELCOME` - The welcome message, where `{username}` is replaced with the chosen
+    ///   username.
+    /// - `PRATTLE_PROMPT_ONCE` - Whether to send the username prompt only once.
+    /// - `PRATTLE_ALLOW_TAKEOVER` - Whether joining with a username in use takes over the existing
+    ///   session.
+    /// - `PRATTLE_METRICS_ADDR` - The address to serve Prometheus metrics on.
+    /// - `PRATTLE_HEALTH_ADDR` - The address to serve health checks on.
+    /// - `PRATTLE_WEBHOOK_URL` - The plain HTTP URL to post joins, leaves, and messages to.
+    /// - `PRATTLE_HEARTBEAT_SECS` - The number of seconds between heartbeat lines sent to clients,
+    ///   or 0 to disable heartbeats.
+    /// - `PRATTLE_KEEPALIVE_IDLE_SECS` - The number of seconds a connection can be idle before TCP
+    ///   keepalive probes are sent, or 0 to disable keepalive.
+    /// - `PRATTLE_KEEPALIVE_INTERVAL_SECS` - The number of seconds between TCP keepalive probes.
+    /// - `PRATTLE_SHUTDOWN_TIMEOUT_SECS` - The number of seconds to wait for all clients to
+    ///   disconnect during graceful shutdown.
+    /// - `PRATTLE_SHUTDOWN_POLL_MS` - The number of milliseconds between checks for remaining
+    ///   clients during graceful shutdown.
+    /// - `PRATTLE_CLIENT_DISCONNECT_TIMEOUT_SECS` - The number of seconds to wait for an individual
+    ///   client to close their connection.
+    /// - `PRATTLE_RESERVED_NAMES` - Comma-separated usernames to reserve in addition to the
+    ///   defaults.
+    /// - `PRATTLE_RESERVED_NAMES_FILE` - The path to a file of usernames to reserve in addition to
+    ///   the defaults, one per line.
+    /// - `PRATTLE_BOT_NAME` - The name of the pseudo-user that sends private messages on behalf of
+    ///   the server.
+    /// - `PRATTLE_BOT_WELCOME` - The private message sent from the bot to each joining client,
+    ///   where `{username}` is replaced with the chosen username.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if an environment variable is set but cannot be parsed, or if the banner or
+    /// reserved names file cannot be read.
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+
+        if let Some(max) = parse_env("PRATTLE_MAX_PENDING_CONNECTIONS")? {
+            config.max_pending_connections = max;
+        }
+
+        if let Some(max) = parse_env("PRATTLE_MAX_CLIENTS")? {
+            config.max_clients = (max > 0).then_some(max);
+        }
+
+        if let Some(max) = parse_env("PRATTLE_MAX_USERNAME_ATTEMPTS")? {
+            config.max_username_attempts = (max > 0).then_some(max);
+        }
+
+        if let Some(cap) = parse_env("PRATTLE_CLIENT_QUEUE_CAP")? {
+            config.client_queue_cap = cap;
+        }
+
+        if let Some(policy) = parse_env_overflow_policy("PRATTLE_OVERFLOW_POLICY")? {
+            config.overflow_policy = policy;
+        }
+
+        if let Some(max) = parse_env("PRATTLE_MAX_CONNECTS_PER_IP")? {
+            config.max_connects_per_ip = (max > 0).then_some(max);
+        }
+
+        if let Some(secs) = parse_env("PRATTLE_CONNECT_WINDOW_SECS")? {
+            config.connect_window = Duration::from_secs(secs);
+        }
+
+        if let Ok(ranges) = env::var("PRATTLE_ALLOW_IPS") {
+            config.allow_ips = parse_ip_ranges(&ranges).context("Invalid PRATTLE_ALLOW_IPS")?;
+        }
+
+        if let Ok(ranges) = env::var("PRATTLE_DENY_IPS") {
+            config.deny_ips = parse_ip_ranges(&ranges).context("Invalid PRATTLE_DENY_IPS")?;
+        }
+
+        if let Some(secs) = parse_env("PRATTLE_WRITE_TIMEOUT_SECS")? {
+            config.write_timeout = Duration::from_secs(secs);
+        }
+
+        if let Some(secs) = parse_env("PRATTLE_READ_TIMEOUT_SECS")? {
+            config.read_timeout = Duration::from_secs(secs);
+        }
+
+        if let Some(millis) = parse_env("PRATTLE_DEDUP_WINDOW_MS")? {
+            config.dedup_window = (millis > 0).then(|| Duration::from_millis(millis));
+        }
+
+        if let Some(enabled) = parse_env_flag("PRATTLE_NAMES_ON_JOIN")? {
+            config.names_on_join = enabled;
+        }
+
+        if let Some(size) = parse_env("PRATTLE_HISTORY_SIZE")? {
+            config.history_size = size;
+        }
+
+        if let Ok(token) = env::var("PRATTLE_ADMIN_TOKEN") {
+            config.admin_token = Some(token);
+        }
+
+        if let Ok(password) = env::var("PRATTLE_PASSWORD") {
+            config.password = Some(password);
+        }
+
+        if let Some(secs) = parse_env("PRATTLE_LEAVE_GRACE_SECS")? {
+            config.leave_grace = Duration::from_secs(secs);
+        }
+
+        if let Some(secs) = parse_env("PRATTLE_RECONNECT_WINDOW_SECS")? {
+            config.reconnect_window = (secs > 0).then(|| Duration::from_secs(secs));
+        }
+
+        if let Ok(addr) = env::var("PRATTLE_REDIRECT_ADDR") {
+            config.redirect_addr = Some(addr);
+        }
+
+        if let Some(enabled) = parse_env_flag("PRATTLE_TIMESTAMPS")? {
+            config.timestamps = enabled;
+        }
+
+        if let Some(enabled) = parse_env_flag("PRATTLE_ONLINE_COUNT")? {
+            config.online_count = enabled;
+        }
+
+        if let Some(enabled) = parse_env_flag("PRATTLE_OFFLINE_MSGS")? {
+            config.offline_msgs = enabled;
+        }
+
+        if let Some(confirm) = parse_env_confirm_username("PRATTLE_CONFIRM_USERNAME")? {
+            config.confirm_username = confirm;
+        }
+
+        config.messages.override_from_env()?;
+
+        if let Some(enabled) = parse_env_flag("PRATTLE_PROMPT_ONCE")? {
+            config.prompt_once = enabled;
+        }
+
+        if let Some(enabled) = parse_env_flag("PRATTLE_ALLOW_TAKEOVER")? {
+            config.allow_takeover = enabled;
+        }
+
+        if let Ok(addr) = env::var("PRATTLE_METRICS_ADDR") {
+            config.metrics_addr = Some(addr);
+        }
+
+        if let Ok(addr) = env::var("PRATTLE_HEALTH_ADDR") {
+            config.health_addr = Some(addr);
+        }
+
+        if let Ok(url) = env::var("PRATTLE_WEBHOOK_URL") {
+            config.webhook_url = Some(url);
+        }
+
+        if let Some(secs) = parse_env("PRATTLE_HEARTBEAT_SECS")? {
+            config.heartbeat_interval = (secs > 0).then(|| Duration::from_secs(secs));
+        }
+
+        if let Some(secs) = parse_env("PRATTLE_KEEPALIVE_IDLE_SECS")? {
+            config.keepalive_idle = (secs > 0).then(|| Duration::from_secs(secs));
+        }
+
+        if let Some(secs) = parse_env("PRATTLE_KEEPALIVE_INTERVAL_SECS")? {
+            config.keepalive_interval = Duration::from_secs(secs);
+        }
+
+        config.override_shutdown_from_env()?;
+        config.override_names_from_env()?;
+
+        Ok(config)
+    }
+
+    /// Overrides the graceful shutdown options with the environment variables documented in
+    /// `from_env`.
+    fn override_shutdown_from_env(&mut self) -> Result<()> {
+        if let Some(secs) = parse_env("PRATTLE_SHUTDOWN_TIMEOUT_SECS")? {
+            self.shutdown_timeout = Duration::from_secs(secs);
+        }
+
+        if let Some(millis) = parse_env("PRATTLE_SHUTDOWN_POLL_MS")? {
+            self.shutdown_poll_interval = Duration::from_millis(millis);
+        }
+
+        if let Some(secs) = parse_env("PRATTLE_CLIENT_DISCONNECT_TIMEOUT_SECS")? {
+            self.client_disconnect_timeout = Duration::from_secs(secs);
+        }
+
+        Ok(())
+    }
+
+    /// Overrides the reserved names and bot options with the environment variables documented in
+    /// `from_env`.
+    fn override_names_from_env(&mut self) -> Result<()> {
+        if let Ok(names) = env::var("PRATTLE_RESERVED_NAMES") {
+            self.reserved_names.extend(parse_name_list(&names, ','));
+        }
+
+        if let Ok(path) = env::var("PRATTLE_RESERVED_NAMES_FILE") {
+            let names = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read reserved names file {path}"))?;
+            self.reserved_names.extend(parse_name_list(&names, '\n'));
+        }
+
+        if let Ok(name) = env::var("PRATTLE_BOT_NAME") {
+            self.bot_name = name;
+        }
+
+        if let Ok(welcome) = env::var("PRATTLE_BOT_WELCOME") {
+            self.bot_welcome = Some(welcome);
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether clients can connect from `ip` according to `allow_ips` and `deny_ips`.
+    #[must_use]
+    pub fn is_ip_allowed(&self, ip: IpAddr) -> bool {
+        (self.allow_ips.is_empty() || self.allow_ips.iter().any(|range| range.contains(ip)))
+            && !self.deny_ips.iter().any(|range| range.contains(ip))
+    }
+
+    /// Checks whether `username` is reserved (including as the bot's name), ignoring case.
+    #[must_use]
+    pub fn is_reserved_name(&self, username: &str) -> bool {
+        let username = username.to_lowercase();
+        self.reserved_names
+            .iter()
+            .chain([&self.bot_name])
+            .any(|reserved| reserved.to_lowercase() == username)
+    }
+
+    /// Creates the line for the bot's private welcome message to `username`, if there is one.
+    #[must_use]
+    pub fn bot_welcome_for(&self, username: &str) -> Option<String> {
+        self.bot_welcome.as_deref().map(|welcome| {
+            format!(
+                "{} (private): {}\n",
+                self.bot_name,
+                welcome.replace(USERNAME_PLACEHOLDER, username)
+            )
+        })
+    }
+}
+
+/// Splits `list` on `separator` into trimmed, non-empty names.
+fn parse_name_list(list: &str, separator: char) -> impl Iterator<Item = String> {
+    list.split(separator)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(ToString::to_string)
+}
+
+/// Parses the environment variable `name` into `T`, returning `None` if it is not set.
+fn parse_env<T>(name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    env::var(name)
+        .ok()
+        .map(|val| {
+            val.parse()
+                .with_context(|| format!("Invalid value for {name}: {val}"))
+        })
+        .transpose()
+}
+
+/// Parses the environment variable `name` as a boolean flag, accepting `1`/`0`, `true`/`false`, and
+/// `yes`/`no` (case insensitive), returning `None` if it is not set.
+fn parse_env_flag(name: &str) -> Result<Option<bool>> {
+    env::var(name)
+        .ok()
+        .map(|val| match val.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" => Ok(true),
+            "0" | "false" | "no" => Ok(false),
+            _ => Err(anyhow!(
+                "Invalid value for {name}: {val} (expected true or false)"
+            )),
+        })
+        .transpose()
+}
+
+/// Parses the environment variable `name` as an `OverflowPolicy`, accepting `drop-oldest`,
+/// `drop-newest`, and `disconnect` (case insensitive), returning `None` if it is not set.
+fn parse_env_overflow_policy(name: &str) -> Result<Option<OverflowPolicy>> {
+    env::var(name)
+        .ok()
+        .map(|val| match val.to_ascii_lowercase().as_str() {
+            "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            "drop-newest" => Ok(OverflowPolicy::DropNewest),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            _ => Err(anyhow!(
+                "Invalid value for {name}: {val} (expected drop-oldest, drop-newest, or disconnect)"
+            )),
+        })
+        .transpose()
+}
+
+/// Parses the environment variable `name` as a `ConfirmUsername` option, accepting `never`,
+/// `changed`, and `always` (case insensitive), returning `None` if it is not set.
+fn parse_env_confirm_username(name: &str) -> Result<Option<ConfirmUsername>> {
+    env::var(name)
+        .ok()
+        .map(|val| match val.to_ascii_lowercase().as_str() {
+            "never" => Ok(ConfirmUsername::Never),
+            "changed" => Ok(ConfirmUsername::IfChanged),
+            "always" => Ok(ConfirmUsername::Always),
+            _ => Err(anyhow!(
+                "Invalid value for {name}: {val} (expected never, changed, or always)"
+            )),
+        })
+        .transpose()
+}
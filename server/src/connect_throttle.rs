@@ -0,0 +1,111 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/// Limits how many connections each IP address can open within a sliding window, to resist
+/// connect-floods from a single source.
+///
+/// Only the accepted connections within the current window are remembered for each address, and
+/// addresses without any are forgotten at most one window later, so memory use is bounded by the
+/// number of addresses that connected recently.
+pub struct ConnectThrottle {
+    max_per_window: usize,
+    window: Duration,
+    recent: HashMap<IpAddr, VecDeque<Instant>>,
+    last_prune: Instant,
+}
+
+impl ConnectThrottle {
+    /// Creates a throttle that allows `max_per_window` connections per address within each
+    /// `window`.
+    pub fn new(max_per_window: usize, window: Duration) -> Self {
+        Self { max_per_window, window, recent: HashMap::new(), last_prune: Instant::now() }
+    }
+
+    /// Records a connection attempt from `ip` at `now`, returning whether it should be allowed.
+    /// Refused attempts are not recorded, so an address is allowed again once its earlier
+    /// connections fall out of the window.
+    pub fn allow(&mut self, ip: IpAddr, now: Instant) -> bool {
+        let window = self.window;
+        let expired = |time: Instant| now.saturating_duration_since(time) >= window;
+
+        if expired(self.last_prune) {
+            self.recent
+                .retain(|_, times| times.back().is_some_and(|&time| !expired(time)));
+            self.last_prune = now;
+        }
+
+        let times = self.recent.entry(ip).or_default();
+
+        while times.front().is_some_and(|&time| expired(time)) {
+            times.pop_front();
+        }
+
+        if times.len() >= self.max_per_window {
+            return false;
+        }
+
+        times.push_back(now);
+        true
+    }
+
+    /// The number of addresses currently being tracked.
+    #[cfg(test)]
+    fn tracked(&self) -> usize { self.recent.len() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const WINDOW: Duration = Duration::from_secs(10);
+
+    fn ip(last_octet: u8) -> IpAddr { IpAddr::V4(Ipv4Addr::new(10, 0, 0, last_octet)) }
+
+    #[test]
+    fn refuses_connections_beyond_the_limit_within_the_window() {
+        let mut throttle = ConnectThrottle::new(3, WINDOW);
+        let start = Instant::now();
+
+        for i in 0..3 {
+            assert!(throttle.allow(ip(1), start + Duration::from_secs(i)));
+        }
+
+        assert!(!throttle.allow(ip(1), start + Duration::from_secs(3)));
+        assert!(!throttle.allow(ip(1), start + Duration::from_secs(9)));
+
+        // Other addresses have their own limits
+        assert!(throttle.allow(ip(2), start + Duration::from_secs(9)));
+    }
+
+    #[test]
+    fn allows_connections_again_once_earlier_ones_expire() {
+        let mut throttle = ConnectThrottle::new(2, WINDOW);
+        let start = Instant::now();
+
+        assert!(throttle.allow(ip(1), start));
+        assert!(throttle.allow(ip(1), start + Duration::from_secs(5)));
+        assert!(!throttle.allow(ip(1), start + Duration::from_secs(6)));
+
+        // Only the first connection has expired, freeing a single slot
+        assert!(throttle.allow(ip(1), start + WINDOW));
+        assert!(!throttle.allow(ip(1), start + WINDOW));
+    }
+
+    #[test]
+    fn forgets_addresses_without_recent_connections() {
+        let mut throttle = ConnectThrottle::new(2, WINDOW);
+        let start = Instant::now();
+
+        for last_octet in 0..100 {
+            throttle.allow(ip(last_octet), start);
+        }
+        assert_eq!(throttle.tracked(), 100);
+
+        throttle.allow(ip(200), start + WINDOW);
+        assert_eq!(throttle.tracked(), 1);
+    }
+}
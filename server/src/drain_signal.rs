@@ -0,0 +1,29 @@
+use crate::signal_handle::{self, Signal, SignalHandle};
+use anyhow::Result;
+
+/// A handle for telling a running server to drain, either programmatically or (with `listen`) on
+/// SIGUSR1.
+///
+/// A draining server closes its listener so that new connections are refused, but keeps serving
+/// existing clients until they leave on their own, without any timeout. A hard shutdown can still
+/// be triggered later with the server's shutdown signal, e.g. once few enough clients remain.
+pub type DrainHandle = SignalHandle;
+
+/// Creates a Unix signal handler that triggers `handle` when SIGUSR1 is received. Does nothing on
+/// other platforms, where draining is still possible programmatically with `DrainHandle::trigger`.
+///
+/// # Errors
+///
+/// Returns `Err` for errors installing the signal handler.
+pub fn listen(handle: DrainHandle) -> Result<()> {
+    signal_handle::listen(
+        handle,
+        Signal {
+            name: "SIGUSR1",
+            #[cfg(unix)]
+            kind: tokio::signal::unix::SignalKind::user_defined1(),
+            action: "draining",
+            feature: "draining on signal",
+        },
+    )
+}
@@ -0,0 +1,67 @@
+use std::time::SystemTime;
+
+/// A line broadcast to all clients, along with the metadata needed to decide whether to deliver it
+/// to a particular client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChatEvent {
+    /// The user who wrote the message or action, or `None` for server notices.
+    pub sender: Option<String>,
+
+    /// What the event is about, for consumers such as webhooks that need more than the text.
+    pub kind: EventKind,
+
+    /// The text to send, including the trailing newline.
+    pub line: String,
+
+    /// When the event was broadcast, shown to clients that turned on timestamps.
+    pub sent_at: SystemTime,
+}
+
+/// What a broadcast event is about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// A message or action written by the sender.
+    Message,
+
+    /// The named user joined (or reconnected to) the server.
+    Join(String),
+
+    /// The named user left the server.
+    Leave(String),
+
+    /// Any other server notice.
+    Notice,
+}
+
+impl ChatEvent {
+    /// Creates an event for a server notice that is not attributed to any user.
+    pub fn notice(line: String) -> Self { Self::new(None, EventKind::Notice, line) }
+
+    /// Creates an event for the notice that `username` joined the server.
+    pub fn join(username: &str, line: String) -> Self {
+        Self::new(None, EventKind::Join(username.to_string()), line)
+    }
+
+    /// Creates an event for the notice that `username` left the server.
+    pub fn leave(username: &str, line: String) -> Self {
+        Self::new(None, EventKind::Leave(username.to_string()), line)
+    }
+
+    /// Creates an event for a message or action written by `sender`.
+    pub fn from_user(sender: &str, line: String) -> Self {
+        Self::new(Some(sender.to_string()), EventKind::Message, line)
+    }
+
+    /// Creates an event sent now.
+    fn new(sender: Option<String>, kind: EventKind, line: String) -> Self {
+        Self { sender, kind, line, sent_at: SystemTime::now() }
+    }
+
+    /// Whether the event is a join or leave notice.
+    pub const fn is_presence(&self) -> bool {
+        matches!(self.kind, EventKind::Join(_) | EventKind::Leave(_))
+    }
+
+    /// Whether the event was written by `username`.
+    pub fn is_from(&self, username: &str) -> bool { self.sender.as_deref() == Some(username) }
+}
@@ -0,0 +1,286 @@
+use crate::{config::OverflowPolicy, event::ChatEvent};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering::Relaxed},
+    },
+};
+use tokio::sync::{Notify, mpsc::error::TryRecvError};
+use tracing::warn;
+
+/// The queues of each subscriber, keyed by subscription ID.
+type Queues = Mutex<HashMap<u64, Arc<Queue>>>;
+
+/// Delivers broadcast events to every subscribed client through a bounded queue per client.
+///
+/// When a slow client's queue is full, the overflow policy decides whether to drop its oldest
+/// event, drop the new one, or evict it. An evicted client's handler notices with
+/// `Subscription::is_evicted` so that it can disconnect the client. Events are sent to all queues
+/// while holding a lock, so every client receives them in the same order.
+pub struct Fanout {
+    queue_cap: usize,
+    policy: OverflowPolicy,
+    next_id: AtomicU64,
+    queues: Arc<Queues>,
+}
+
+impl Fanout {
+    /// Creates a fanout with no subscribers, where each subscriber's queue holds up to `queue_cap`
+    /// events (at least 1) before `policy` applies.
+    pub fn new(queue_cap: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            queue_cap: queue_cap.max(1),
+            policy,
+            next_id: AtomicU64::new(0),
+            queues: Arc::default(),
+        }
+    }
+
+    /// Subscribes a new client to all events sent from now on.
+    pub fn subscribe(&self) -> Subscription {
+        let id = self.next_id.fetch_add(1, Relaxed);
+        let queue = Arc::new(Queue::default());
+
+        lock(&self.queues).insert(id, Arc::clone(&queue));
+
+        Subscription { id, queue, queues: Arc::clone(&self.queues) }
+    }
+
+    /// Queues `event` for every subscriber, applying the overflow policy to those whose queues are
+    /// full. Returns the number of subscribers remaining, including any that dropped the event.
+    pub fn send(&self, event: &ChatEvent) -> usize {
+        let mut queues = lock(&self.queues);
+
+        queues.retain(|id, queue| {
+            let mut state = lock(&queue.state);
+
+            if state.events.len() >= self.queue_cap {
+                if !state.overflowing {
+                    warn!("Subscriber {id} fell behind, applying {:?}", self.policy);
+                    state.overflowing = true;
+                }
+
+                match self.policy {
+                    OverflowPolicy::DropOldest => _ = state.events.pop_front(),
+                    OverflowPolicy::DropNewest => return true,
+                    OverflowPolicy::Disconnect => {
+                        state.evicted = true;
+                        drop(state);
+                        queue.ready.notify_one();
+                        return false;
+                    }
+                }
+            }
+
+            state.events.push_back(event.clone());
+            drop(state);
+            queue.ready.notify_one();
+            true
+        });
+
+        queues.len()
+    }
+}
+
+/// A subscriber's queued events, shared between the fanout and the subscription.
+#[derive(Default)]
+struct Queue {
+    state: Mutex<QueueState>,
+
+    /// Notified whenever an event is queued or the subscriber is evicted.
+    ready: Notify,
+}
+
+#[derive(Default)]
+struct QueueState {
+    events: VecDeque<ChatEvent>,
+
+    /// Whether the queue has been full since it was last emptied, so that falling behind is only
+    /// logged once each time.
+    overflowing: bool,
+
+    /// Whether the subscriber was evicted, in which case no more events will be queued.
+    evicted: bool,
+}
+
+/// A client's queue of broadcast events, which unsubscribes when dropped.
+pub struct Subscription {
+    id: u64,
+    queue: Arc<Queue>,
+    queues: Arc<Queues>,
+}
+
+impl Subscription {
+    /// Waits for the next event, returning `None` if the subscriber was evicted and all events
+    /// queued before then have been received. Cancellation safe, since events are only removed
+    /// from the queue when they are returned.
+    pub async fn recv(&self) -> Option<ChatEvent> {
+        loop {
+            match self.try_recv() {
+                Ok(event) => return Some(event),
+                Err(TryRecvError::Disconnected) => return None,
+                // A notification sent since the last check is kept until this waits for it
+                Err(TryRecvError::Empty) => self.queue.ready.notified().await,
+            }
+        }
+    }
+
+    /// Receives the next event if one is already queued.
+    pub fn try_recv(&self) -> Result<ChatEvent, TryRecvError> {
+        let mut state = lock(&self.queue.state);
+
+        match state.events.pop_front() {
+            Some(event) => {
+                if state.events.is_empty() {
+                    state.overflowing = false;
+                }
+
+                Ok(event)
+            }
+
+            None if state.evicted => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Whether the subscriber was evicted for falling too far behind, in which case no more events
+    /// will be queued for it.
+    pub fn is_evicted(&self) -> bool { lock(&self.queue.state).evicted }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) { lock(&self.queues).remove(&self.id); }
+}
+
+/// Locks `mutex`, ignoring poisoning because every update to the fanout's maps and queues is made
+/// in a single step, so they are always consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CLIENT_QUEUE_CAP;
+
+    /// Receives every event already queued on `subscription`, returning their lines.
+    fn received_lines(subscription: &Subscription) -> Vec<String> {
+        std::iter::from_fn(|| subscription.try_recv().ok())
+            .map(|event| event.line)
+            .collect()
+    }
+
+    #[test]
+    fn subscribers_receive_events_in_order() {
+        let fanout = Fanout::new(CLIENT_QUEUE_CAP, OverflowPolicy::default());
+        let mut first = fanout.subscribe();
+        let mut second = fanout.subscribe();
+
+        for i in 0..3 {
+            assert_eq!(fanout.send(&ChatEvent::notice(format!("{i}\n"))), 2);
+        }
+
+        for subscription in [&mut first, &mut second] {
+            for i in 0..3 {
+                assert_eq!(
+                    subscription.try_recv().map(|event| event.line),
+                    Ok(format!("{i}\n"))
+                );
+            }
+            assert_eq!(subscription.try_recv(), Err(TryRecvError::Empty));
+        }
+    }
+
+    #[test]
+    fn full_subscribers_are_evicted_without_affecting_others() {
+        let fanout = Fanout::new(CLIENT_QUEUE_CAP, OverflowPolicy::Disconnect);
+        let fast = fanout.subscribe();
+        let slow = fanout.subscribe();
+
+        for i in 0..=CLIENT_QUEUE_CAP {
+            fanout.send(&ChatEvent::notice(format!("{i}\n")));
+            assert_eq!(
+                fast.try_recv().map(|event| event.line),
+                Ok(format!("{i}\n"))
+            );
+        }
+
+        assert!(slow.is_evicted());
+        assert!(!fast.is_evicted());
+        assert_eq!(fanout.send(&ChatEvent::notice(String::from("after\n"))), 1);
+
+        // Events queued before the eviction can still be received
+        for i in 0..CLIENT_QUEUE_CAP {
+            assert_eq!(
+                slow.try_recv().map(|event| event.line),
+                Ok(format!("{i}\n"))
+            );
+        }
+        assert_eq!(slow.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn dropping_a_subscription_unsubscribes() {
+        let fanout = Fanout::new(CLIENT_QUEUE_CAP, OverflowPolicy::default());
+        let subscription = fanout.subscribe();
+        assert_eq!(fanout.send(&ChatEvent::notice(String::from("hi\n"))), 1);
+
+        drop(subscription);
+        assert_eq!(fanout.send(&ChatEvent::notice(String::from("hi\n"))), 0);
+    }
+
+    #[test]
+    fn dropping_the_oldest_keeps_the_most_recent_events() {
+        let fanout = Fanout::new(3, OverflowPolicy::DropOldest);
+        let slow = fanout.subscribe();
+
+        for i in 0..5 {
+            assert_eq!(fanout.send(&ChatEvent::notice(format!("{i}\n"))), 1);
+        }
+
+        assert!(!slow.is_evicted());
+        assert_eq!(received_lines(&slow), ["2\n", "3\n", "4\n"]);
+    }
+
+    #[test]
+    fn dropping_the_newest_keeps_the_earliest_events() {
+        let fanout = Fanout::new(3, OverflowPolicy::DropNewest);
+        let slow = fanout.subscribe();
+
+        for i in 0..5 {
+            assert_eq!(fanout.send(&ChatEvent::notice(format!("{i}\n"))), 1);
+        }
+
+        assert!(!slow.is_evicted());
+        assert_eq!(received_lines(&slow), ["0\n", "1\n", "2\n"]);
+
+        // Once there is room again, new events are queued as usual
+        fanout.send(&ChatEvent::notice(String::from("5\n")));
+        assert_eq!(received_lines(&slow), ["5\n"]);
+    }
+
+    #[test]
+    fn queued_events_wake_a_waiting_subscriber() -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?
+            .block_on(async {
+                let fanout = Arc::new(Fanout::new(CLIENT_QUEUE_CAP, OverflowPolicy::default()));
+                let subscription = fanout.subscribe();
+
+                let sender = Arc::clone(&fanout);
+                tokio::spawn(async move {
+                    tokio::task::yield_now().await;
+                    sender.send(&ChatEvent::notice(String::from("hi\n")));
+                });
+
+                let event =
+                    tokio::time::timeout(std::time::Duration::from_secs(1), subscription.recv())
+                        .await?;
+                assert_eq!(event.map(|event| event.line), Some(String::from("hi\n")));
+
+                Ok(())
+            })
+    }
+}
@@ -0,0 +1,21 @@
+use crate::{
+    http::{self, Response},
+    state::SharedState,
+};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+/// Serves health checks over plain HTTP on `listener` until the task is aborted. Only
+/// `GET /healthz` is supported, which responds with `OK` and the number of users currently online
+/// so that load balancers and orchestrators can tell the server is accepting work.
+pub async fn serve(listener: TcpListener, state: Arc<SharedState>) {
+    http::serve(listener, state, "health check", |state, path| async move {
+        if path != "/healthz" {
+            return None;
+        }
+
+        let online = state.users.lock().await.len();
+        Some(Response { content_type: "text/plain", body: format!("OK users={online}\n") })
+    })
+    .await;
+}
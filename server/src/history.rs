@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+/// The maximum combined size in bytes of the messages replayed to a client when they join, so that
+/// a long history cannot turn into a single huge write that stalls the client's first read.
+pub const MAX_REPLAY_BYTES: usize = 16 * 1024;
+
+/// The most recent messages and actions sent by users, replayed to clients when they join and shown
+/// on request with `/log`. Once full, recording a new line forgets the oldest one.
+pub struct History {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl History {
+    /// Creates an empty history that remembers up to `capacity` lines, or none if `capacity` is 0.
+    pub const fn new(capacity: usize) -> Self { Self { lines: VecDeque::new(), capacity } }
+
+    /// Records `line`, which should include the trailing newline.
+    pub fn record(&mut self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.lines.len() >= self.capacity {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(line);
+    }
+
+    /// Creates the backlog to send a joining client, made up of as many of the most recent lines as
+    /// fit within `max_bytes`. If older lines had to be left out, the backlog starts with a note
+    /// saying how many are shown, unless not even the most recent line fits, in which case the
+    /// backlog is empty.
+    pub fn replay(&self, max_bytes: usize) -> String {
+        let mut bytes = 0;
+        let shown = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|line| {
+                bytes += line.len();
+                bytes <= max_bytes
+            })
+            .count();
+
+        let mut backlog = if shown == 0 {
+            return String::new();
+        } else if shown < self.lines.len() {
+            format!("(showing last {shown} of {} messages)\n", self.lines.len())
+        } else {
+            String::new()
+        };
+
+        for line in self.lines.range(self.lines.len() - shown..) {
+            backlog.push_str(line);
+        }
+
+        backlog
+    }
+
+    /// Returns up to `count` of the most recent lines joined together, along with the number of
+    /// lines included, which is less than `count` if fewer have been recorded.
+    pub fn recent(&self, count: usize) -> (usize, String) {
+        let shown = count.min(self.lines.len());
+
+        (
+            shown,
+            self.lines
+                .range(self.lines.len() - shown..)
+                .map(String::as_str)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_the_oldest_lines_when_full() {
+        let mut history = History::new(2);
+
+        for line in ["one\n", "two\n", "three\n"] {
+            history.record(line.to_string());
+        }
+
+        assert_eq!(history.replay(MAX_REPLAY_BYTES), "two\nthree\n");
+    }
+
+    #[test]
+    fn replays_only_the_most_recent_lines_that_fit() {
+        let mut history = History::new(10);
+
+        for line in ["aaaa\n", "bbbb\n", "cccc\n"] {
+            history.record(line.to_string());
+        }
+
+        assert_eq!(
+            history.replay(12),
+            "(showing last 2 of 3 messages)\nbbbb\ncccc\n"
+        );
+        assert_eq!(history.replay(15), "aaaa\nbbbb\ncccc\n");
+    }
+
+    #[test]
+    fn replays_nothing_when_the_most_recent_line_is_too_long() {
+        let mut history = History::new(10);
+        history.record("a".repeat(20) + "\n");
+
+        assert_eq!(history.replay(12), "");
+
+        // Older lines that would fit are not shown without the newer ones either
+        let mut history = History::new(10);
+        history.record(String::from("short\n"));
+        history.record("a".repeat(20) + "\n");
+
+        assert_eq!(history.replay(12), "");
+    }
+
+    #[test]
+    fn shows_up_to_the_requested_number_of_recent_lines() {
+        let mut history = History::new(10);
+
+        for line in ["one\n", "two\n", "three\n"] {
+            history.record(line.to_string());
+        }
+
+        assert_eq!(history.recent(2), (2, String::from("two\nthree\n")));
+        assert_eq!(history.recent(5), (3, String::from("one\ntwo\nthree\n")));
+    }
+
+    #[test]
+    fn records_nothing_when_disabled() {
+        let mut history = History::new(0);
+        history.record(String::from("hello\n"));
+
+        assert_eq!(history.replay(MAX_REPLAY_BYTES), "");
+    }
+}
@@ -0,0 +1,108 @@
+use crate::state::SharedState;
+use std::{io, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{info, warn};
+
+/// The time allowed for a client to send its request and accept the response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The maximum size of a request head that is read before responding.
+const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+
+/// A successful plain-text response.
+pub struct Response {
+    /// The value of the `Content-Type` header.
+    pub content_type: &'static str,
+
+    /// The response body.
+    pub body: String,
+}
+
+/// Serves minimal plain HTTP on `listener` until the task is aborted, for endpoints such as
+/// metrics that don't go through the chat protocol. Each connection is closed after a single
+/// response. `handle` is called with the path of each `GET` request and returns the response, or
+/// `None` for a 404. `name` describes the endpoint in logs.
+pub async fn serve<F, Fut>(
+    listener: TcpListener,
+    state: Arc<SharedState>,
+    name: &'static str,
+    handle: F,
+) where
+    F: Fn(Arc<SharedState>, String) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Option<Response>> + Send,
+{
+    loop {
+        match listener.accept().await {
+            Ok((socket, addr)) => {
+                let state = Arc::clone(&state);
+                let handle = handle.clone();
+
+                tokio::spawn(async move {
+                    match tokio::time::timeout(REQUEST_TIMEOUT, respond(socket, state, handle))
+                        .await
+                    {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => warn!("Error serving {name} to {addr}: {e}"),
+                        Err(_) => warn!("{name} request from {addr} timed out"),
+                    }
+                });
+            }
+
+            Err(e) => warn!("Failed to accept {name} connection: {e}"),
+        }
+    }
+}
+
+/// Reads a single HTTP request from `socket` and responds with the result of `handle` or an error
+/// status.
+async fn respond<F, Fut>(socket: TcpStream, state: Arc<SharedState>, handle: F) -> io::Result<()>
+where
+    F: Fn(Arc<SharedState>, String) -> Fut,
+    Fut: Future<Output = Option<Response>>,
+{
+    let mut reader = BufReader::new(socket).take(MAX_REQUEST_BYTES);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+
+    // Skip the headers, which are not needed
+    let mut header = String::new();
+    while reader.read_line(&mut header).await? > 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+
+    let path = request_line
+        .strip_prefix("GET ")
+        .and_then(|rest| rest.split_once(' '))
+        .map(|(path, _)| path.to_string());
+
+    let response = match path {
+        Some(path) => handle(state, path).await,
+        None => None,
+    };
+
+    let (status, content_type, body) = if let Some(Response { content_type, body }) = response {
+        ("200 OK", content_type, body)
+    } else {
+        info!("Unsupported HTTP request: {}", request_line.trim_end());
+        ("404 Not Found", "text/plain", String::from("Not found\n"))
+    };
+
+    let mut socket = reader.into_inner().into_inner();
+    socket
+        .write_all(
+            format!(
+                "HTTP/1.1 {status}\r\n\
+                Content-Type: {content_type}\r\n\
+                Content-Length: {}\r\n\
+                Connection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .as_bytes(),
+        )
+        .await?;
+    socket.shutdown().await
+}
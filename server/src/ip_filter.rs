@@ -0,0 +1,154 @@
+use anyhow::{Context, Result, bail};
+use std::{net::IpAddr, str::FromStr};
+
+/// A range of IP addresses in CIDR notation (e.g., `10.0.0.0/8` or `fd00::/8`), or a single
+/// address if no prefix length is given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Whether `ip` is within the range. IPv4 addresses mapped into IPv6 (e.g., from a dual-stack
+    /// listener) are matched as IPv4.
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let network = addr
+            .parse::<IpAddr>()
+            .with_context(|| format!("Invalid IP address: {addr}"))?
+            .to_canonical();
+
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse()
+                .with_context(|| format!("Invalid prefix length: {prefix_len}"))?,
+            None => max_len,
+        };
+
+        if prefix_len > max_len {
+            bail!("Prefix length {prefix_len} is too long for {network}");
+        }
+
+        Ok(Self { network, prefix_len })
+    }
+}
+
+/// Parses a comma-separated list of IP ranges, ignoring surrounding whitespace and empty entries.
+///
+/// # Errors
+///
+/// Returns `Err` if any entry is not a valid IP address or CIDR range.
+pub fn parse_ip_ranges(list: &str) -> Result<Vec<IpRange>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether the range parsed from `range` contains the address parsed from `ip`.
+    fn contains(range: &str, ip: &str) -> Result<bool> {
+        Ok(range.parse::<IpRange>()?.contains(ip.parse()?))
+    }
+
+    #[test]
+    fn single_addresses_match_only_themselves() -> Result<()> {
+        assert!(contains("192.168.1.10", "192.168.1.10")?);
+        assert!(!contains("192.168.1.10", "192.168.1.11")?);
+        assert!(contains("::1", "::1")?);
+        assert!(!contains("::1", "::2")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cidr_ranges_match_addresses_within_them() -> Result<()> {
+        assert!(contains("10.1.0.0/16", "10.1.0.0")?);
+        assert!(contains("10.1.0.0/16", "10.1.255.255")?);
+        assert!(!contains("10.1.0.0/16", "10.2.0.0")?);
+        assert!(contains("fd00::/8", "fd12:3456::1")?);
+        assert!(!contains("fd00::/8", "fe80::1")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn zero_length_prefixes_match_everything_of_the_same_family() -> Result<()> {
+        assert!(contains("0.0.0.0/0", "1.2.3.4")?);
+        assert!(contains("0.0.0.0/0", "255.255.255.255")?);
+        assert!(!contains("0.0.0.0/0", "::1")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_match_ipv4_ranges() -> Result<()> {
+        assert!(contains("127.0.0.0/8", "::ffff:127.0.0.1")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_ranges_are_rejected() {
+        for input in [
+            "",
+            "localhost",
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/x",
+            "10.0.0/8",
+        ] {
+            assert!(
+                input.parse::<IpRange>().is_err(),
+                "expected error for {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_comma_separated_lists() -> Result<()> {
+        assert_eq!(
+            parse_ip_ranges(" 10.0.0.0/8, ,::1 ")?,
+            vec!["10.0.0.0/8".parse()?, "::1/128".parse()?]
+        );
+        assert!(parse_ip_ranges("10.0.0.0/8,nope").is_err());
+
+        Ok(())
+    }
+}
@@ -1,7 +1,30 @@
+pub mod config;
+pub mod drain_signal;
+pub mod ip_filter;
 pub mod logger;
+#[cfg(any(test, feature = "memory-transport"))]
+pub mod memory_transport;
+pub mod reload_signal;
 pub mod server;
 pub mod shutdown_signal;
+pub mod signal_handle;
 pub mod tls;
 
+mod auth;
 mod client;
 mod command;
+mod connect_throttle;
+mod event;
+mod fanout;
+mod health;
+mod history;
+mod http;
+mod line_reader;
+mod mailbox;
+mod metrics;
+mod reconnect;
+mod sanitize;
+mod seen;
+mod state;
+mod user;
+mod webhook;
@@ -0,0 +1,318 @@
+use std::{io, mem, time::Duration};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt},
+    time::{self, Instant, error::Elapsed},
+};
+
+/// The maximum number of bytes in a line read from a client, excluding the newline. Longer lines
+/// are rejected without being buffered in full.
+pub const MAX_LINE_LENGTH: usize = 1024;
+
+/// The result of reading from a client with `LineReader::read_line`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LineRead {
+    /// A complete line, including the newline unless the client closed the connection mid-line.
+    /// Lines ending with `\r\n` are normalized to end with `\n`.
+    Line(String),
+
+    /// The line exceeded `MAX_LINE_LENGTH` and is being discarded.
+    TooLong,
+
+    /// The client closed the connection.
+    Eof,
+}
+
+/// Reads lines from a client while buffering at most `MAX_LINE_LENGTH` bytes of any single line,
+/// so that a client sending a huge amount of data without a newline cannot exhaust the server's
+/// memory.
+///
+/// Partially read lines are kept between calls, so `read_line` is cancellation safe (e.g., when
+/// used in `tokio::select!`) as long as the same `LineReader` is used for the next call.
+#[derive(Debug, Default)]
+pub struct LineReader {
+    buf: Vec<u8>,
+    discarding: bool,
+
+    /// When the first bytes of the line currently being read arrived, if any have.
+    line_started: Option<Instant>,
+}
+
+impl LineReader {
+    /// Reads the next line from `reader`. If the line is too long, `LineRead::TooLong` is returned
+    /// as soon as the limit is exceeded, and the rest of the line is skipped as it arrives during
+    /// subsequent calls.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` for I/O errors or if the line is not valid UTF-8.
+    pub async fn read_line<R>(&mut self, reader: &mut R) -> io::Result<LineRead>
+    where R: AsyncBufRead + Unpin {
+        while self.discarding {
+            let available = reader.fill_buf().await?;
+
+            if available.is_empty() {
+                return Ok(LineRead::Eof);
+            }
+
+            if let Some(newline_idx) = available.iter().position(|&b| b == b'\n') {
+                reader.consume(newline_idx + 1);
+                self.discarding = false;
+            } else {
+                let len = available.len();
+                reader.consume(len);
+            }
+        }
+
+        // Account for anything already read before a previous call was cancelled, leaving room for
+        // a `\r\n` line ending
+        let limit = (MAX_LINE_LENGTH + 2).saturating_sub(self.buf.len());
+
+        let bytes_read = (&mut *reader)
+            .take(limit as u64)
+            .read_until(b'\n', &mut self.buf)
+            .await?;
+
+        if bytes_read == 0 && self.buf.is_empty() {
+            return Ok(LineRead::Eof);
+        }
+
+        // Clients such as telnet end lines with `\r\n`, which is normalized here so that no
+        // carriage return reaches usernames, commands, or broadcasts
+        if self.buf.ends_with(b"\r\n") {
+            self.buf.remove(self.buf.len() - 2);
+        }
+
+        let complete = self.buf.ends_with(b"\n");
+
+        if self.buf.len() - usize::from(complete) > MAX_LINE_LENGTH {
+            self.buf.clear();
+            self.discarding = !complete;
+            return Ok(LineRead::TooLong);
+        }
+
+        String::from_utf8(mem::take(&mut self.buf))
+            .map(LineRead::Line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Reads the next line from `reader` like `read_line`, but gives up with `Err(Elapsed)` if the
+    /// line is not finished within `timeout` of its first bytes arriving. Waiting for a line to
+    /// start is not limited, so this only catches clients that stall partway through a line.
+    ///
+    /// The start of the line is kept between calls, so cancelled calls do not extend the deadline.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Ok(Err)` for the same reasons as `read_line`.
+    pub async fn read_line_within<R>(
+        &mut self,
+        reader: &mut R,
+        timeout: Duration,
+    ) -> Result<io::Result<LineRead>, Elapsed>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let line_started = if let Some(line_started) = self.line_started {
+            line_started
+        } else {
+            if self.buf.is_empty() && !self.discarding {
+                match reader.fill_buf().await {
+                    Ok([]) => return Ok(Ok(LineRead::Eof)),
+                    Ok(_) => {}
+                    Err(e) => return Ok(Err(e)),
+                }
+            }
+
+            *self.line_started.insert(Instant::now())
+        };
+
+        let result = time::timeout_at(line_started + timeout, self.read_line(reader)).await;
+
+        if result.is_ok() {
+            self.line_started = None;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use tokio::io::AsyncWriteExt;
+
+    /// Reads every line from `input` with a single `LineReader`.
+    fn read_all(input: &[u8]) -> Result<Vec<LineRead>> {
+        tokio::runtime::Builder::new_current_thread()
+            .build()?
+            .block_on(async {
+                let mut reader = tokio::io::BufReader::new(input);
+                let mut line_reader = LineReader::default();
+                let mut results = Vec::new();
+
+                loop {
+                    let result = line_reader.read_line(&mut reader).await?;
+
+                    if result == LineRead::Eof {
+                        break Ok(results);
+                    }
+
+                    results.push(result);
+                }
+            })
+    }
+
+    #[test]
+    fn reads_regular_lines() -> Result<()> {
+        assert_eq!(
+            read_all(b"hello\nworld\npartial")?,
+            [
+                LineRead::Line(String::from("hello\n")),
+                LineRead::Line(String::from("world\n")),
+                LineRead::Line(String::from("partial")),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_long_lines_and_skips_the_rest() -> Result<()> {
+        let max_line = "a".repeat(MAX_LINE_LENGTH);
+        let mut input = format!("{max_line}\n").into_bytes();
+        input.extend(b"b".repeat(MAX_LINE_LENGTH * 10));
+        input.extend(b"\nafter\n");
+
+        assert_eq!(
+            read_all(&input)?,
+            [
+                LineRead::Line(format!("{max_line}\n")),
+                LineRead::TooLong,
+                LineRead::Line(String::from("after\n")),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn crlf_line_endings_are_normalized() -> Result<()> {
+        let max_line = "a".repeat(MAX_LINE_LENGTH);
+        let input = format!("hello\r\n{max_line}\r\n{max_line}a\r\nlone\rcr\n");
+
+        assert_eq!(
+            read_all(input.as_bytes())?,
+            [
+                LineRead::Line(String::from("hello\n")),
+                // The carriage return does not count toward the limit
+                LineRead::Line(format!("{max_line}\n")),
+                LineRead::TooLong,
+                // Only a carriage return right before the newline is removed
+                LineRead::Line(String::from("lone\rcr\n")),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lines_just_over_the_limit_are_rejected_without_skipping_the_next() -> Result<()> {
+        let long_line = "a".repeat(MAX_LINE_LENGTH + 1);
+
+        assert_eq!(
+            read_all(format!("{long_line}\nafter\n").as_bytes())?,
+            [LineRead::TooLong, LineRead::Line(String::from("after\n"))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn multibyte_characters_at_the_limit_are_not_errors() -> Result<()> {
+        let long_line = "あ".repeat(MAX_LINE_LENGTH);
+        assert_eq!(
+            read_all(format!("{long_line}\n").as_bytes())?,
+            [LineRead::TooLong]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn partial_lines_are_kept_across_cancelled_reads() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?
+            .block_on(async {
+                let (mut client, server) = tokio::io::duplex(64);
+                let mut reader = tokio::io::BufReader::new(server);
+                let mut line_reader = LineReader::default();
+
+                // A read that is cancelled (e.g., by another `select!` branch) mid-line keeps what
+                // it has read so far
+                client.write_all(b"hel").await?;
+                assert!(
+                    tokio::time::timeout(
+                        std::time::Duration::from_millis(50),
+                        line_reader.read_line(&mut reader)
+                    )
+                    .await
+                    .is_err()
+                );
+
+                client.write_all(b"lo\n").await?;
+                assert_eq!(
+                    line_reader.read_line(&mut reader).await?,
+                    LineRead::Line(String::from("hello\n"))
+                );
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn only_unfinished_lines_time_out() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?
+            .block_on(async {
+                let timeout = Duration::from_millis(100);
+                let (mut client, server) = tokio::io::duplex(64);
+                let mut reader = tokio::io::BufReader::new(server);
+                let mut line_reader = LineReader::default();
+
+                // Waiting for a line to start is not limited
+                assert!(
+                    time::timeout(
+                        timeout * 2,
+                        line_reader.read_line_within(&mut reader, timeout)
+                    )
+                    .await
+                    .is_err()
+                );
+
+                client.write_all(b"hello\n").await?;
+                assert_eq!(
+                    line_reader.read_line_within(&mut reader, timeout).await??,
+                    LineRead::Line(String::from("hello\n"))
+                );
+
+                // Cancelled reads do not restart the clock for a partial line
+                client.write_all(b"hel").await?;
+                assert!(
+                    time::timeout(
+                        timeout / 2,
+                        line_reader.read_line_within(&mut reader, timeout)
+                    )
+                    .await
+                    .is_err()
+                );
+                let started = Instant::now();
+                assert!(
+                    line_reader
+                        .read_line_within(&mut reader, timeout)
+                        .await
+                        .is_err()
+                );
+                assert!(started.elapsed() < timeout);
+
+                Ok(())
+            })
+    }
+}
@@ -1,24 +1,59 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
+use std::{env, fs::OpenOptions, path::Path, sync::Arc};
 use tracing::{debug, level_filters::LevelFilter};
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
 /// Installs a global tracing subscriber that defaults to `default_level` unless overridden by the
 /// `RUST_LOG` environment variable.
 ///
+/// Logs are written to stderr, and also appended to the file at `PRATTLE_LOG_FILE` if that
+/// environment variable is set.
+///
 /// Also checks for the case where `RUST_LOG` is set to something other than "OFF" (case
 /// insensitive), but logging is off, printing a warning to stderr if so.
 ///
 /// # Errors
 ///
-/// Returns `Err` if initializing the subscriber was unsuccessful, likely because there was already
-/// a global subscriber installed.
+/// Returns `Err` if the log file cannot be opened or initializing the subscriber was unsuccessful,
+/// likely because there was already a global subscriber installed.
 pub fn init_with_default(default_level: LevelFilter) -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
+    init_with_log_file(default_level, env::var_os("PRATTLE_LOG_FILE"))
+}
+
+/// Installs a global tracing subscriber the same way as `init_with_default`, but with an explicit
+/// log file.
+///
+/// Logs are appended to `log_file` (if any) instead of the file at `PRATTLE_LOG_FILE`. The same
+/// filtering applies to both stderr and the log file.
+///
+/// # Errors
+///
+/// Returns `Err` if the log file cannot be opened or initializing the subscriber was unsuccessful,
+/// likely because there was already a global subscriber installed.
+pub fn init_with_log_file(
+    default_level: LevelFilter,
+    log_file: Option<impl AsRef<Path>>,
+) -> Result<()> {
+    let file_layer = log_file
+        .map(|path| {
+            let path = path.as_ref();
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("failed to open log file {}", path.display()))
+        })
+        .transpose()?
+        .map(|file| fmt::layer().with_ansi(false).with_writer(Arc::new(file)));
+
+    tracing_subscriber::registry()
+        .with(
             EnvFilter::builder()
                 .with_default_directive(default_level.into())
                 .from_env_lossy(),
         )
+        .with(fmt::layer())
+        .with(file_layer)
         .try_init()
         .map_err(|e| anyhow!("failed to initialize tracing subscriber: {e}"))?;
 
@@ -26,7 +61,7 @@ pub fn init_with_default(default_level: LevelFilter) -> Result<()> {
     // set to a typo/bogus value, so check if the "error" level is disabled but `RUST_LOG` is set to
     // something other than "OFF" (case insensitive).
     if !tracing::enabled!(tracing::Level::ERROR)
-        && let Ok(val) = std::env::var(EnvFilter::DEFAULT_ENV)
+        && let Ok(val) = env::var(EnvFilter::DEFAULT_ENV)
         && !val.eq_ignore_ascii_case(&LevelFilter::OFF.to_string())
     {
         eprintln!(
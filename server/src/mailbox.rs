@@ -0,0 +1,125 @@
+use std::{collections::HashMap, time::SystemTime};
+
+/// The default maximum number of private messages kept for each offline user.
+pub const MAX_OFFLINE_MSGS: usize = 20;
+
+/// The default maximum number of offline users that messages can be queued for at once.
+pub const MAX_MAILBOX_RECIPIENTS: usize = 1000;
+
+/// A private message waiting for its recipient to join.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OfflineMsg {
+    /// The user who sent the message.
+    pub from: String,
+
+    /// The text of the message.
+    pub body: String,
+
+    /// When the message was sent.
+    pub sent_at: SystemTime,
+}
+
+/// Private messages sent to users who were offline, kept until each recipient next joins. Once a
+/// recipient's queue is full, queueing another message for them drops the oldest one. Once
+/// `max_recipients` users have messages waiting, messages for anyone else are refused rather than
+/// evicting queued ones, since recipients can be any unused name.
+pub struct Mailbox {
+    queued: HashMap<String, Vec<OfflineMsg>>,
+    capacity: usize,
+    max_recipients: usize,
+}
+
+impl Mailbox {
+    /// Creates an empty mailbox that keeps up to `capacity` messages per recipient for up to
+    /// `max_recipients` recipients.
+    pub fn new(capacity: usize, max_recipients: usize) -> Self {
+        Self { queued: HashMap::new(), capacity, max_recipients }
+    }
+
+    /// Queues `msg` for `recipient`, returning whether it was queued, which it is not if the
+    /// mailbox is full.
+    pub fn push(&mut self, recipient: &str, msg: OfflineMsg) -> bool {
+        if self.capacity == 0
+            || (!self.queued.contains_key(recipient) && self.queued.len() >= self.max_recipients)
+        {
+            return false;
+        }
+
+        let queue = self.queued.entry(recipient.to_string()).or_default();
+
+        if queue.len() >= self.capacity {
+            queue.drain(..=queue.len() - self.capacity);
+        }
+
+        queue.push(msg);
+        true
+    }
+
+    /// Removes and returns the messages queued for `recipient`, oldest first.
+    pub fn take(&mut self, recipient: &str) -> Vec<OfflineMsg> {
+        self.queued.remove(recipient).unwrap_or_default()
+    }
+}
+
+impl Default for Mailbox {
+    fn default() -> Self { Self::new(MAX_OFFLINE_MSGS, MAX_MAILBOX_RECIPIENTS) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a message from `from` with the given `body`.
+    fn msg(from: &str, body: &str) -> OfflineMsg {
+        OfflineMsg {
+            from: from.to_string(),
+            body: body.to_string(),
+            sent_at: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn messages_are_taken_once_in_order() {
+        let mut mailbox = Mailbox::default();
+
+        assert!(mailbox.push("bob", msg("alice", "first")));
+        assert!(mailbox.push("bob", msg("charlie", "second")));
+        assert!(mailbox.push("dave", msg("alice", "other")));
+
+        assert_eq!(
+            mailbox.take("bob"),
+            [msg("alice", "first"), msg("charlie", "second")]
+        );
+        assert_eq!(mailbox.take("bob"), []);
+        assert_eq!(mailbox.take("dave"), [msg("alice", "other")]);
+    }
+
+    #[test]
+    fn the_oldest_messages_are_dropped_when_full() {
+        let mut mailbox = Mailbox::new(2, MAX_MAILBOX_RECIPIENTS);
+
+        for body in ["1", "2", "3"] {
+            assert!(mailbox.push("bob", msg("alice", body)));
+        }
+
+        assert_eq!(mailbox.take("bob"), [msg("alice", "2"), msg("alice", "3")]);
+    }
+
+    #[test]
+    fn new_recipients_are_refused_when_full() {
+        let mut mailbox = Mailbox::new(MAX_OFFLINE_MSGS, 2);
+
+        assert!(mailbox.push("bob", msg("alice", "1")));
+        assert!(mailbox.push("charlie", msg("alice", "2")));
+        assert!(!mailbox.push("dave", msg("alice", "3")));
+
+        // Recipients who already have messages waiting can still get more
+        assert!(mailbox.push("bob", msg("alice", "4")));
+        assert_eq!(mailbox.take("dave"), []);
+
+        // Collecting messages makes room for someone else
+        mailbox.take("charlie");
+        assert!(mailbox.push("dave", msg("alice", "5")));
+        assert_eq!(mailbox.take("dave"), [msg("alice", "5")]);
+    }
+}
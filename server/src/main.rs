@@ -1,4 +1,7 @@
-/// Sets up the async runtime and logging, then runs the server.
+/// Sets up the async runtime and logging, then runs the server, reloading the TLS certificate on
+/// SIGHUP and draining on SIGUSR1 (on Unix). A second shutdown signal skips waiting for clients to
+/// disconnect. When built with the `plaintext` feature, setting
+/// `PRATTLE_PLAINTEXT` serves raw TCP without TLS instead.
 fn main() -> anyhow::Result<()> {
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -6,11 +9,34 @@ fn main() -> anyhow::Result<()> {
         .block_on(async {
             prattle_server::logger::init_with_default(tracing::level_filters::LevelFilter::INFO)?;
 
-            prattle_server::server::run(
-                &std::env::var("BIND_ADDR").unwrap_or_else(|_| String::from("127.0.0.1:8000")),
+            let bind_addr = std::env::var("BIND_ADDR")
+                .unwrap_or_else(|_| String::from(prattle_server::server::DEFAULT_BIND_ADDR));
+            let config = prattle_server::config::Config::from_env()?;
+
+            #[cfg(feature = "plaintext")]
+            if std::env::var_os("PRATTLE_PLAINTEXT").is_some() {
+                let server =
+                    prattle_server::server::Server::bind_plaintext(&bind_addr, config).await?;
+                prattle_server::drain_signal::listen(server.drain_handle())?;
+                let shutdown = prattle_server::shutdown_signal::listen_handle()?;
+                return server
+                    .run_with_force(shutdown.signal(), shutdown.force_signal())
+                    .await;
+            }
+
+            let server = prattle_server::server::Server::bind(
+                &bind_addr,
                 prattle_server::tls::create_config()?,
-                prattle_server::shutdown_signal::listen()?,
+                config,
             )
-            .await
+            .await?;
+
+            prattle_server::reload_signal::listen(server.reload_handle())?;
+            prattle_server::drain_signal::listen(server.drain_handle())?;
+
+            let shutdown = prattle_server::shutdown_signal::listen_handle()?;
+            server
+                .run_with_force(shutdown.signal(), shutdown.force_signal())
+                .await
         })
 }
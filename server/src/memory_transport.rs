@@ -0,0 +1,465 @@
+//! An in-memory transport that runs client handlers over `tokio::io::duplex` pipes instead of TCP
+//! and TLS, so that commands and protocol behavior can be tested without ports or certificates.
+//!
+//! Only available in tests or with the `memory-transport` feature.
+
+use crate::{
+    config::Config,
+    server::{client_span, serve_stream},
+    state::SharedState,
+};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+use tokio::{
+    io::{self, DuplexStream},
+    sync::broadcast,
+};
+use tracing::{Instrument, warn};
+
+/// The buffer size of each direction of an in-memory connection.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// A server without a listener, whose clients connect through in-memory pipes.
+pub struct MemoryServer {
+    state: Arc<SharedState>,
+    shutdown_tx: broadcast::Sender<()>,
+}
+
+impl MemoryServer {
+    /// Creates a server with the options in `config`.
+    #[must_use]
+    pub fn new(config: Config) -> Self {
+        let (shutdown_tx, _) = broadcast::channel(1);
+
+        Self { state: Arc::new(SharedState::new(config)), shutdown_tx }
+    }
+
+    /// Connects a new client, returning the client's end of the pipe. The client is handled as if
+    /// they had connected over TCP and completed the TLS handshake.
+    ///
+    /// Must be called from within a Tokio runtime, because the client's handler is spawned as a
+    /// task.
+    #[must_use]
+    pub fn connect(&self) -> DuplexStream {
+        let (client_end, server_end) = io::duplex(PIPE_CAPACITY);
+
+        let client_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+
+        tokio::spawn(
+            serve_stream(
+                server_end,
+                client_addr,
+                Arc::clone(&self.state),
+                self.shutdown_tx.subscribe(),
+            )
+            .instrument(client_span(client_addr)),
+        );
+
+        client_end
+    }
+
+    /// Tells all connected clients that the server is shutting down.
+    pub fn shutdown(&self) {
+        if self.shutdown_tx.send(()).is_err() {
+            warn!("No clients connected to broadcast shutdown to");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{CLIENT_QUEUE_CAP, OverflowPolicy},
+        event::ChatEvent,
+    };
+    use anyhow::{Context, Result};
+    use std::{
+        pin::Pin,
+        sync::atomic::{AtomicBool, Ordering::SeqCst},
+        task::{Context as TaskContext, Poll},
+        time::Duration,
+    };
+    use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf};
+
+    /// The amount of time to wait when reading from the server.
+    const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Reads a line from `client` and asserts that it contains `expected`.
+    async fn read_line_assert_contains(
+        client: &mut BufReader<DuplexStream>,
+        expected: &str,
+    ) -> Result<String> {
+        let mut line = String::new();
+
+        tokio::time::timeout(READ_TIMEOUT, client.read_line(&mut line))
+            .await
+            .context("Timeout reading line")??;
+
+        assert!(
+            line.contains(expected),
+            "Expected \"{expected}\", got: \"{line}\""
+        );
+        Ok(line)
+    }
+
+    /// Connects to `server` and chooses `username`, reading the welcome and join lines.
+    async fn join(server: &MemoryServer, username: &str) -> Result<BufReader<DuplexStream>> {
+        let mut client = BufReader::new(server.connect());
+
+        read_line_assert_contains(&mut client, "Choose a username").await?;
+        client.write_all(format!("{username}\n").as_bytes()).await?;
+        read_line_assert_contains(&mut client, "welcome").await?;
+        read_line_assert_contains(&mut client, &format!("\x05USERNAME {username}\n")).await?;
+        read_line_assert_contains(&mut client, &format!("{username} joined")).await?;
+
+        Ok(client)
+    }
+
+    /// Joins `server` as Alice and Bob, then broadcasts numbered lines while Alice reads each one
+    /// and Bob reads nothing, until Bob's pipe and then his queue have overflowed. Returns both
+    /// clients along with the number of lines broadcast.
+    async fn flood_slow_reader(
+        server: &MemoryServer,
+    ) -> Result<(BufReader<DuplexStream>, BufReader<DuplexStream>, usize)> {
+        let mut alice = join(server, "alice").await?;
+        let bob = join(server, "bob").await?;
+        read_line_assert_contains(&mut alice, "bob joined").await?;
+
+        let filler = filler();
+        let count = PIPE_CAPACITY / filler.len() + CLIENT_QUEUE_CAP * 2;
+
+        for i in 0..count {
+            server
+                .state
+                .fanout
+                .send(&ChatEvent::notice(format!("{i} {filler}\n")));
+            let line = read_line_assert_contains(&mut alice, &filler).await?;
+            assert_eq!(line, format!("{i} {filler}\n"));
+        }
+
+        Ok((alice, bob, count))
+    }
+
+    /// Reads the numbered lines sent by `flood_slow_reader` until `last`, returning their numbers.
+    async fn read_flood(client: &mut BufReader<DuplexStream>, last: usize) -> Result<Vec<usize>> {
+        let filler = filler();
+        let mut numbers = Vec::new();
+
+        while numbers.last() != Some(&last) {
+            let line = read_line_assert_contains(client, &filler).await?;
+            numbers.push(
+                line.split_once(' ')
+                    .and_then(|(number, _)| number.parse().ok())
+                    .context("expected a numbered line")?,
+            );
+        }
+
+        Ok(numbers)
+    }
+
+    /// The text following the number on each line sent by `flood_slow_reader`.
+    fn filler() -> String { "x".repeat(1000) }
+
+    /// The server's end of a pipe whose writes start failing once `fail_writes` is set, while reads
+    /// keep working.
+    struct FailingStream {
+        inner: DuplexStream,
+        fail_writes: Arc<AtomicBool>,
+    }
+
+    impl AsyncRead for FailingStream {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut TaskContext<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for FailingStream {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut TaskContext<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            if self.fail_writes.load(SeqCst) {
+                Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+            } else {
+                Pin::new(&mut self.inner).poll_write(cx, buf)
+            }
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            mut self: Pin<&mut Self>,
+            cx: &mut TaskContext<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    #[test]
+    fn clients_can_join_and_chat_over_pipes() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let server = MemoryServer::new(Config::default());
+
+                let mut alice = join(&server, "alice").await?;
+                let mut bob = join(&server, "bob").await?;
+                read_line_assert_contains(&mut alice, "bob joined").await?;
+
+                alice.write_all(b"Hello over a pipe!\n").await?;
+                read_line_assert_contains(&mut bob, "alice: Hello over a pipe!").await?;
+                read_line_assert_contains(&mut alice, "alice: Hello over a pipe!").await?;
+
+                bob.write_all(b"/who\n").await?;
+                let who = read_line_assert_contains(&mut bob, "Currently online:").await?;
+                assert!(who.contains("alice") && who.contains("bob"), "got: {who}");
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn shutdown_is_sent_over_pipes() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let server = MemoryServer::new(Config::default());
+                let mut alice = join(&server, "alice").await?;
+
+                server.shutdown();
+                read_line_assert_contains(&mut alice, "Server is shutting down").await?;
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn shutdown_does_not_broadcast_leave_notices() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let server = MemoryServer::new(Config::default());
+                let mut alice = join(&server, "alice").await?;
+                let observer = server.state.fanout.subscribe();
+
+                server.shutdown();
+                read_line_assert_contains(&mut alice, "Server is shutting down").await?;
+                drop(alice);
+
+                // Once Alice has been removed, her handler would already have broadcast a notice
+                tokio::time::timeout(READ_TIMEOUT, async {
+                    while server.state.users.lock().await.contains_key("alice") {
+                        tokio::task::yield_now().await;
+                    }
+                })
+                .await?;
+
+                assert!(observer.try_recv().is_err());
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn messages_sent_just_before_shutdown_are_still_delivered() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let server = MemoryServer::new(Config::default());
+                let mut alice = join(&server, "alice").await?;
+
+                // On a single-threaded runtime, the handler can't run between these calls, so the
+                // messages are all still queued when it sees the shutdown signal
+                for i in 0..10 {
+                    server.state.fanout.send(&ChatEvent::from_user(
+                        "bob",
+                        format!("bob: final message {i}\n"),
+                    ));
+                }
+                server.shutdown();
+
+                for i in 0..10 {
+                    read_line_assert_contains(&mut alice, &format!("bob: final message {i}"))
+                        .await?;
+                }
+                read_line_assert_contains(&mut alice, "Server is shutting down").await?;
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn slow_readers_are_disconnected_while_fast_readers_stay_in_sync() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let server = MemoryServer::new(Config {
+                    overflow_policy: OverflowPolicy::Disconnect,
+                    ..Config::default()
+                });
+                let (mut alice, mut bob, _) = flood_slow_reader(&server).await?;
+                let filler = filler();
+
+                // When Bob reads again, he gets the messages that fit in his pipe in order, and
+                // then finds that he was disconnected instead of silently missing the rest
+                for i in 0.. {
+                    let line = read_line_assert_contains(&mut bob, "").await?;
+
+                    if line.contains("too slow") {
+                        assert_eq!(line, "You were too slow and were disconnected\n");
+                        break;
+                    }
+
+                    assert_eq!(line, format!("{i} {filler}\n"));
+                }
+
+                drop(bob);
+                read_line_assert_contains(&mut alice, "* bob lost connection").await?;
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn slow_readers_skip_to_the_most_recent_messages_by_default() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let server = MemoryServer::new(Config::default());
+                let (mut alice, mut bob, count) = flood_slow_reader(&server).await?;
+
+                // Bob gets what fit in his pipe, then skips ahead to the newest messages
+                let numbers = read_flood(&mut bob, count - 1).await?;
+                assert_eq!(numbers.first(), Some(&0));
+                assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+                assert!(numbers.len() < count);
+                assert!(numbers.ends_with(&(count - CLIENT_QUEUE_CAP..count).collect::<Vec<_>>()));
+
+                // And he is still connected
+                alice.write_all(b"still there?\n").await?;
+                read_line_assert_contains(&mut bob, "alice: still there?").await?;
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn slow_readers_can_keep_the_oldest_messages_instead() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let server = MemoryServer::new(Config {
+                    overflow_policy: OverflowPolicy::DropNewest,
+                    ..Config::default()
+                });
+                let (mut alice, mut bob, count) = flood_slow_reader(&server).await?;
+
+                // Bob gets an unbroken run of the earliest messages (until reading times out),
+                // missing the rest
+                let filler = filler();
+                let mut numbers = Vec::new();
+
+                while let Ok(line) = read_line_assert_contains(&mut bob, &filler).await {
+                    numbers.push(
+                        line.split_once(' ')
+                            .map(|(number, _)| number.parse::<usize>()),
+                    );
+                }
+
+                assert!(numbers.len() < count);
+                assert!(
+                    numbers
+                        .into_iter()
+                        .enumerate()
+                        .all(|(i, number)| number == Some(Ok(i)))
+                );
+
+                // Once he has caught up, new messages reach him again
+                alice.write_all(b"still there?\n").await?;
+                read_line_assert_contains(&mut bob, "alice: still there?").await?;
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn write_failures_still_remove_the_user_and_announce_their_departure() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let server = MemoryServer::new(Config::default());
+                let mut alice = join(&server, "alice").await?;
+
+                let fail_writes = Arc::new(AtomicBool::new(false));
+                let (client_end, server_end) = io::duplex(PIPE_CAPACITY);
+                tokio::spawn(serve_stream(
+                    FailingStream { inner: server_end, fail_writes: Arc::clone(&fail_writes) },
+                    SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+                    Arc::clone(&server.state),
+                    server.shutdown_tx.subscribe(),
+                ));
+
+                let mut bob = BufReader::new(client_end);
+                read_line_assert_contains(&mut bob, "Choose a username").await?;
+                bob.write_all(b"bob\n").await?;
+                read_line_assert_contains(&mut bob, "welcome").await?;
+                read_line_assert_contains(&mut bob, "\x05USERNAME bob\n").await?;
+                read_line_assert_contains(&mut bob, "bob joined").await?;
+                read_line_assert_contains(&mut alice, "bob joined").await?;
+
+                // Delivering Alice's message to Bob fails partway through the session
+                fail_writes.store(true, SeqCst);
+                alice.write_all(b"are you there?\n").await?;
+                read_line_assert_contains(&mut alice, "alice: are you there?").await?;
+
+                read_line_assert_contains(&mut alice, "* bob lost connection").await?;
+                assert!(!server.state.users.lock().await.contains_key("bob"));
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn clients_that_stop_reading_during_username_selection_time_out() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let server = MemoryServer::new(Config {
+                    write_timeout: Duration::from_millis(200),
+                    max_username_attempts: None,
+                    ..Config::default()
+                });
+                let mut client = server.connect();
+
+                // Enough rejected usernames that the rejections fill the pipe, without ever
+                // reading them
+                let count = PIPE_CAPACITY / "Username cannot be empty".len() + 1;
+                client.write_all("\n".repeat(count).as_bytes()).await?;
+
+                // The handler gives up on writing and closes its end of the pipe
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                assert!(client.write_all(b"alice\n").await.is_err());
+
+                Ok(())
+            })
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{
+    http::{self, Response},
+    state::SharedState,
+};
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering::SeqCst},
+};
+use tokio::net::TcpListener;
+
+/// Cumulative counters describing the server's activity since it started or since they were last
+/// reset with `/stats-reset`. Live values such as the number of online users are read directly
+/// from the shared state instead, so they are unaffected by resets.
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    /// The number of connections accepted past the TLS handshake and the pending connection cap.
+    pub connections: AtomicU64,
+
+    /// The number of messages and actions broadcast by users.
+    pub messages: AtomicU64,
+
+    /// The combined size in bytes of the messages and actions broadcast by users.
+    pub message_bytes: AtomicU64,
+}
+
+impl ServerMetrics {
+    /// Records a chat line of `len` bytes broadcast by a user.
+    pub fn record_message(&self, len: usize) {
+        self.messages.fetch_add(1, SeqCst);
+        self.message_bytes
+            .fetch_add(u64::try_from(len).unwrap_or(u64::MAX), SeqCst);
+    }
+
+    /// Sets every counter back to zero.
+    pub fn reset(&self) {
+        self.connections.store(0, SeqCst);
+        self.messages.store(0, SeqCst);
+        self.message_bytes.store(0, SeqCst);
+    }
+
+    /// Creates a human-readable summary of the counters along with the number of users currently
+    /// `online`.
+    pub fn summary(&self, online: usize) -> String {
+        format!(
+            "Online: {online}, connections: {}, messages: {}, message bytes: {}\n",
+            self.connections.load(SeqCst),
+            self.messages.load(SeqCst),
+            self.message_bytes.load(SeqCst),
+        )
+    }
+
+    /// Renders the counters along with the number of users currently `online` in the Prometheus
+    /// text exposition format.
+    pub fn prometheus(&self, online: usize) -> String {
+        format!(
+            "# HELP prattle_connected_users The number of users currently online.\n\
+            # TYPE prattle_connected_users gauge\n\
+            prattle_connected_users {online}\n\
+            # HELP prattle_connections_total The number of connections accepted.\n\
+            # TYPE prattle_connections_total counter\n\
+            prattle_connections_total {}\n\
+            # HELP prattle_messages_total The number of messages and actions broadcast by users.\n\
+            # TYPE prattle_messages_total counter\n\
+            prattle_messages_total {}\n\
+            # HELP prattle_message_bytes_total The size of the messages and actions broadcast by \
+            users.\n\
+            # TYPE prattle_message_bytes_total counter\n\
+            prattle_message_bytes_total {}\n",
+            self.connections.load(SeqCst),
+            self.messages.load(SeqCst),
+            self.message_bytes.load(SeqCst),
+        )
+    }
+}
+
+/// Serves Prometheus metrics over plain HTTP on `listener` until the task is aborted. Only
+/// `GET /metrics` is supported.
+pub async fn serve(listener: TcpListener, state: Arc<SharedState>) {
+    http::serve(listener, state, "metrics", |state, path| async move {
+        if path != "/metrics" {
+            return None;
+        }
+
+        let online = state.users.lock().await.len();
+        Some(Response {
+            content_type: "text/plain; version=0.0.4",
+            body: state.metrics.prometheus(online),
+        })
+    })
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_zeroes_every_counter() {
+        let metrics = ServerMetrics::default();
+        metrics.connections.fetch_add(3, SeqCst);
+        metrics.record_message(5);
+        metrics.record_message(7);
+
+        assert_eq!(
+            metrics.summary(2),
+            "Online: 2, connections: 3, messages: 2, message bytes: 12\n"
+        );
+
+        metrics.reset();
+
+        assert_eq!(
+            metrics.summary(2),
+            "Online: 2, connections: 0, messages: 0, message bytes: 0\n"
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use rand::{Rng, distr::Alphanumeric};
+use std::{collections::HashMap, time::Instant};
+
+/// The default maximum number of reconnect tokens kept for users who have left.
+pub const MAX_RECONNECT_TOKENS: usize = 1000;
+
+/// The number of characters in a reconnect token.
+const TOKEN_LEN: usize = 24;
+
+/// The username a reconnect token restores, and when it stops working.
+struct Ticket {
+    username: String,
+
+    /// `None` while the user is still online, since the window only starts once they leave.
+    expires_at: Option<Instant>,
+}
+
+/// Opaque tokens that let a client skip the username prompt by presenting `/token <token>` instead,
+/// restoring the username the token was issued for. Each user is issued a token when they join,
+/// which keeps working until a configured window after they leave and can only be used once.
+///
+/// Once `capacity` tokens are kept for users who have left, issuing a new one forgets the one
+/// closest to expiring.
+pub struct ReconnectTokens {
+    tickets: HashMap<String, Ticket>,
+    capacity: usize,
+}
+
+impl ReconnectTokens {
+    /// Creates an empty set of tokens that keeps up to `capacity` tokens for users who have left.
+    pub fn new(capacity: usize) -> Self { Self { tickets: HashMap::new(), capacity } }
+
+    /// Issues a new token for `username`, who is online, forgetting any tokens that have expired
+    /// by `now`.
+    pub fn issue(&mut self, username: &str, now: Instant) -> String {
+        self.tickets
+            .retain(|_, ticket| ticket.expires_at.is_none_or(|expires_at| expires_at > now));
+
+        let departed = self
+            .tickets
+            .values()
+            .filter(|ticket| ticket.expires_at.is_some())
+            .count();
+
+        if departed >= self.capacity
+            && let Some(soonest) = self
+                .tickets
+                .iter()
+                .filter_map(|(token, ticket)| Some((token, ticket.expires_at?)))
+                .min_by_key(|(_, expires_at)| *expires_at)
+                .map(|(token, _)| token.clone())
+        {
+            self.tickets.remove(&soonest);
+        }
+
+        let token = rand::rng()
+            .sample_iter(Alphanumeric)
+            .take(TOKEN_LEN)
+            .map(char::from)
+            .collect::<String>();
+
+        self.tickets.insert(
+            token.clone(),
+            Ticket { username: username.to_string(), expires_at: None },
+        );
+
+        token
+    }
+
+    /// Starts the countdown for `token` once its user has left, so that it stops working at
+    /// `expires_at`.
+    pub fn expire_at(&mut self, token: &str, expires_at: Instant) {
+        if let Some(ticket) = self.tickets.get_mut(token) {
+            ticket.expires_at = Some(expires_at);
+        }
+    }
+
+    /// Forgets `token` immediately (e.g., when its user's session is taken over).
+    pub fn revoke(&mut self, token: &str) { self.tickets.remove(token); }
+
+    /// Returns the username `token` restores without using it up, unless it is unknown or expired
+    /// by `now`.
+    pub fn lookup(&self, token: &str, now: Instant) -> Option<String> {
+        self.tickets
+            .get(token)
+            .filter(|ticket| ticket.expires_at.is_none_or(|expires_at| expires_at > now))
+            .map(|ticket| ticket.username.clone())
+    }
+
+    /// Uses up `token`, returning the username it restores unless it is unknown or expired by
+    /// `now`.
+    pub fn redeem(&mut self, token: &str, now: Instant) -> Option<String> {
+        self.tickets
+            .remove(token)
+            .filter(|ticket| ticket.expires_at.is_none_or(|expires_at| expires_at > now))
+            .map(|ticket| ticket.username)
+    }
+}
+
+impl Default for ReconnectTokens {
+    fn default() -> Self { Self::new(MAX_RECONNECT_TOKENS) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn tokens_restore_their_username_once() {
+        let mut tokens = ReconnectTokens::default();
+        let start = Instant::now();
+
+        let token = tokens.issue("alice", start);
+        assert_eq!(token.len(), TOKEN_LEN);
+        assert_ne!(tokens.issue("alice", start), token);
+
+        tokens.expire_at(&token, start + Duration::from_mins(1));
+
+        // Looking a token up doesn't use it
+        for _ in 0..2 {
+            assert_eq!(
+                tokens.lookup(&token, start + Duration::from_secs(59)),
+                Some(String::from("alice"))
+            );
+        }
+        assert_eq!(tokens.lookup(&token, start + Duration::from_mins(1)), None);
+
+        assert_eq!(
+            tokens.redeem(&token, start + Duration::from_secs(59)),
+            Some(String::from("alice"))
+        );
+        assert_eq!(tokens.redeem(&token, start), None);
+    }
+
+    #[test]
+    fn expired_unknown_and_revoked_tokens_are_rejected() {
+        let mut tokens = ReconnectTokens::default();
+        let start = Instant::now();
+
+        let expired = tokens.issue("alice", start);
+        tokens.expire_at(&expired, start + Duration::from_mins(1));
+        assert_eq!(
+            tokens.redeem(&expired, start + Duration::from_mins(1)),
+            None
+        );
+
+        let revoked = tokens.issue("bob", start);
+        tokens.revoke(&revoked);
+        assert_eq!(tokens.redeem(&revoked, start), None);
+
+        assert_eq!(tokens.redeem("not-a-token", start), None);
+    }
+
+    #[test]
+    fn tokens_for_online_users_do_not_expire() {
+        let mut tokens = ReconnectTokens::default();
+        let start = Instant::now();
+
+        let token = tokens.issue("alice", start);
+        tokens.issue("bob", start + Duration::from_hours(24));
+        assert_eq!(
+            tokens.redeem(&token, start + Duration::from_hours(24)),
+            Some(String::from("alice"))
+        );
+    }
+
+    #[test]
+    fn the_token_closest_to_expiring_is_forgotten_when_full() {
+        let mut tokens = ReconnectTokens::new(2);
+        let start = Instant::now();
+
+        let first = tokens.issue("alice", start);
+        let second = tokens.issue("bob", start);
+        tokens.expire_at(&second, start + Duration::from_secs(10));
+        tokens.expire_at(&first, start + Duration::from_secs(20));
+
+        let online = tokens.issue("charlie", start);
+        tokens.issue("dave", start);
+
+        assert_eq!(tokens.redeem(&second, start), None);
+        assert_eq!(tokens.redeem(&first, start), Some(String::from("alice")));
+        assert_eq!(tokens.redeem(&online, start), Some(String::from("charlie")));
+    }
+}
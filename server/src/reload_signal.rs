@@ -0,0 +1,29 @@
+use crate::signal_handle::{self, Signal, SignalHandle};
+use anyhow::Result;
+
+/// A handle for telling a running server to reload its TLS certificate and private key from file,
+/// either programmatically or (with `listen`) on SIGHUP.
+///
+/// Reloading only affects new handshakes, so existing connections are not interrupted. If the
+/// files cannot be loaded, the server logs the error and keeps using the previous certificate.
+pub type ReloadHandle = SignalHandle;
+
+/// Creates a Unix signal handler that triggers `handle` every time SIGHUP is received. Does
+/// nothing on other platforms, where reloading is still possible programmatically with
+/// `ReloadHandle::trigger`.
+///
+/// # Errors
+///
+/// Returns `Err` for errors installing the signal handler.
+pub fn listen(handle: ReloadHandle) -> Result<()> {
+    signal_handle::listen(
+        handle,
+        Signal {
+            name: "SIGHUP",
+            #[cfg(unix)]
+            kind: tokio::signal::unix::SignalKind::hangup(),
+            action: "reloading TLS certificate",
+            feature: "certificate reloading",
+        },
+    )
+}
@@ -0,0 +1,126 @@
+use crate::line_reader::MAX_LINE_LENGTH;
+
+/// The maximum number of bytes in the sanitized text of a message or action, which is the same as
+/// the limit on lines read from clients.
+pub const MAX_TEXT_LENGTH: usize = MAX_LINE_LENGTH;
+
+/// The escape character that begins terminal escape sequences.
+const ESC: char = '\x1b';
+
+/// The bell character, which can terminate operating system commands.
+const BEL: char = '\x07';
+
+/// Removes terminal escape sequences and other control characters (including `\r` and `\n`) from
+/// text sent by a client, so that it cannot forge extra lines or manipulate other users' terminals
+/// when broadcast. Tabs are replaced with spaces, and the result is trimmed.
+///
+/// Returns `None` if the sanitized text is longer than `MAX_TEXT_LENGTH`.
+pub fn sanitize(text: &str) -> Option<String> {
+    let mut sanitized = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ESC => skip_escape_sequence(&mut chars),
+            '\t' => sanitized.push(' '),
+            c if c.is_control() => {}
+            c => sanitized.push(c),
+        }
+    }
+
+    let sanitized = sanitized.trim();
+
+    (sanitized.len() <= MAX_TEXT_LENGTH).then(|| sanitized.to_string())
+}
+
+/// Skips the rest of an escape sequence after `ESC`, i.e., a control sequence (`ESC [` followed by
+/// parameters and a final byte), an operating system command (`ESC ]` terminated by `BEL` or
+/// `ESC \`), or a single character for any other sequence.
+fn skip_escape_sequence(chars: &mut std::str::Chars) {
+    match chars.next() {
+        Some('[') => {
+            for c in chars.by_ref() {
+                if ('\x40'..='\x7e').contains(&c) {
+                    break;
+                }
+            }
+        }
+
+        Some(']') => {
+            while let Some(c) = chars.next() {
+                if c == BEL || (c == ESC && chars.next().is_some()) {
+                    break;
+                }
+            }
+        }
+
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_is_unchanged() {
+        for text in [
+            "hello",
+            "waves hello",
+            "こんにちは 👋",
+            "¯\\_(ツ)_/¯",
+            "[1] ]x[",
+        ] {
+            assert_eq!(sanitize(text).as_deref(), Some(text));
+        }
+    }
+
+    #[test]
+    fn control_sequences_are_stripped() {
+        for (text, expected) in [
+            ("\x1b[31mred\x1b[0m", "red"),
+            ("\x1b[2J\x1b[Hcleared", "cleared"),
+            ("\x1b[1;31;40mbold\x1b[m text", "bold text"),
+            ("\x1b]0;fake title\x07title", "title"),
+            ("\x1b]8;;http://example.com\x1b\\link\x1b]8;;\x1b\\", "link"),
+            ("\x1bcreset", "reset"),
+            ("unterminated\x1b[", "unterminated"),
+            ("unterminated\x1b]0;title", "unterminated"),
+        ] {
+            assert_eq!(sanitize(text).as_deref(), Some(expected), "for {text:?}");
+        }
+    }
+
+    #[test]
+    fn newline_injection_is_stripped() {
+        for (text, expected) in [
+            ("waves\nbob: fake", "wavesbob: fake"),
+            ("waves\r\nbob: fake", "wavesbob: fake"),
+            ("waves\rbob: fake", "wavesbob: fake"),
+            ("waves\r\x1b[2Kbob: fake", "wavesbob: fake"),
+            ("waves\u{85}bob: fake", "wavesbob: fake"),
+        ] {
+            assert_eq!(sanitize(text).as_deref(), Some(expected), "for {text:?}");
+        }
+    }
+
+    #[test]
+    fn other_control_characters_are_stripped_and_tabs_become_spaces() {
+        assert_eq!(sanitize("a\x07b\x08c\x00d").as_deref(), Some("abcd"));
+        assert_eq!(sanitize("a\tb").as_deref(), Some("a b"));
+        assert_eq!(sanitize("\x1b[31m \t\r").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn text_longer_than_the_limit_is_rejected() {
+        let max = "a".repeat(MAX_TEXT_LENGTH);
+        assert_eq!(sanitize(&max).as_deref(), Some(max.as_str()));
+        assert_eq!(sanitize(&format!("{max}a")), None);
+
+        // The limit applies after stripping
+        assert_eq!(
+            sanitize(&format!("\x1b[31m{max}\x1b[0m")).as_deref(),
+            Some(max.as_str())
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::{collections::HashMap, time::Instant};
+
+/// The default maximum number of users whose last activity is remembered.
+pub const MAX_SEEN_USERS: usize = 1000;
+
+/// When each user was last active (sending a message or action, or leaving), kept across their
+/// sessions for the server's lifetime. Once full, recording a new user forgets the one that has
+/// been inactive the longest.
+pub struct SeenLog {
+    last_seen: HashMap<String, Instant>,
+    capacity: usize,
+}
+
+impl SeenLog {
+    /// Creates an empty log that remembers up to `capacity` users.
+    pub fn new(capacity: usize) -> Self { Self { last_seen: HashMap::new(), capacity } }
+
+    /// Records that `username` was active at `now`.
+    pub fn record(&mut self, username: &str, now: Instant) {
+        if let Some(last_seen) = self.last_seen.get_mut(username) {
+            *last_seen = now;
+            return;
+        }
+
+        if self.last_seen.len() >= self.capacity
+            && let Some(stalest) = self
+                .last_seen
+                .iter()
+                .min_by_key(|(_, last_seen)| **last_seen)
+                .map(|(username, _)| username.clone())
+        {
+            self.last_seen.remove(&stalest);
+        }
+
+        if self.capacity > 0 {
+            self.last_seen.insert(username.to_string(), now);
+        }
+    }
+
+    /// When `username` was last active, if they are remembered.
+    pub fn get(&self, username: &str) -> Option<Instant> { self.last_seen.get(username).copied() }
+}
+
+impl Default for SeenLog {
+    fn default() -> Self { Self::new(MAX_SEEN_USERS) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn records_the_latest_activity() {
+        let mut log = SeenLog::default();
+        let start = Instant::now();
+
+        log.record("alice", start);
+        log.record("alice", start + Duration::from_secs(5));
+
+        assert_eq!(log.get("alice"), Some(start + Duration::from_secs(5)));
+        assert_eq!(log.get("bob"), None);
+    }
+
+    #[test]
+    fn forgets_the_least_recently_active_user_when_full() {
+        let mut log = SeenLog::new(2);
+        let start = Instant::now();
+
+        log.record("alice", start);
+        log.record("bob", start + Duration::from_secs(1));
+
+        // Alice becoming active again makes Bob the stalest
+        log.record("alice", start + Duration::from_secs(2));
+        log.record("charlie", start + Duration::from_secs(3));
+
+        assert!(log.get("alice").is_some());
+        assert_eq!(log.get("bob"), None);
+        assert!(log.get("charlie").is_some());
+    }
+}
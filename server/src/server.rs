@@ -1,25 +1,35 @@
-use crate::client;
-use anyhow::Result;
+use crate::{
+    client::{self, Departure, Session},
+    config::Config,
+    connect_throttle::ConnectThrottle,
+    drain_signal::DrainHandle,
+    event::ChatEvent,
+    health, metrics,
+    reload_signal::ReloadHandle,
+    state::SharedState,
+    tls,
+    webhook::{self, Endpoint},
+};
+use anyhow::{Context, Result, bail};
+use socket2::{SockRef, TcpKeepalive};
 use std::{
-    collections::HashSet,
-    sync::{
-        Arc,
-        atomic::{AtomicUsize, Ordering::SeqCst},
-    },
+    future, io,
+    net::SocketAddr,
+    sync::{Arc, OnceLock, atomic::Ordering::SeqCst},
     time::{Duration, Instant},
 };
 use tokio::{
-    net::TcpListener,
-    sync::{Mutex, broadcast},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    pin,
+    sync::broadcast,
+    task::{JoinHandle, JoinSet},
 };
 use tokio_rustls::{TlsAcceptor, rustls::ServerConfig};
-use tracing::{error, info, warn};
-
-/// The number of messages that can be held in the channel.
-const CHANNEL_CAP: usize = 100;
+use tracing::{Instrument, Span, error, field, info, info_span, warn};
 
-/// The time to wait for all clients to disconnect during graceful shutdown.
-pub(crate) const GLOBAL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// The address `ServerBuilder` listens on unless another is set.
+pub const DEFAULT_BIND_ADDR: &str = "127.0.0.1:8000";
 
 /// Runs the chat server on `bind_addr` using TLS as configured with `tls_config` until receiving
 /// `shutdown_signal`.
@@ -40,94 +50,965 @@ pub async fn run(
     tls_config: Arc<ServerConfig>,
     shutdown_signal: impl Future<Output = ()>,
 ) -> Result<()> {
-    let listener = TcpListener::bind(bind_addr).await?;
-    let tls_acceptor = TlsAcceptor::from(tls_config);
-    info!("Listening on {bind_addr}");
-
-    let (sender, _) = broadcast::channel(CHANNEL_CAP);
-    let (shutdown_tx, _) = broadcast::channel(1);
-    // All client connections, regardless of whether they have provided a username
-    let active_clients = Arc::new(AtomicUsize::new(0));
-    // The set of usernames provided by active clients
-    let users = Arc::new(Mutex::new(HashSet::new()));
-
-    tokio::pin!(shutdown_signal);
-
-    if loop {
-        tokio::select! {
-            conn_result = listener.accept() => {
-                let (socket, client_addr) = conn_result?;
-                info!("New connection from {client_addr}");
-
-                let acceptor = tls_acceptor.clone();
-                let tx = sender.clone();
-                let rx = tx.subscribe();
-                let users_clone = Arc::clone(&users);
-                let active_clients_clone = Arc::clone(&active_clients);
-                let shutdown_rx = shutdown_tx.subscribe();
-
-                tokio::spawn(async move {
-                    match acceptor.accept(socket).await {
-                        Err(e) => error!("TLS handshake failed for {client_addr}: {e}"),
-
-                        Ok(tls_stream) => {
-                            info!("TLS handshake completed for {client_addr}");
-
-                            active_clients_clone.fetch_add(1, SeqCst);
-
-                            if let Err(e) =
-                                client::handle_client(tls_stream, tx, rx, shutdown_rx, users_clone)
-                                    .await
-                            {
-                                error!("Error handling client {client_addr}: {e}");
-                            } else {
-                                info!("Client {client_addr} disconnected");
-                            }
-
-                            active_clients_clone.fetch_sub(1, SeqCst);
-                        }
-                    }
-                });
+    run_with_config(bind_addr, tls_config, Config::default(), shutdown_signal).await
+}
+
+/// Runs the chat server the same way as `run`, but with the options in `config` instead of the
+/// defaults.
+///
+/// # Errors
+///
+/// Returns `Err` for any errors with the overall operation of the server, but logs and does not
+/// return errors from handling specific clients.
+pub async fn run_with_config(
+    bind_addr: &str,
+    tls_config: Arc<ServerConfig>,
+    config: Config,
+    shutdown_signal: impl Future<Output = ()>,
+) -> Result<()> {
+    ServerBuilder::new()
+        .bind_addr(bind_addr)
+        .tls_config(tls_config)
+        .config(config)
+        .run(shutdown_signal)
+        .await
+}
+
+/// Runs the chat server on `bind_addr` without TLS until receiving `shutdown_signal`, as described
+/// for `Server::bind_plaintext`.
+///
+/// # Errors
+///
+/// Returns `Err` for any errors with the overall operation of the server, but logs and does not
+/// return errors from handling specific clients.
+#[cfg(feature = "plaintext")]
+pub async fn run_plaintext(
+    bind_addr: &str,
+    shutdown_signal: impl Future<Output = ()>,
+) -> Result<()> {
+    Server::bind_plaintext(bind_addr, Config::default())
+        .await?
+        .run(shutdown_signal)
+        .await
+}
+
+/// Configures a server entirely in code, as an alternative to passing a `Config` (e.g., from
+/// `Config::from_env`) to `Server::bind`. Options that are not set keep their defaults:
+///
+/// ```no_run
+/// # async fn example(shutdown_signal: impl Future<Output = ()>) -> anyhow::Result<()> {
+/// use prattle_server::server::ServerBuilder;
+/// use std::time::Duration;
+///
+/// ServerBuilder::new()
+///     .bind_addr("0.0.0.0:8000")
+///     .max_clients(100)
+///     .shutdown_timeout(Duration::from_secs(10))
+///     .banner("Welcome to the Prattle server!")
+///     .run(shutdown_signal)
+///     .await
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+#[must_use]
+pub struct ServerBuilder {
+    bind_addr: String,
+    tls_config: Option<Arc<ServerConfig>>,
+    config: Config,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self {
+            bind_addr: String::from(DEFAULT_BIND_ADDR),
+            tls_config: None,
+            config: Config::default(),
+        }
+    }
+}
+
+impl ServerBuilder {
+    /// Creates a builder with the default options, binding to `DEFAULT_BIND_ADDR` and using the
+    /// TLS configuration from `tls::create_config`.
+    pub fn new() -> Self { Self::default() }
+
+    /// Sets the address to listen on.
+    pub fn bind_addr(mut self, bind_addr: impl Into<String>) -> Self {
+        self.bind_addr = bind_addr.into();
+        self
+    }
+
+    /// Sets the TLS configuration to use instead of the one from `tls::create_config`.
+    pub fn tls_config(mut self, tls_config: Arc<ServerConfig>) -> Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
+
+    /// Replaces all of the options with those in `config`, including any set with this builder
+    /// so far. Useful as a starting point for the other setters.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the number of broadcast events that can be queued for each client before it is
+    /// considered too slow and the overflow policy applies.
+    pub const fn channel_cap(mut self, channel_cap: usize) -> Self {
+        self.config.client_queue_cap = channel_cap;
+        self
+    }
+
+    /// Sets the maximum number of simultaneous connections.
+    pub const fn max_clients(mut self, max_clients: usize) -> Self {
+        self.config.max_clients = Some(max_clients);
+        self
+    }
+
+    /// Sets the time to wait for all clients to disconnect during graceful shutdown.
+    pub const fn shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.config.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    /// Sets the banner shown to clients as soon as they connect, before they are prompted for the
+    /// password (if there is one) or a username.
+    pub fn banner(mut self, banner: impl Into<String>) -> Self {
+        self.config.messages.banner = Some(banner.into());
+        self
+    }
+
+    /// Binds the server with the configured options without starting it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the TLS configuration cannot be created or binding fails, as described
+    /// for `Server::bind`.
+    pub async fn bind(self) -> Result<Server> {
+        let tls_config = match self.tls_config {
+            Some(tls_config) => tls_config,
+            None => tls::create_config()?,
+        };
+
+        Server::bind(&self.bind_addr, tls_config, self.config).await
+    }
+
+    /// Binds and runs the server with the configured options until receiving `shutdown_signal`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if binding fails or for any errors with the overall operation of the server,
+    /// as described for `run`.
+    pub async fn run(self, shutdown_signal: impl Future<Output = ()>) -> Result<()> {
+        self.bind().await?.run(shutdown_signal).await
+    }
+}
+
+/// A chat server that is bound to an address but not yet accepting connections.
+///
+/// Binding separately from running allows embedders to learn the actual address before the server
+/// starts, which is useful when binding to port 0 to get a random available port:
+///
+/// ```no_run
+/// # async fn example(shutdown_signal: impl Future<Output = ()>) -> anyhow::Result<()> {
+/// use prattle_server::{config::Config, server::Server, tls};
+///
+/// let server = Server::bind("127.0.0.1:0", tls::create_config()?, Config::default()).await?;
+/// println!("Listening on port {}", server.local_addr()?.port());
+/// server.run(shutdown_signal).await
+/// # }
+/// ```
+pub struct Server {
+    listener: TcpListener,
+    metrics_listener: Option<TcpListener>,
+    health_listener: Option<TcpListener>,
+    webhook: Option<Endpoint>,
+
+    /// `None` if the server accepts plaintext connections instead of using TLS.
+    tls_acceptor: Option<TlsAcceptor>,
+    config: Config,
+    reload: ReloadHandle,
+    drain: DrainHandle,
+}
+
+impl Server {
+    /// Binds a TCP listener to `bind_addr` for a server using TLS as configured with `tls_config`
+    /// and the options in `config`, as well as listeners for the metrics and health check
+    /// endpoints if `config.metrics_addr` and `config.health_addr` are set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if any address or the webhook URL is malformed or binding any TCP listener
+    /// fails.
+    pub async fn bind(
+        bind_addr: &str,
+        tls_config: Arc<ServerConfig>,
+        config: Config,
+    ) -> Result<Self> {
+        Self::bind_with_acceptor(bind_addr, Some(TlsAcceptor::from(tls_config)), config).await
+    }
+
+    /// Binds a server the same way as `bind`, but without TLS, so that clients connect over raw
+    /// TCP (e.g., with `telnet` or `prattle_client::connect_plaintext`). Everything sent between
+    /// the server and its clients, including passwords and admin tokens, can be read by anyone on
+    /// the network, so this is only meant for local testing.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if any address or the webhook URL is malformed or binding any TCP listener
+    /// fails.
+    #[cfg(feature = "plaintext")]
+    pub async fn bind_plaintext(bind_addr: &str, config: Config) -> Result<Self> {
+        warn!("TLS is disabled, so connections to this server are not encrypted");
+        Self::bind_with_acceptor(bind_addr, None, config).await
+    }
+
+    /// Binds a server that uses `tls_acceptor` for each connection, or plaintext if `None`.
+    async fn bind_with_acceptor(
+        bind_addr: &str,
+        tls_acceptor: Option<TlsAcceptor>,
+        mut config: Config,
+    ) -> Result<Self> {
+        if config.client_disconnect_timeout > config.shutdown_timeout {
+            warn!(
+                "Client disconnect timeout ({:?}) exceeds shutdown timeout ({:?}), clamping it",
+                config.client_disconnect_timeout, config.shutdown_timeout
+            );
+            config.client_disconnect_timeout = config.shutdown_timeout;
+        }
+
+        let webhook = config
+            .webhook_url
+            .as_deref()
+            .map(str::parse::<Endpoint>)
+            .transpose()?;
+
+        let listener = bind_listener(bind_addr).await?;
+        info!("Listening on {}", listener.local_addr()?);
+
+        let metrics_listener = match &config.metrics_addr {
+            Some(metrics_addr) => {
+                let metrics_listener = bind_listener(metrics_addr).await?;
+                info!("Serving metrics on {}", metrics_listener.local_addr()?);
+                Some(metrics_listener)
             }
 
-            () = &mut shutdown_signal => {
-                break match shutdown_tx.send(()) {
-                    Ok(receivers) => {
-                        info!("Broadcast shutdown to {receivers} client(s)");
-                        true
+            None => None,
+        };
+
+        let health_listener = match &config.health_addr {
+            Some(health_addr) => {
+                let health_listener = bind_listener(health_addr).await?;
+                info!("Serving health checks on {}", health_listener.local_addr()?);
+                Some(health_listener)
+            }
+
+            None => None,
+        };
+
+        Ok(Self {
+            listener,
+            metrics_listener,
+            health_listener,
+            webhook,
+            tls_acceptor,
+            config,
+            reload: ReloadHandle::new(),
+            drain: DrainHandle::new(),
+        })
+    }
+
+    /// Returns the address the server is bound to.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the address cannot be retrieved from the underlying socket.
+    pub fn local_addr(&self) -> Result<SocketAddr> { Ok(self.listener.local_addr()?) }
+
+    /// Returns the address the metrics endpoint is bound to, or `None` if it is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the address cannot be retrieved from the underlying socket.
+    pub fn metrics_addr(&self) -> Result<Option<SocketAddr>> {
+        Ok(self
+            .metrics_listener
+            .as_ref()
+            .map(TcpListener::local_addr)
+            .transpose()?)
+    }
+
+    /// Returns the address the health check endpoint is bound to, or `None` if it is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the address cannot be retrieved from the underlying socket.
+    pub fn health_addr(&self) -> Result<Option<SocketAddr>> {
+        Ok(self
+            .health_listener
+            .as_ref()
+            .map(TcpListener::local_addr)
+            .transpose()?)
+    }
+
+    /// Returns a handle for reloading the TLS certificate and private key from `tls::CERT_PATH`
+    /// and its accompanying key file while the server is running, e.g. by passing it to
+    /// `reload_signal::listen`.
+    #[must_use]
+    pub fn reload_handle(&self) -> ReloadHandle { self.reload.clone() }
+
+    /// Returns a handle for making the server stop accepting new connections while continuing to
+    /// serve existing clients, e.g. by passing it to `drain_signal::listen`.
+    #[must_use]
+    pub fn drain_handle(&self) -> DrainHandle { self.drain.clone() }
+
+    /// Runs the server until receiving `shutdown_signal`, as described for `run`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` for any errors with the overall operation of the server, but logs and does not
+    /// return errors from handling specific clients.
+    pub async fn run(self, shutdown_signal: impl Future<Output = ()>) -> Result<()> {
+        self.run_with_force(shutdown_signal, future::pending())
+            .await
+    }
+
+    /// Runs the server until receiving `shutdown_signal` the same way as `run`, but stops waiting
+    /// for clients to disconnect as soon as `force_signal` completes (e.g., on a second Ctrl+C with
+    /// `ShutdownHandle::force_signal`). `force_signal` is only polled once shutdown has begun.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` for any errors with the overall operation of the server, but logs and does not
+    /// return errors from handling specific clients.
+    pub async fn run_with_force(
+        self,
+        shutdown_signal: impl Future<Output = ()>,
+        force_signal: impl Future<Output = ()>,
+    ) -> Result<()> {
+        let Self {
+            listener,
+            metrics_listener,
+            health_listener,
+            webhook,
+            mut tls_acceptor,
+            config,
+            reload,
+            drain,
+        } = self;
+
+        // Dropped when draining so that new connections are refused
+        let mut listener = Some(listener);
+
+        let (shutdown_tx, _) = broadcast::channel(1);
+        let state = Arc::new(SharedState::new(config));
+
+        let mut throttle = state
+            .config
+            .max_connects_per_ip
+            .map(|max| ConnectThrottle::new(max, state.config.connect_window));
+
+        // Tasks in the set are aborted when it is dropped, so they stop when the server does
+        let mut background_tasks = JoinSet::new();
+
+        if let Some(metrics_listener) = metrics_listener {
+            background_tasks.spawn(metrics::serve(metrics_listener, Arc::clone(&state)));
+        }
+
+        if let Some(health_listener) = health_listener {
+            background_tasks.spawn(health::serve(health_listener, Arc::clone(&state)));
+        }
+
+        // Subscribed before accepting anyone, so that the first join is not missed
+        if let Some(webhook) = webhook {
+            let subscription = state.fanout.subscribe();
+            background_tasks.spawn(webhook::run(webhook, subscription, Arc::clone(&state)));
+        }
+
+        tokio::pin!(shutdown_signal);
+
+        if loop {
+            tokio::select! {
+                conn_result = async {
+                    match &listener {
+                        Some(listener) => listener.accept().await,
+                        None => future::pending().await,
                     }
-                    Err(e) if users.lock().await.is_empty() && active_clients.load(SeqCst) == 0 => {
-                        warn!("No users online to broadcast shutdown to: {e}");
-                        false
+                } => {
+                    let (socket, client_addr) = conn_result?;
+
+                    // Dropping the socket closes it without spending anything on a handshake
+                    if !admit(client_addr, &state.config, throttle.as_mut()) {
+                        continue;
+                    }
+
+                    info!("New connection from {client_addr}");
+
+                    if let Err(e) = enable_keepalive(&socket, &state.config) {
+                        warn!("Failed to enable TCP keepalive for {client_addr}: {e}");
+                    }
+
+                    tokio::spawn(
+                        handle_connection(
+                            tls_acceptor.clone(),
+                            socket,
+                            client_addr,
+                            Arc::clone(&state),
+                            shutdown_tx.subscribe(),
+                        )
+                        .instrument(client_span(client_addr)),
+                    );
+                }
+
+                () = drain.requested(), if listener.is_some() => {
+                    listener = None;
+                    info!("Draining: refusing new connections until shutdown");
+                }
+
+                // Connections already accepted keep the acceptor they were given. Malformed files
+                // are not regenerated, since they may be new ones that are only partly written.
+                () = reload.requested(), if tls_acceptor.is_some() => match tls::create_config_with(false) {
+                    Ok(tls_config) => {
+                        tls_acceptor = Some(TlsAcceptor::from(tls_config));
+                        info!("Reloaded TLS certificate");
                     }
-                    Err(e) => {
-                        error!("Failed to broadcast shutdown with users online: {e}");
-                        false
+
+                    Err(e) => error!("Failed to reload TLS certificate, keeping the previous one: {e}"),
+                },
+
+                () = &mut shutdown_signal => {
+                    break match shutdown_tx.send(()) {
+                        Ok(receivers) => {
+                            info!("Broadcast shutdown to {receivers} client(s)");
+                            true
+                        }
+                        Err(e) if state.users.lock().await.is_empty()
+                            && state.active_clients.load(SeqCst) == 0 => {
+                            warn!("No users online to broadcast shutdown to: {e}");
+                            false
+                        }
+                        Err(e) => {
+                            error!("Failed to broadcast shutdown with users online: {e}");
+                            false
+                        }
                     }
                 }
             }
+        } {
+            tokio::select! {
+                () = wait_for_clients(&state) => {}
+                () = force_signal => warn!(
+                    "Shutdown forced with {} user(s) and {} active client(s) still connected",
+                    state.users.lock().await.len(),
+                    state.active_clients.load(SeqCst)
+                ),
+            }
         }
-    } {
-        info!("Waiting for clients to disconnect");
-
-        let start = Instant::now();
-
-        while !users.lock().await.is_empty() || active_clients.load(SeqCst) > 0 {
-            if start.elapsed() >= GLOBAL_SHUTDOWN_TIMEOUT {
-                warn!(
-                    "Global shutdown timeout reached with {} user(s) and \
-                    {} active client(s) still connected",
-                    users.lock().await.len(),
-                    active_clients.load(SeqCst)
-                );
 
-                break;
+        info!("Server shutting down now");
+        Ok(())
+    }
+}
+
+/// Checks whether a newly accepted connection from `client_addr` may proceed, i.e., whether its IP
+/// address is allowed by `config` and has not connected too often according to `throttle`.
+/// Logs the reason for refusing the connection if not.
+fn admit(client_addr: SocketAddr, config: &Config, throttle: Option<&mut ConnectThrottle>) -> bool {
+    if !config.is_ip_allowed(client_addr.ip()) {
+        info!("Refused connection from {client_addr}: IP address not allowed");
+        return false;
+    }
+
+    if let Some(throttle) = throttle
+        && !throttle.allow(client_addr.ip(), Instant::now())
+    {
+        warn!("Refused connection from {client_addr}: too many recent connections");
+        return false;
+    }
+
+    true
+}
+
+/// Waits for all clients to disconnect after shutdown was broadcast, giving up once the shutdown
+/// timeout is reached. Wakes up whenever a client disconnects, and otherwise checks again every
+/// shutdown poll interval.
+async fn wait_for_clients(state: &SharedState) {
+    info!("Waiting for clients to disconnect");
+
+    let start = Instant::now();
+
+    loop {
+        // Registered before checking, so that a client disconnecting in between still wakes this
+        let disconnected = state.client_disconnected.notified();
+        pin!(disconnected);
+        disconnected.as_mut().enable();
+
+        if state.users.lock().await.is_empty() && state.active_clients.load(SeqCst) == 0 {
+            return;
+        }
+
+        let remaining = state
+            .config
+            .shutdown_timeout
+            .saturating_sub(start.elapsed());
+
+        if remaining.is_zero() {
+            warn!(
+                "Global shutdown timeout reached with {} user(s) and \
+                {} active client(s) still connected",
+                state.users.lock().await.len(),
+                state.active_clients.load(SeqCst)
+            );
+
+            return;
+        }
+
+        let wait = state.config.shutdown_poll_interval.min(remaining);
+        let _ = tokio::time::timeout(wait, disconnected).await;
+    }
+}
+
+/// Validates `addr` with `validate_addr` and binds a TCP listener to it, adding context to common
+/// errors.
+async fn bind_listener(addr: &str) -> Result<TcpListener> {
+    validate_addr(addr)?;
+
+    TcpListener::bind(addr).await.map_err(|e| {
+        let hint = match e.kind() {
+            io::ErrorKind::AddrInUse => {
+                "the port is already in use (is another server running?), so try a different port"
             }
+            io::ErrorKind::AddrNotAvailable => "the host is not an address of this machine",
+            io::ErrorKind::PermissionDenied => "ports below 1024 may require elevated privileges",
+            _ => "",
+        };
 
-            tokio::time::sleep(Duration::from_millis(100)).await;
+        let err = anyhow::Error::new(e);
+
+        if hint.is_empty() {
+            err.context(format!("Failed to bind to {addr}"))
+        } else {
+            err.context(format!("Failed to bind to {addr}: {hint}"))
         }
+    })
+}
+
+/// Checks that `addr` has the form `host:port` before trying to bind to it, since the OS errors
+/// for malformed addresses are confusing. IPv6 hosts must be in brackets, e.g. `[::1]:8000`.
+fn validate_addr(addr: &str) -> Result<()> {
+    let invalid = || format!("Invalid bind address \"{addr}\": expected host:port");
+
+    let (host, port) = addr.rsplit_once(':').with_context(invalid)?;
+
+    if host.is_empty() || host.contains(char::is_whitespace) {
+        bail!("{}", invalid());
+    }
+
+    if host.contains(':') && !(host.starts_with('[') && host.ends_with(']')) {
+        bail!("Invalid bind address \"{addr}\": IPv6 hosts must be in brackets, e.g. [::1]:8000");
     }
 
-    info!("Server shutting down now");
+    port.parse::<u16>().with_context(|| {
+        format!("Invalid bind address \"{addr}\": port must be a number from 0 to 65535")
+    })?;
+
     Ok(())
 }
+
+/// Enables TCP keepalive on `socket` as configured, so that if the client vanishes without closing
+/// the connection, reads eventually fail and the client's handler cleans up after them instead of
+/// waiting forever. Does nothing if keepalive is disabled.
+fn enable_keepalive(socket: &TcpStream, config: &Config) -> io::Result<()> {
+    let Some(idle) = config.keepalive_idle else {
+        return Ok(());
+    };
+
+    let keepalive = TcpKeepalive::new().with_time(idle);
+
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+    let keepalive = keepalive.with_interval(config.keepalive_interval);
+
+    SockRef::from(socket).set_tcp_keepalive(&keepalive)
+}
+
+/// Completes the TLS handshake with a newly accepted connection (unless `acceptor` is `None` for a
+/// plaintext server) and then handles the client until they disconnect with `serve_stream`.
+async fn handle_connection(
+    acceptor: Option<TlsAcceptor>,
+    socket: TcpStream,
+    client_addr: SocketAddr,
+    state: Arc<SharedState>,
+    shutdown_rx: broadcast::Receiver<()>,
+) {
+    let Some(acceptor) = acceptor else {
+        serve_stream(socket, client_addr, state, shutdown_rx).await;
+        return;
+    };
+
+    let tls_stream = match acceptor.accept(socket).await {
+        Err(e) => {
+            error!("TLS handshake failed for {client_addr}: {e}");
+            return;
+        }
+
+        Ok(tls_stream) => tls_stream,
+    };
+
+    let (_, connection) = tls_stream.get_ref();
+    let version = connection.protocol_version().map_or_else(
+        || String::from("unknown version"),
+        |version| format!("{version:?}"),
+    );
+    let suite = connection.negotiated_cipher_suite().map_or_else(
+        || String::from("unknown cipher suite"),
+        |suite| format!("{:?}", suite.suite()),
+    );
+    info!("TLS handshake completed for {client_addr} using {version} with {suite}");
+
+    serve_stream(tls_stream, client_addr, state, shutdown_rx).await;
+}
+
+/// Creates the span that everything logged while handling the client at `client_addr` is recorded
+/// in, so that interleaved logs from concurrent clients can be told apart. The client's `username`
+/// is recorded in the span once they choose one.
+pub(crate) fn client_span(client_addr: SocketAddr) -> Span {
+    info_span!("client", %client_addr, username = field::Empty)
+}
+
+/// Handles the client connected over `stream` until they disconnect, rejecting them instead if too
+/// many connections are in username selection.
+pub(crate) async fn serve_stream<S>(
+    stream: S,
+    client_addr: SocketAddr,
+    state: Arc<SharedState>,
+    shutdown_rx: broadcast::Receiver<()>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let Some(client_guard) = ClientGuard::acquire(Arc::clone(&state)) else {
+        warn!("Too many clients, rejecting {client_addr}");
+        reject(stream, client_addr, b"Server full, try again later\n").await;
+        return;
+    };
+
+    let Some(pending_guard) = PendingGuard::acquire(Arc::clone(&state)) else {
+        drop(client_guard);
+        warn!("Too many pending connections, rejecting {client_addr}");
+        reject(stream, client_addr, b"Server busy, try again shortly\n").await;
+        return;
+    };
+
+    state.metrics.connections.fetch_add(1, SeqCst);
+
+    // Run the handler as its own task so that a panic can be caught and cleaned up after here
+    let session_slot = Arc::new(OnceLock::new());
+
+    let handler = tokio::spawn(
+        client::handle_client(
+            stream,
+            Arc::clone(&state),
+            shutdown_rx,
+            pending_guard,
+            Arc::clone(&session_slot),
+            client_addr,
+        )
+        .in_current_span(),
+    );
+
+    supervise_handler(handler, &session_slot, &state, client_addr).await;
+    drop(client_guard);
+}
+
+/// Sends `reason` to the client at `client_addr` and closes the connection.
+async fn reject<S>(mut stream: S, client_addr: SocketAddr, reason: &[u8])
+where S: AsyncWrite + Unpin {
+    if let Err(e) = async {
+        stream.write_all(reason).await?;
+        stream.shutdown().await
+    }
+    .await
+    {
+        error!("Error rejecting {client_addr}: {e}");
+    }
+}
+
+/// Waits for a client handler task to finish and logs the result. If the handler panicked (or was
+/// otherwise aborted) after the client chose a username, removes the user and broadcasts that they
+/// left, as the handler would have done if it had finished normally, unless another session has
+/// taken over the username.
+async fn supervise_handler(
+    handler: JoinHandle<Result<()>>,
+    session_slot: &OnceLock<Session>,
+    state: &SharedState,
+    client_addr: SocketAddr,
+) {
+    match handler.await {
+        Ok(Ok(())) => info!("Client {client_addr} disconnected"),
+
+        Ok(Err(e)) => error!("Error handling client {client_addr}: {e}"),
+
+        Err(e) => {
+            error!("Handler for client {client_addr} failed: {e}");
+
+            let Some(Session { username, control_tx }) = session_slot.get() else {
+                return;
+            };
+
+            let online = {
+                let mut users_guard = state.users.lock().await;
+
+                // A session that took over the username has its own entry, which is left alone
+                let removed = if users_guard
+                    .get(username)
+                    .is_some_and(|user| user.control_tx.same_channel(control_tx))
+                {
+                    users_guard.remove(username)
+                } else {
+                    None
+                };
+                let online = users_guard.len();
+
+                if let Some(user) = &removed {
+                    state.expire_reconnect_token(user).await;
+                }
+
+                drop(users_guard);
+                removed.map(|_| online)
+            };
+
+            if let Some(online) = online {
+                state.seen.lock().await.record(username, Instant::now());
+
+                if state.fanout.send(&ChatEvent::leave(
+                    username,
+                    client::presence_notice(
+                        &state.config,
+                        &Departure::LostConnection.notice(username),
+                        online,
+                    ),
+                )) == 0
+                {
+                    warn!("No clients to receive the notice that {username} left");
+                }
+            }
+        }
+    }
+}
+
+/// Holds one of the slots for client connections counted in `active_clients`, releasing it when
+/// dropped.
+struct ClientGuard(Arc<SharedState>);
+
+impl ClientGuard {
+    /// Claims a client connection slot, or returns `None` if `max_clients` are already connected.
+    /// The slot is claimed before checking the limit, so that connections accepted at the same time
+    /// can't all pass the check.
+    fn acquire(state: Arc<SharedState>) -> Option<Self> {
+        let active = state.active_clients.fetch_add(1, SeqCst);
+
+        if state.config.max_clients.is_some_and(|max| active >= max) {
+            state.active_clients.fetch_sub(1, SeqCst);
+            None
+        } else {
+            Some(Self(state))
+        }
+    }
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.0.active_clients.fetch_sub(1, SeqCst);
+        self.0.client_disconnected.notify_waiters();
+    }
+}
+
+/// Holds one of the limited slots for connections in username selection, releasing it when
+/// dropped.
+pub(crate) struct PendingGuard(Arc<SharedState>);
+
+impl PendingGuard {
+    /// Claims a pending connection slot, or returns `None` if all slots are already taken.
+    fn acquire(state: Arc<SharedState>) -> Option<Self> {
+        if state.pending_clients.fetch_add(1, SeqCst) < state.config.max_pending_connections {
+            Some(Self(state))
+        } else {
+            state.pending_clients.fetch_sub(1, SeqCst);
+            None
+        }
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) { self.0.pending_clients.fetch_sub(1, SeqCst); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, user::UserInfo};
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn keepalive_is_enabled_unless_disabled() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let listener = TcpListener::bind("127.0.0.1:0").await?;
+                let _client = TcpStream::connect(listener.local_addr()?).await?;
+                let (socket, _) = listener.accept().await?;
+                let config =
+                    Config { keepalive_idle: Some(Duration::from_secs(30)), ..Config::default() };
+
+                enable_keepalive(&socket, &config)?;
+                assert!(SockRef::from(&socket).keepalive()?);
+
+                // Disabling keepalive leaves the socket's default (off) alone
+                let _client = TcpStream::connect(listener.local_addr()?).await?;
+                let (socket, _) = listener.accept().await?;
+                enable_keepalive(
+                    &socket,
+                    &Config { keepalive_idle: None, ..Config::default() },
+                )?;
+                assert!(!SockRef::from(&socket).keepalive()?);
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn client_disconnect_timeout_is_clamped_to_shutdown_timeout() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let server = Server::bind(
+                    "127.0.0.1:0",
+                    tls::create_config()?,
+                    Config {
+                        shutdown_timeout: Duration::from_secs(2),
+                        client_disconnect_timeout: Duration::from_secs(10),
+                        ..Config::default()
+                    },
+                )
+                .await?;
+
+                assert_eq!(
+                    server.config.client_disconnect_timeout,
+                    Duration::from_secs(2)
+                );
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn well_formed_addresses_are_valid() {
+        for addr in [
+            "127.0.0.1:8000",
+            "0.0.0.0:0",
+            "localhost:8000",
+            "chat.example.com:443",
+            "[::1]:8000",
+        ] {
+            assert!(validate_addr(addr).is_ok(), "expected {addr} to be valid");
+        }
+    }
+
+    #[test]
+    fn malformed_addresses_are_invalid() {
+        for (addr, expected) in [
+            ("foo", "expected host:port"),
+            ("127.0.0.1", "expected host:port"),
+            (":8000", "expected host:port"),
+            ("my host:8000", "expected host:port"),
+            ("127.0.0.1:", "port must be a number"),
+            ("127.0.0.1:http", "port must be a number"),
+            ("127.0.0.1:70000", "port must be a number"),
+            ("::1:8000", "IPv6 hosts must be in brackets"),
+        ] {
+            let err = validate_addr(addr).err().map(|e| format!("{e:#}"));
+            assert!(
+                err.as_deref().is_some_and(|err| err.contains(expected)),
+                "expected error containing \"{expected}\" for {addr}, got: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn binding_an_address_in_use_suggests_another_port() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let listener = TcpListener::bind("127.0.0.1:0").await?;
+                let addr = listener.local_addr()?.to_string();
+
+                let err = bind_listener(&addr).await.err().map(|e| format!("{e:#}"));
+                assert!(
+                    err.as_deref()
+                        .is_some_and(|err| err.contains("try a different port")),
+                    "got: {err:?}"
+                );
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn panicking_handler_releases_its_user_slot() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let state = SharedState::new(Config::default());
+                let subscription = state.fanout.subscribe();
+                let control_tx = mpsc::unbounded_channel().0;
+
+                state.users.lock().await.insert(
+                    String::from("alice"),
+                    UserInfo::new(control_tx.clone(), "127.0.0.1:0".parse()?),
+                );
+
+                let session_slot =
+                    OnceLock::from(Session { username: String::from("alice"), control_tx });
+                let handler = tokio::spawn(async { panic!("injected handler panic") });
+
+                supervise_handler(handler, &session_slot, &state, "127.0.0.1:0".parse()?).await;
+
+                assert!(!state.users.lock().await.contains_key("alice"));
+                assert_eq!(subscription.try_recv()?.line, "* alice lost connection\n");
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn panicking_handler_leaves_a_session_that_took_over_alone() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let state = SharedState::new(Config::default());
+                let subscription = state.fanout.subscribe();
+
+                // The username now belongs to a newer session with its own control channel
+                state.users.lock().await.insert(
+                    String::from("alice"),
+                    UserInfo::new(mpsc::unbounded_channel().0, "127.0.0.1:0".parse()?),
+                );
+
+                let session_slot = OnceLock::from(Session {
+                    username: String::from("alice"),
+                    control_tx: mpsc::unbounded_channel().0,
+                });
+                let handler = tokio::spawn(async { panic!("injected handler panic") });
+
+                supervise_handler(handler, &session_slot, &state, "127.0.0.1:0".parse()?).await;
+
+                assert!(state.users.lock().await.contains_key("alice"));
+                assert!(subscription.try_recv().is_err());
+
+                Ok(())
+            })
+    }
+}
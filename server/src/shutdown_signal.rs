@@ -1,40 +1,150 @@
 use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::watch;
 use tracing::{info, warn};
 
-/// Creates Unix signal handlers that listen for SIGINT and SIGTERM.
+/// A handle for triggering graceful shutdown programmatically rather than with a signal, e.g. when
+/// embedding the server in another application.
+///
+/// Pass a future from `signal()` as the server's shutdown signal and call `trigger()` (from any
+/// clone of the handle) to shut the server down:
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// use prattle_server::{server, shutdown_signal::ShutdownHandle, tls};
+///
+/// let shutdown = ShutdownHandle::new();
+/// let server_task = tokio::spawn(server::run(
+///     "127.0.0.1:8000",
+///     tls::create_config()?,
+///     shutdown.signal(),
+/// ));
+///
+/// // ...later
+/// shutdown.trigger();
+/// server_task.await??;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Triggering a second time (e.g., pressing Ctrl+C twice) completes futures created by
+/// `force_signal()`, which a server run with `Server::run_with_force` uses to stop waiting for
+/// clients to disconnect.
+///
+/// Dropping every handle without calling `trigger()` does not trigger shutdown.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownHandle {
+    /// The number of times shutdown has been triggered, up to 2.
+    tx: Arc<watch::Sender<u8>>,
+}
+
+impl ShutdownHandle {
+    /// Creates a new handle that has not been triggered.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Triggers shutdown, completing all futures created by `signal()`. Triggering a second time
+    /// also completes all futures created by `force_signal()`. Further triggers have no additional
+    /// effect.
+    pub fn trigger(&self) {
+        let mut previous = 0;
+
+        self.tx.send_if_modified(|count| {
+            previous = *count;
+            *count = (*count + 1).min(2);
+            previous < 2
+        });
+
+        match previous {
+            0 => info!("Shutdown triggered, shutting down..."),
+            1 => warn!("Shutdown triggered again, no longer waiting for clients to disconnect..."),
+            _ => {}
+        }
+    }
+
+    /// Returns whether shutdown has been triggered.
+    #[must_use]
+    pub fn is_triggered(&self) -> bool { *self.tx.borrow() > 0 }
+
+    /// Creates a future that completes once shutdown is triggered, or immediately if it has been
+    /// triggered already.
+    pub fn signal(&self) -> impl Future<Output = ()> + Send + use<> { self.wait_for_count(1) }
+
+    /// Creates a future that completes once shutdown is triggered a second time, or immediately if
+    /// it has been already.
+    pub fn force_signal(&self) -> impl Future<Output = ()> + Send + use<> { self.wait_for_count(2) }
+
+    /// Creates a future that completes once shutdown has been triggered at least `count` times.
+    fn wait_for_count(&self, count: u8) -> impl Future<Output = ()> + Send + use<> {
+        let mut rx = self.tx.subscribe();
+
+        async move {
+            if rx.wait_for(|&triggered| triggered >= count).await.is_err() {
+                // Every handle was dropped without triggering, so shutdown can never be triggered
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// Creates signal handlers that listen for SIGINT and SIGTERM (or Ctrl+C on other platforms).
+///
+/// The returned future completes on the first signal. Use `listen_handle` instead to also let a
+/// second signal force shutdown.
+///
+/// # Errors
+///
+/// Returns `Err` for errors installing the signal handlers, but logs and does not return errors
+/// receiving the signals.
+pub fn listen() -> Result<impl Future<Output = ()>> { Ok(listen_handle()?.signal()) }
+
+/// Creates Unix signal handlers that trigger the returned handle each time SIGINT or SIGTERM is
+/// received, so that a second signal can force shutdown.
 ///
 /// # Errors
 ///
 /// Returns `Err` for errors installing the signal handlers, but logs and does not return errors
 /// receiving the signals.
 #[cfg(unix)]
-pub fn listen() -> Result<impl Future<Output = ()>> {
+pub fn listen_handle() -> Result<ShutdownHandle> {
     use tokio::signal::unix;
 
     let mut sigint = unix::signal(unix::SignalKind::interrupt())?;
     let mut sigterm = unix::signal(unix::SignalKind::terminate())?;
 
-    Ok(async move {
-        tokio::select! {
-            v = sigint.recv() => {
-                if v == Some(()) {
-                    info!("SIGINT received, shutting down...");
-                } else {
-                    warn!("SIGINT stream ended unexpectedly, shutting down...");
+    let handle = ShutdownHandle::new();
+    let signal_handle = handle.clone();
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                v = sigint.recv() => {
+                    if v.is_none() {
+                        warn!("SIGINT stream ended unexpectedly, shutting down...");
+                        break;
+                    }
+                    info!("SIGINT received");
                 }
-            }
-            v = sigterm.recv() => {
-                if v == Some(()) {
-                    info!("SIGTERM received, shutting down...");
-                } else {
-                    warn!("SIGTERM stream ended unexpectedly, shutting down...");
+                v = sigterm.recv() => {
+                    if v.is_none() {
+                        warn!("SIGTERM stream ended unexpectedly, shutting down...");
+                        break;
+                    }
+                    info!("SIGTERM received");
                 }
             }
+
+            signal_handle.trigger();
         }
-    })
+
+        signal_handle.trigger();
+    });
+
+    Ok(handle)
 }
 
-/// Creates a cross-platform signal handler that listens for Ctrl+C.
+/// Creates a cross-platform signal handler that triggers the returned handle each time Ctrl+C is
+/// received, so that a second Ctrl+C can force shutdown.
 ///
 /// # Errors
 ///
@@ -42,11 +152,127 @@ pub fn listen() -> Result<impl Future<Output = ()>> {
 /// Errors receiving Ctrl+C are logged, but not returned.
 #[allow(clippy::unnecessary_wraps)]
 #[cfg(not(unix))]
-pub fn listen() -> Result<impl Future<Output = ()>> {
-    Ok(async {
-        match tokio::signal::ctrl_c().await {
-            Ok(()) => info!("Ctrl+C received, shutting down..."),
-            Err(e) => warn!("Ctrl+C handler error, shutting down: {e}"),
+pub fn listen_handle() -> Result<ShutdownHandle> {
+    let handle = ShutdownHandle::new();
+    let signal_handle = handle.clone();
+
+    tokio::spawn(async move {
+        loop {
+            match tokio::signal::ctrl_c().await {
+                Ok(()) => info!("Ctrl+C received"),
+                Err(e) => {
+                    warn!("Ctrl+C handler error, shutting down: {e}");
+                    signal_handle.trigger();
+                    break;
+                }
+            }
+
+            signal_handle.trigger();
         }
-    })
+    });
+
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn shutdown_handle_completes_signal_when_triggered() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let handle = ShutdownHandle::new();
+                let signal = handle.signal();
+                tokio::pin!(signal);
+
+                // The signal should not complete before shutdown is triggered
+                assert!(
+                    tokio::time::timeout(Duration::from_millis(50), &mut signal)
+                        .await
+                        .is_err()
+                );
+                assert!(!handle.is_triggered());
+
+                // Triggering from a clone completes the signal
+                handle.clone().trigger();
+                tokio::time::timeout(Duration::from_millis(50), signal).await?;
+                assert!(handle.is_triggered());
+
+                // Signals created after triggering complete immediately
+                tokio::time::timeout(Duration::from_millis(50), handle.signal()).await?;
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn second_trigger_completes_force_signal() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let handle = ShutdownHandle::new();
+                let force_signal = handle.force_signal();
+                tokio::pin!(force_signal);
+
+                // The first trigger completes the signal, but not the force signal
+                handle.trigger();
+                tokio::time::timeout(Duration::from_millis(50), handle.signal()).await?;
+                assert!(
+                    tokio::time::timeout(Duration::from_millis(50), &mut force_signal)
+                        .await
+                        .is_err()
+                );
+
+                // The second trigger completes the force signal
+                handle.trigger();
+                tokio::time::timeout(Duration::from_millis(50), force_signal).await?;
+
+                // Further triggers have no effect
+                handle.trigger();
+                tokio::time::timeout(Duration::from_millis(50), handle.force_signal()).await?;
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn listen_returns_a_signal_that_waits_for_a_signal() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let signal = listen()?;
+
+                assert!(
+                    tokio::time::timeout(Duration::from_millis(50), signal)
+                        .await
+                        .is_err()
+                );
+
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn dropping_shutdown_handle_does_not_complete_signal() -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let signal = ShutdownHandle::new().signal();
+
+                assert!(
+                    tokio::time::timeout(Duration::from_millis(50), signal)
+                        .await
+                        .is_err()
+                );
+
+                Ok(())
+            })
+    }
 }
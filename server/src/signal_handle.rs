@@ -0,0 +1,85 @@
+use anyhow::Result;
+use std::sync::Arc;
+#[cfg(unix)]
+use tokio::signal::unix::SignalKind;
+use tokio::sync::Notify;
+use tracing::info;
+
+/// A handle for asking a running server to do something, either programmatically or (with the
+/// `listen` function of the module for that action) on a Unix signal. See `DrainHandle` and
+/// `ReloadHandle`.
+#[derive(Clone, Debug, Default)]
+pub struct SignalHandle {
+    notify: Arc<Notify>,
+}
+
+impl SignalHandle {
+    /// Creates a new handle with no pending request.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Makes a request. Requests made before the server gets to the first one are handled once.
+    pub fn trigger(&self) { self.notify.notify_one(); }
+
+    /// Waits until a request is made.
+    pub(crate) async fn requested(&self) { self.notify.notified().await; }
+}
+
+/// A Unix signal that triggers a `SignalHandle`, along with how to describe it in logs.
+#[derive(Clone, Copy)]
+pub(crate) struct Signal {
+    /// The name of the signal, e.g. `SIGHUP`.
+    pub name: &'static str,
+
+    /// The signal to listen for.
+    #[cfg(unix)]
+    pub kind: SignalKind,
+
+    /// What receiving the signal does, e.g. `reloading TLS certificate`.
+    pub action: &'static str,
+
+    /// What stops working without the signal, e.g. `certificate reloading`.
+    pub feature: &'static str,
+}
+
+/// Creates a Unix signal handler that triggers `handle` every time `signal` is received.
+///
+/// # Errors
+///
+/// Returns `Err` for errors installing the signal handler.
+#[cfg(unix)]
+pub(crate) fn listen(handle: SignalHandle, signal: Signal) -> Result<()> {
+    let mut stream = tokio::signal::unix::signal(signal.kind)?;
+
+    tokio::spawn(async move {
+        while stream.recv().await.is_some() {
+            info!("{} received, {}...", signal.name, signal.action);
+            handle.trigger();
+        }
+
+        tracing::warn!(
+            "{} stream ended unexpectedly, {} disabled",
+            signal.name,
+            signal.feature
+        );
+    });
+
+    Ok(())
+}
+
+/// Does nothing, because Unix signals are not available on this platform. The handle can still be
+/// triggered programmatically.
+///
+/// # Errors
+///
+/// Does not return `Err`. This function is only wrapped in `Result` to match the Unix version.
+#[allow(clippy::unnecessary_wraps, clippy::needless_pass_by_value)] // Matches the Unix version
+#[cfg(not(unix))]
+pub(crate) fn listen(handle: SignalHandle, signal: Signal) -> Result<()> {
+    let _ = handle;
+    info!(
+        "{} is not supported on this platform, so {} is only available programmatically",
+        signal.name, signal.feature
+    );
+    Ok(())
+}
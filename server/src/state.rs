@@ -0,0 +1,147 @@
+use crate::{
+    config::Config, fanout::Fanout, history::History, mailbox::Mailbox, metrics::ServerMetrics,
+    reconnect::ReconnectTokens, seen::SeenLog, user::UserInfo,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    sync::{Mutex, Notify, mpsc::UnboundedSender},
+    task::AbortHandle,
+};
+
+/// State shared between the server and all client handlers.
+pub struct SharedState {
+    /// The options the server was started with.
+    pub config: Config,
+
+    /// The per-client queues for messages broadcast to all clients.
+    pub fanout: Fanout,
+
+    /// The recent messages and actions replayed to joining clients. Lines are recorded and
+    /// broadcast while this is locked, and clients subscribe to broadcasts while it is locked, so
+    /// that each line reaches a joining client exactly once.
+    pub history: Mutex<History>,
+
+    /// The usernames provided by active clients and their associated state.
+    pub users: Mutex<HashMap<String, UserInfo>>,
+
+    /// The usernames of recently departed users whose leave notices are being deferred, and the
+    /// handles for aborting the deferred notices if they reconnect.
+    pub pending_leaves: Mutex<HashMap<String, AbortHandle>>,
+
+    /// The number of client connections, regardless of whether they have provided a username.
+    pub active_clients: AtomicUsize,
+
+    /// Notified whenever a client connection ends, so that graceful shutdown can finish as soon as
+    /// the last client leaves.
+    pub client_disconnected: Notify,
+
+    /// The number of client connections that have completed the TLS handshake but not yet chosen
+    /// a username.
+    pub pending_clients: AtomicUsize,
+
+    /// The usernames of users muted by an operator, whose messages and actions are not broadcast.
+    /// Mutes are kept by name rather than by connection, so they survive the user reconnecting
+    /// (and also apply to anyone else who later joins with the same name) until lifted with
+    /// `/unmute`.
+    pub muted: Mutex<HashSet<String>>,
+
+    /// Whether the server is read-only, in which case only operators can send messages and
+    /// actions.
+    pub locked: AtomicBool,
+
+    /// The number of seconds everyone but operators must wait between messages and actions, set
+    /// with `/slowmode`. Slow mode is off if 0.
+    pub slow_mode_secs: AtomicU64,
+
+    /// Cumulative activity counters, shown by `/stats`.
+    pub metrics: ServerMetrics,
+
+    /// When each user was last active, shown by `/seen`.
+    pub seen: Mutex<SeenLog>,
+
+    /// The private messages waiting for offline users, if `Config::offline_msgs` is set. Locked
+    /// while `users` is locked when queueing, so that a message is never queued for a user who has
+    /// already joined and collected theirs.
+    pub mailbox: Mutex<Mailbox>,
+
+    /// The tokens that let users reclaim their username after reconnecting, if
+    /// `Config::reconnect_window` is set. Locked while `users` is locked, so that a token is only
+    /// issued or expired along with the user it belongs to.
+    pub reconnect_tokens: Mutex<ReconnectTokens>,
+
+    /// When the server started, for measuring uptime.
+    pub started_at: Instant,
+
+    /// The wall-clock time when the server started, for display.
+    pub started_wall: SystemTime,
+}
+
+impl SharedState {
+    /// Creates the shared state for a server with the options in `config`.
+    pub fn new(config: Config) -> Self {
+        Self {
+            history: Mutex::new(History::new(config.history_size)),
+            fanout: Fanout::new(config.client_queue_cap, config.overflow_policy),
+            config,
+            users: Mutex::new(HashMap::new()),
+            pending_leaves: Mutex::new(HashMap::new()),
+            active_clients: AtomicUsize::new(0),
+            client_disconnected: Notify::new(),
+            pending_clients: AtomicUsize::new(0),
+            muted: Mutex::new(HashSet::new()),
+            locked: AtomicBool::new(false),
+            slow_mode_secs: AtomicU64::new(0),
+            metrics: ServerMetrics::default(),
+            seen: Mutex::new(SeenLog::default()),
+            mailbox: Mutex::new(Mailbox::default()),
+            reconnect_tokens: Mutex::new(ReconnectTokens::default()),
+            started_at: Instant::now(),
+            started_wall: SystemTime::now(),
+        }
+    }
+
+    /// Starts the reconnect window for `user`, who was just removed from `users`, so that their
+    /// reconnect token (if any) stops working once it has passed.
+    pub async fn expire_reconnect_token(&self, user: &UserInfo) {
+        if let (Some(token), Some(window)) = (&user.reconnect_token, self.config.reconnect_window) {
+            self.reconnect_tokens
+                .lock()
+                .await
+                .expire_at(token, Instant::now() + window);
+        }
+    }
+}
+
+/// An instruction sent to a specific client handler rather than broadcast to all clients.
+#[derive(Debug)]
+pub enum ControlMessage {
+    /// Disconnect the client because they were kicked by the contained user.
+    Kick(String),
+
+    /// Send the contained latency probe to the client.
+    Ping(PingProbe),
+
+    /// Send the contained line to the client.
+    Notice(String),
+
+    /// Disconnect the client because a new connection has taken over their username.
+    TakenOver,
+}
+
+/// A latency probe sent to clients by `/ping-all`.
+#[derive(Clone, Debug)]
+pub struct PingProbe {
+    /// The token that the client must send back with `/pong <token>`.
+    pub token: String,
+
+    /// When the probe was created, for measuring round-trip time.
+    pub sent_at: Instant,
+
+    /// The sender half of the channel for reporting the usernames and round-trip times of clients
+    /// that respond.
+    pub responses: UnboundedSender<(String, Duration)>,
+}
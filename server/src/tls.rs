@@ -1,17 +1,19 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, bail};
 use pem::Pem;
 use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair, SanType, string::Ia5String};
 use rustls::{
     ServerConfig,
+    crypto::{CryptoProvider, aws_lc_rs},
     pki_types::{CertificateDer, PrivateKeyDer},
 };
 use std::{
     fs,
     net::{IpAddr, Ipv4Addr},
+    path::Path,
     str::FromStr,
     sync::{Arc, Mutex, OnceLock},
 };
-use tracing::info;
+use tracing::{info, warn};
 
 /// The file path for the server's certificate (public key and metadata) for TLS.
 pub const CERT_PATH: &str = "server.crt";
@@ -22,18 +24,64 @@ const KEY_PATH: &str = "server.key";
 /// Global lock to ensure certificate generation happens only once across concurrent threads.
 static CERT_FILE_LOCK: OnceLock<Mutex<()>> = OnceLock::new();
 
+/// Installs aws-lc-rs as the process-wide default crypto provider for Rustls, unless a provider is
+/// already installed (e.g., by an application embedding the server).
+///
+/// Called before creating any TLS config, so that it never depends on a provider having been set
+/// up elsewhere.
+///
+/// # Errors
+///
+/// Returns `Err` if no provider is installed afterward.
+pub fn install_crypto_provider() -> Result<()> {
+    // Failing to install means a provider was already installed, possibly by another thread
+    if CryptoProvider::get_default().is_none()
+        && aws_lc_rs::default_provider().install_default().is_err()
+        && CryptoProvider::get_default().is_none()
+    {
+        bail!("Failed to install the default crypto provider");
+    }
+
+    Ok(())
+}
+
 /// Creates a Rustls `ServerConfig` using a persistent self-signed certificate.
 ///
 /// If certificate files (`CERT_PATH` and `KEY_PATH`) exist, they are loaded. Otherwise, a new
-/// self-signed certificate is generated and saved to file.
+/// self-signed certificate is generated and saved to file. Existing files that cannot be parsed
+/// (e.g., because writing them was interrupted) are replaced with a newly generated certificate.
 ///
 /// This function uses a lock to ensure that certificate generation is atomic across threads,
 /// preventing race conditions when multiple servers/tests start simultaneously.
 ///
 /// # Errors
 ///
-/// Returns `Err` if certificate generation, file I/O, or config creation fails.
-pub fn create_config() -> Result<Arc<ServerConfig>> {
+/// Returns `Err` if certificate generation, file I/O, or config creation fails, including if no
+/// crypto provider could be installed.
+pub fn create_config() -> Result<Arc<ServerConfig>> { create_config_with(true) }
+
+/// Creates a Rustls `ServerConfig` as described for `create_config`, except that malformed
+/// certificate or key files are only regenerated if `regenerate_on_corrupt` is `true`.
+///
+/// # Errors
+///
+/// Returns `Err` if certificate generation, file I/O, or config creation fails, including if the
+/// existing files are malformed and `regenerate_on_corrupt` is `false`.
+pub fn create_config_with(regenerate_on_corrupt: bool) -> Result<Arc<ServerConfig>> {
+    create_config_at(
+        Path::new(CERT_PATH),
+        Path::new(KEY_PATH),
+        regenerate_on_corrupt,
+    )
+}
+
+/// Creates a Rustls `ServerConfig` using the certificate and private key files at the specified
+/// paths, as described for `create_config_with`.
+fn create_config_at(
+    cert_path: &Path,
+    key_path: &Path,
+    regenerate_on_corrupt: bool,
+) -> Result<Arc<ServerConfig>> {
     // Get/initialize and acquire the lock to ensure atomic check/generate
     let guard = CERT_FILE_LOCK
         .get_or_init(|| Mutex::new(()))
@@ -41,31 +89,50 @@ pub fn create_config() -> Result<Arc<ServerConfig>> {
         .map_err(|e| anyhow!("Lock poisoned: {e}"))?;
 
     // Check if certificate files exist and load/regenerate them accordingly while holding the lock
-    let files_found = fs::exists(CERT_PATH).is_ok_and(|verified| verified)
-        && fs::exists(KEY_PATH).is_ok_and(|verified| verified);
+    let files_found = fs::exists(cert_path).is_ok_and(|verified| verified)
+        && fs::exists(key_path).is_ok_and(|verified| verified);
+
+    let loaded = if files_found {
+        match load_cert_and_key(cert_path, key_path).and_then(|(cert, key)| build_config(cert, key))
+        {
+            Ok(config) => Some(config),
 
-    let (cert, key) = if files_found {
-        load_cert_and_key()?
+            Err(e) if regenerate_on_corrupt => {
+                warn!("Existing TLS certificate or key is malformed, regenerating: {e}");
+                None
+            }
+
+            Err(e) => return Err(e.context("Existing TLS certificate or key is malformed")),
+        }
     } else {
-        let (cert, key) = generate_self_signed_cert_and_key()?;
-        save_cert_and_key(&cert, &key)?;
-        (cert, key)
+        None
     };
 
-    drop(guard);
-
-    if files_found {
+    let config = if let Some(config) = loaded {
+        drop(guard);
         info!("Loaded existing TLS certificate from file");
+        config
     } else {
+        let (cert, key) = generate_self_signed_cert_and_key()?;
+        save_cert_and_key(cert_path, key_path, &cert, &key)?;
+        drop(guard);
         info!("Generated and saved new self-signed TLS certificate");
-    }
+        build_config(cert, key)?
+    };
 
-    // Configure to use the self-signed certificate and not to require client certificates
-    Ok(Arc::new(
-        ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(vec![cert], key)?,
-    ))
+    Ok(Arc::new(config))
+}
+
+/// Configures TLS to use the given certificate and not to require client certificates.
+fn build_config(
+    cert: CertificateDer<'static>,
+    key: PrivateKeyDer<'static>,
+) -> Result<ServerConfig> {
+    install_crypto_provider()?;
+
+    Ok(ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)?)
 }
 
 /// Generates a self-signed certificate and private key for TLS valid for localhost/127.0.0.1.
@@ -98,15 +165,20 @@ fn generate_self_signed_cert_and_key() -> Result<(CertificateDer<'static>, Priva
 }
 
 /// Saves a certificate and private key to file in PEM format.
-fn save_cert_and_key(cert: &CertificateDer<'_>, key: &PrivateKeyDer<'_>) -> Result<()> {
+fn save_cert_and_key(
+    cert_path: &Path,
+    key_path: &Path,
+    cert: &CertificateDer<'_>,
+    key: &PrivateKeyDer<'_>,
+) -> Result<()> {
     // Convert DER to PEM format and save as files
     fs::write(
-        CERT_PATH,
+        cert_path,
         pem::encode(&Pem::new("CERTIFICATE", cert.as_ref())),
     )?;
 
     fs::write(
-        KEY_PATH,
+        key_path,
         pem::encode(&Pem::new("PRIVATE KEY", key.secret_der())),
     )?;
 
@@ -114,19 +186,75 @@ fn save_cert_and_key(cert: &CertificateDer<'_>, key: &PrivateKeyDer<'_>) -> Resu
 }
 
 /// Loads a certificate and private key from file in PEM format.
-fn load_cert_and_key() -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
+fn load_cert_and_key(
+    cert_path: &Path,
+    key_path: &Path,
+) -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
     // Read files, parse PEM, and convert to DER
     Ok((
         CertificateDer::from(
-            pem::parse(&fs::read_to_string(CERT_PATH)?)?
+            pem::parse(&fs::read_to_string(cert_path)?)?
                 .contents()
                 .to_vec(),
         ),
         PrivateKeyDer::try_from(
-            pem::parse(&fs::read_to_string(KEY_PATH)?)?
+            pem::parse(&fs::read_to_string(key_path)?)?
                 .contents()
                 .to_vec(),
         )
         .map_err(|e| anyhow!("Failed to parse private key: {e}"))?,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Creates an empty temporary directory unique to this process and `name`.
+    fn temp_dir(name: &str) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("prattle-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn malformed_cert_is_regenerated() -> Result<()> {
+        let dir = temp_dir("malformed-cert")?;
+        let (cert_path, key_path) = (dir.join(CERT_PATH), dir.join(KEY_PATH));
+
+        create_config_at(&cert_path, &key_path, true)?;
+        let original_key = fs::read_to_string(&key_path)?;
+
+        // Simulate a half-written certificate file
+        fs::write(&cert_path, "-----BEGIN CERTIFICATE-----\ngarbage")?;
+
+        create_config_at(&cert_path, &key_path, true)?;
+        load_cert_and_key(&cert_path, &key_path)?;
+        assert_ne!(fs::read_to_string(&key_path)?, original_key);
+
+        // The regenerated files are loaded as-is next time
+        let regenerated_key = fs::read_to_string(&key_path)?;
+        create_config_at(&cert_path, &key_path, true)?;
+        assert_eq!(fs::read_to_string(&key_path)?, regenerated_key);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_cert_is_an_error_without_regeneration() -> Result<()> {
+        let dir = temp_dir("malformed-cert-no-regen")?;
+        let (cert_path, key_path) = (dir.join(CERT_PATH), dir.join(KEY_PATH));
+
+        create_config_at(&cert_path, &key_path, true)?;
+        fs::write(&cert_path, "garbage")?;
+
+        assert!(create_config_at(&cert_path, &key_path, false).is_err());
+        assert_eq!(fs::read_to_string(&cert_path)?, "garbage");
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
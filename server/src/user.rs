@@ -0,0 +1,130 @@
+use crate::state::ControlMessage;
+use std::{net::SocketAddr, time::Instant};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Shared information about an online user. It is removed from `SharedState::users` as soon as the
+/// user leaves, so nothing is kept for departed users other than when they were last active (in
+/// the bounded `SeenLog`).
+pub struct UserInfo {
+    /// When the user chose their username.
+    pub joined_at: Instant,
+
+    /// When the user last sent a message or action, or `joined_at` if they haven't yet.
+    pub last_seen: Instant,
+
+    /// The user's away message if they are away, which may be empty if no message was given.
+    pub away: Option<String>,
+
+    /// Whether the user is an operator, either by authenticating with the admin token or by being
+    /// granted operator status by another operator.
+    pub is_admin: bool,
+
+    /// The sender half of the channel for instructions addressed to this user's handler alone.
+    pub control_tx: UnboundedSender<ControlMessage>,
+
+    /// The address the user connected from.
+    pub addr: SocketAddr,
+
+    /// The color from `COLORS` that clients should show the user's name in, if they chose one.
+    pub color: Option<&'static str>,
+
+    /// The token that lets the user reclaim their username after reconnecting, if reconnect
+    /// tokens are enabled.
+    pub reconnect_token: Option<String>,
+}
+
+impl UserInfo {
+    /// Creates the information for a user who just joined from `addr`, whose handler listens on
+    /// `control_tx`.
+    pub fn new(control_tx: UnboundedSender<ControlMessage>, addr: SocketAddr) -> Self {
+        let now = Instant::now();
+
+        Self {
+            joined_at: now,
+            last_seen: now,
+            away: None,
+            is_admin: false,
+            control_tx,
+            addr,
+            color: None,
+            reconnect_token: None,
+        }
+    }
+
+    /// Records that the user sent a message or action at `now`, which also ends their away status.
+    /// Returns whether they were away.
+    pub fn record_activity(&mut self, now: Instant) -> bool {
+        self.last_seen = now;
+        self.away.take().is_some()
+    }
+
+    /// How the user, named `username`, appears in the `/who` listing.
+    pub fn who_entry(&self, username: &str) -> String {
+        match self.away.as_deref() {
+            None => username.to_string(),
+            Some("") => format!("{username} (away)"),
+            Some(away_msg) => format!("{username} (away: {away_msg})"),
+        }
+    }
+
+    /// The automatic reply to a private message for the user, named `username`, if they are away.
+    pub fn away_reply(&self, username: &str) -> Option<String> {
+        self.away.as_deref().map(|away_msg| {
+            if away_msg.is_empty() {
+                format!("{username} is away\n")
+            } else {
+                format!("{username} is away: {away_msg}\n")
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    fn user() -> UserInfo { UserInfo::new(mpsc::unbounded_channel().0, ([127, 0, 0, 1], 0).into()) }
+
+    #[test]
+    fn new_users_were_last_seen_when_they_joined() {
+        let user = user();
+
+        assert_eq!(user.last_seen, user.joined_at);
+        assert!(user.away.is_none());
+        assert!(!user.is_admin);
+    }
+
+    #[test]
+    fn activity_updates_last_seen_and_ends_away_status() {
+        let mut user = user();
+        let later = user.joined_at + Duration::from_secs(5);
+
+        user.away = Some(String::from("lunch"));
+        assert!(user.record_activity(later));
+        assert_eq!(user.last_seen, later);
+        assert!(user.away.is_none());
+
+        // Not away anymore
+        assert!(!user.record_activity(later + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn who_entries_and_auto_replies_show_the_away_message() {
+        let mut user = user();
+        assert_eq!(user.who_entry("alice"), "alice");
+        assert_eq!(user.away_reply("alice"), None);
+
+        user.away = Some(String::new());
+        assert_eq!(user.who_entry("alice"), "alice (away)");
+        assert_eq!(user.away_reply("alice").as_deref(), Some("alice is away\n"));
+
+        user.away = Some(String::from("lunch"));
+        assert_eq!(user.who_entry("alice"), "alice (away: lunch)");
+        assert_eq!(
+            user.away_reply("alice").as_deref(),
+            Some("alice is away: lunch\n")
+        );
+    }
+}
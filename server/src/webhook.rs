@@ -0,0 +1,199 @@
+use crate::{
+    event::{ChatEvent, EventKind},
+    fanout::Subscription,
+    state::SharedState,
+};
+use anyhow::{Context, Result, bail};
+use std::{str::FromStr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tracing::warn;
+
+/// The time allowed for each webhook request, including connecting and reading the response's
+/// status line.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A plain HTTP URL that events are posted to, e.g. `http://127.0.0.1:9000/hooks/prattle`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    /// The host and port to connect to.
+    authority: String,
+
+    /// The path (and query, if any) to post to, starting with a slash.
+    path: String,
+}
+
+impl FromStr for Endpoint {
+    type Err = anyhow::Error;
+
+    /// Parses an `http://` URL, using port 80 if none is given. HTTPS is not supported, so webhooks
+    /// should be sent to a local relay if they need to cross a network.
+    fn from_str(url: &str) -> Result<Self> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            bail!("Webhook URL must start with http:// (got {url})");
+        };
+
+        let (authority, path) = rest
+            .find('/')
+            .map_or((rest, "/"), |slash| rest.split_at(slash));
+
+        if authority.is_empty() {
+            bail!("Webhook URL has no host (got {url})");
+        }
+
+        // Bracketed IPv6 addresses contain colons even without a port
+        let authority = if authority.ends_with(']') || !authority.contains(':') {
+            format!("{authority}:80")
+        } else {
+            authority.to_string()
+        };
+
+        Ok(Self { authority, path: path.to_string() })
+    }
+}
+
+/// Posts a JSON payload to `endpoint` for each join, leave, and message received on
+/// `subscription`, until the task is aborted. Failed requests are logged and skipped. If the
+/// webhook falls so far behind that it is evicted from the fanout, it subscribes again and
+/// continues with new events.
+pub async fn run(endpoint: Endpoint, mut subscription: Subscription, state: Arc<SharedState>) {
+    loop {
+        let Some(event) = subscription.recv().await else {
+            warn!("Webhook fell behind and skipped some events");
+            subscription = state.fanout.subscribe();
+            continue;
+        };
+
+        let Some(payload) = payload(&event) else { continue };
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, post(&endpoint, &payload)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Failed to send webhook: {e}"),
+            Err(_) => warn!("Webhook request timed out"),
+        }
+    }
+}
+
+/// Creates the JSON payload describing `event`, or `None` if it is not sent to webhooks.
+fn payload(event: &ChatEvent) -> Option<String> {
+    let (kind, user) = match &event.kind {
+        EventKind::Message => ("message", event.sender.as_deref()?),
+        EventKind::Join(username) => ("join", username.as_str()),
+        EventKind::Leave(username) => ("leave", username.as_str()),
+        EventKind::Notice => return None,
+    };
+
+    Some(
+        serde_json::json!({ "event": kind, "user": user, "text": event.line.trim_end() })
+            .to_string(),
+    )
+}
+
+/// Sends `body` to `endpoint` in a single HTTP POST request, returning an error unless the
+/// response has a success status.
+async fn post(endpoint: &Endpoint, body: &str) -> Result<()> {
+    let mut stream = BufReader::new(TcpStream::connect(&endpoint.authority).await?);
+
+    stream
+        .get_mut()
+        .write_all(
+            format!(
+                "POST {} HTTP/1.1\r\n\
+                Host: {}\r\n\
+                Content-Type: application/json\r\n\
+                Content-Length: {}\r\n\
+                Connection: close\r\n\r\n{body}",
+                endpoint.path,
+                endpoint.authority,
+                body.len()
+            )
+            .as_bytes(),
+        )
+        .await?;
+
+    let mut status_line = String::new();
+    stream.read_line(&mut status_line).await?;
+
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .context("Malformed webhook response")?;
+
+    if !status.starts_with('2') {
+        bail!("Webhook responded with status {status}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_http_urls() -> Result<()> {
+        assert_eq!(
+            "http://127.0.0.1:9000/hooks/prattle?key=abc".parse::<Endpoint>()?,
+            Endpoint {
+                authority: String::from("127.0.0.1:9000"),
+                path: String::from("/hooks/prattle?key=abc"),
+            }
+        );
+        assert_eq!(
+            "http://relay.local".parse::<Endpoint>()?,
+            Endpoint { authority: String::from("relay.local:80"), path: String::from("/") }
+        );
+        assert_eq!(
+            "http://[::1]/hook".parse::<Endpoint>()?,
+            Endpoint { authority: String::from("[::1]:80"), path: String::from("/hook") }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unsupported_urls() {
+        for url in [
+            "https://example.com/hook",
+            "example.com",
+            "http://",
+            "http:///hook",
+        ] {
+            assert!(
+                url.parse::<Endpoint>().is_err(),
+                "expected an error for {url}"
+            );
+        }
+    }
+
+    #[test]
+    fn payloads_describe_joins_leaves_and_messages() {
+        assert_eq!(
+            payload(&ChatEvent::join(
+                "alice",
+                String::from("* alice joined the server\n")
+            )),
+            Some(String::from(
+                r#"{"event":"join","text":"* alice joined the server","user":"alice"}"#
+            ))
+        );
+        assert_eq!(
+            payload(&ChatEvent::leave("alice", String::from("* alice left\n"))),
+            Some(String::from(
+                r#"{"event":"leave","text":"* alice left","user":"alice"}"#
+            ))
+        );
+        assert_eq!(
+            payload(&ChatEvent::from_user("bob", String::from("bob: hi\n"))),
+            Some(String::from(
+                r#"{"event":"message","text":"bob: hi","user":"bob"}"#
+            ))
+        );
+        assert_eq!(
+            payload(&ChatEvent::notice(String::from("[ANNOUNCEMENT] hi\n"))),
+            None
+        );
+    }
+}
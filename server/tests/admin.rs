@@ -0,0 +1,566 @@
+mod common;
+
+use crate::common::{test_client::TestClient, test_server, tokio_test};
+use anyhow::{Context, Result};
+use prattle_server::config::Config;
+use std::time::Duration;
+
+/// The admin token used by test servers in this module.
+const ADMIN_TOKEN: &str = "correct horse battery staple";
+
+/// Creates a server config with the test admin token.
+fn admin_config() -> Config {
+    Config { admin_token: Some(ADMIN_TOKEN.into()), ..Config::default() }
+}
+
+#[test]
+fn admin_token_is_required_to_become_operator() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Operator commands are rejected before authenticating
+        bob.send_line("/op bob").await?;
+        bob.read_line_assert_contains("Permission denied").await?;
+
+        // A wrong token is rejected
+        bob.send_line("/admin wrong").await?;
+        bob.read_line_assert_contains("Invalid admin token").await?;
+        bob.send_line("/op bob").await?;
+        bob.read_line_assert_contains("Permission denied").await?;
+
+        // Nobody else saw anything
+        assert!(alice.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn admin_token_is_rejected_when_not_configured() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("Invalid admin token")
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn operators_can_grant_and_revoke_operator_status() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        let mut charlie = TestClient::connect_with_username("charlie", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+        alice.read_line_assert_contains("charlie joined").await?;
+        bob.read_line_assert_contains("charlie joined").await?;
+
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("You are now an operator")
+            .await?;
+
+        // Granting operator status is broadcast to everyone
+        alice.send_line("/op bob").await?;
+        for client in [&mut alice, &mut bob, &mut charlie] {
+            client
+                .read_line_assert_contains("bob is now an operator")
+                .await?;
+        }
+
+        // The new operator can use operator commands
+        bob.send_line("/op charlie").await?;
+        for client in [&mut alice, &mut bob, &mut charlie] {
+            client
+                .read_line_assert_contains("charlie is now an operator")
+                .await?;
+        }
+
+        // Revoking operator status is broadcast to everyone
+        alice.send_line("/deop bob").await?;
+        for client in [&mut alice, &mut bob, &mut charlie] {
+            client
+                .read_line_assert_contains("bob is no longer an operator")
+                .await?;
+        }
+
+        // The former operator can no longer use operator commands
+        bob.send_line("/deop charlie").await?;
+        bob.read_line_assert_contains("Permission denied").await?;
+
+        // Granting or revoking redundantly or for unknown users is rejected privately
+        alice.send_line("/op charlie").await?;
+        alice
+            .read_line_assert_contains("charlie is already an operator")
+            .await?;
+        alice.send_line("/deop bob").await?;
+        alice
+            .read_line_assert_contains("bob is not an operator")
+            .await?;
+        alice.send_line("/op nobody").await?;
+        alice.read_line_assert_contains("No such user").await?;
+        assert!(charlie.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn non_operators_cannot_kick() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        bob.send_line("/kick alice").await?;
+        bob.read_line_assert_contains("Permission denied").await?;
+
+        // The target is still connected and nobody else saw anything
+        assert!(alice.read_line_assert_contains("").await.is_err());
+        bob.send_line("still here").await?;
+        alice.read_line_assert_contains("bob: still here").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn operators_can_kick_users() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        let mut charlie = TestClient::connect_with_username("charlie", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+        alice.read_line_assert_contains("charlie joined").await?;
+        bob.read_line_assert_contains("charlie joined").await?;
+
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("You are now an operator")
+            .await?;
+
+        // Kicking yourself or unknown users is rejected
+        alice.send_line("/kick alice").await?;
+        alice
+            .read_line_assert_contains("cannot kick yourself")
+            .await?;
+        alice.send_line("/kick nobody").await?;
+        alice.read_line_assert_contains("No such user").await?;
+
+        // The target is told who kicked them and disconnected
+        alice.send_line("/kick bob").await?;
+        bob.read_line_assert_contains("You were kicked by alice")
+            .await?;
+        bob.graceful_disconnect().await?;
+
+        // Everyone else sees the leave notice
+        for client in [&mut alice, &mut charlie] {
+            client.read_line_assert_contains("* bob left").await?;
+        }
+
+        // The username is available again
+        TestClient::connect_with_username("bob", &addr).await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn operators_can_broadcast_announcements() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("You are now an operator")
+            .await?;
+
+        alice
+            .send_line("/announce maintenance in 10 minutes")
+            .await?;
+        for client in [&mut alice, &mut bob] {
+            assert_eq!(
+                client.read_line_assert_contains("ANNOUNCEMENT").await?,
+                "*** ANNOUNCEMENT: maintenance in 10 minutes ***\n"
+            );
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn non_operators_cannot_broadcast_announcements() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        bob.send_line("/announce free pizza").await?;
+        bob.read_line_assert_contains("Permission denied").await?;
+
+        // Nobody else saw anything
+        assert!(alice.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn operators_can_mute_and_unmute_users() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        let mut charlie = TestClient::connect_with_username("charlie", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+        alice.read_line_assert_contains("charlie joined").await?;
+        bob.read_line_assert_contains("charlie joined").await?;
+
+        // Muting requires operator status
+        bob.send_line("/mute charlie").await?;
+        bob.read_line_assert_contains("Permission denied").await?;
+
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("You are now an operator")
+            .await?;
+
+        alice.send_line("/mute bob").await?;
+        alice.read_line_assert_contains("bob is now muted").await?;
+        bob.read_line_assert_contains("You have been muted").await?;
+
+        // Bob's messages and actions don't reach anyone
+        bob.send_line("can anyone hear me?").await?;
+        bob.read_line_assert_contains("You are muted").await?;
+        bob.send_line("/action shouts").await?;
+        bob.read_line_assert_contains("You are muted").await?;
+        assert!(alice.read_line_assert_contains("").await.is_err());
+        assert!(charlie.read_line_assert_contains("").await.is_err());
+
+        // Unmuting lets Bob's messages through again
+        alice.send_line("/unmute bob").await?;
+        alice
+            .read_line_assert_contains("bob is no longer muted")
+            .await?;
+        bob.read_line_assert_contains("You are no longer muted")
+            .await?;
+
+        bob.send_line("back again").await?;
+        for client in [&mut alice, &mut bob, &mut charlie] {
+            client.read_line_assert_contains("bob: back again").await?;
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn mutes_survive_reconnecting() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("You are now an operator")
+            .await?;
+        alice.send_line("/mute bob").await?;
+        alice.read_line_assert_contains("bob is now muted").await?;
+
+        bob.send_line("/quit").await?;
+        bob.read_until_line_contains("Goodbye").await?;
+        bob.graceful_disconnect().await?;
+        alice.read_line_assert_contains("bob left").await?;
+
+        // The mute is kept by name, so it still applies after reconnecting
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+        bob.send_line("I'm back").await?;
+        bob.read_line_assert_contains("You are muted").await?;
+        assert!(alice.read_line_assert_contains("").await.is_err());
+
+        // Offline users can't be muted
+        alice.send_line("/mute nobody").await?;
+        alice.read_line_assert_contains("No such user").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn ping_all_summarizes_responsive_and_stalled_clients() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        let mut charlie = TestClient::connect_with_username("charlie", &addr).await?;
+        let mut dave = TestClient::connect_with_username("dave", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+        alice.read_line_assert_contains("charlie joined").await?;
+        alice.read_line_assert_contains("dave joined").await?;
+        bob.read_line_assert_contains("charlie joined").await?;
+        bob.read_line_assert_contains("dave joined").await?;
+        charlie.read_line_assert_contains("dave joined").await?;
+
+        // Probing requires operator status
+        bob.send_line("/ping-all").await?;
+        bob.read_line_assert_contains("Permission denied").await?;
+
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("You are now an operator")
+            .await?;
+        alice.send_line("/ping-all").await?;
+
+        // Bob and charlie answer the probe like a capability-aware client would, dave never does
+        for client in [&mut bob, &mut charlie] {
+            let probe = client.read_line_assert_contains("/ping ").await?;
+            let reply = prattle_client::pong_reply(&probe).context("Expected a latency probe")?;
+            client.send_line(&reply).await?;
+        }
+        dave.read_line_assert_contains("/ping ").await?;
+
+        // The operator is not probed and gets the summary after the response window
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        alice
+            .read_line_assert_contains_all(&["2/3 responded", "slowest", "possibly stalled: dave"])
+            .await?;
+
+        // When everyone responds, the summary arrives without waiting for the whole window
+        alice.send_line("/ping-all").await?;
+        for client in [&mut bob, &mut charlie, &mut dave] {
+            let probe = client.read_line_assert_contains("/ping ").await?;
+            let reply = prattle_client::pong_reply(&probe).context("Expected a latency probe")?;
+            client.send_line(&reply).await?;
+        }
+        let summary = alice.read_line_assert_contains("3/3 responded").await?;
+        assert!(!summary.contains("stalled"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn read_only_mode_blocks_everyone_but_operators() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Locking requires operator status
+        bob.send_line("/lock").await?;
+        bob.read_line_assert_contains("Permission denied").await?;
+
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("You are now an operator")
+            .await?;
+
+        alice.send_line("/lock").await?;
+        for client in [&mut alice, &mut bob] {
+            client
+                .read_line_assert_contains("** server is now read-only **")
+                .await?;
+        }
+
+        // Regular users' messages and actions are rejected privately
+        bob.send_line("Hello?").await?;
+        bob.read_line_assert_contains("read-only mode").await?;
+        bob.send_line("/action waves").await?;
+        bob.read_line_assert_contains("read-only mode").await?;
+        assert!(alice.read_line_assert_contains("").await.is_err());
+
+        // Operators can still send messages
+        alice.send_line("Maintenance starts soon").await?;
+        for client in [&mut alice, &mut bob] {
+            client
+                .read_line_assert_contains("alice: Maintenance starts soon")
+                .await?;
+        }
+
+        alice.send_line("/unlock").await?;
+        for client in [&mut alice, &mut bob] {
+            client
+                .read_line_assert_contains("** server is no longer read-only **")
+                .await?;
+        }
+
+        bob.send_line("Hello!").await?;
+        alice.read_line_assert_contains("bob: Hello!").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn operators_can_reset_cumulative_statistics() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Generate some activity
+        bob.send_line("hello").await?;
+        for client in [&mut alice, &mut bob] {
+            client.read_line_assert_contains("bob: hello").await?;
+        }
+
+        bob.send_line("/stats").await?;
+        let stats = bob.read_line_assert_contains("Online:").await?;
+        assert_eq!(
+            stats,
+            "Online: 2, connections: 2, messages: 1, message bytes: 11\n"
+        );
+
+        // Resetting requires operator status
+        bob.send_line("/stats-reset").await?;
+        bob.read_line_assert_contains("Permission denied").await?;
+
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("You are now an operator")
+            .await?;
+        alice.send_line("/stats-reset").await?;
+        alice.read_line_assert_contains("Statistics reset").await?;
+
+        // Cumulative counters are zeroed while the online count is unchanged
+        alice.send_line("/stats").await?;
+        let stats = alice.read_line_assert_contains("Online:").await?;
+        assert_eq!(
+            stats,
+            "Online: 2, connections: 0, messages: 0, message bytes: 0\n"
+        );
+
+        // Nobody else saw anything
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn whois_shows_join_time_and_address_only_to_operators() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Regular users see the join time but not the address
+        bob.send_line("/whois alice").await?;
+        let whois = bob
+            .read_line_assert_contains_all(&["alice: joined", "last active"])
+            .await?;
+        assert!(
+            whois.trim_end().ends_with("s ago"),
+            "unexpected whois: {whois}"
+        );
+        assert!(!whois.contains("127.0.0.1"));
+
+        bob.send_line("/whois nobody").await?;
+        bob.read_line_assert_contains("No such user").await?;
+
+        // Operators also see the address
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("You are now an operator")
+            .await?;
+        alice.send_line("/whois bob").await?;
+        alice
+            .read_line_assert_contains_all(&["bob: joined", "ago", "connected from 127.0.0.1:"])
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn slow_mode_makes_everyone_but_operators_wait_between_messages() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(admin_config()).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Slow mode requires operator status
+        bob.send_line("/slowmode 5").await?;
+        bob.read_line_assert_contains("Permission denied").await?;
+
+        alice.send_line(&format!("/admin {ADMIN_TOKEN}")).await?;
+        alice
+            .read_line_assert_contains("You are now an operator")
+            .await?;
+
+        alice.send_line("/slowmode 5").await?;
+        for client in [&mut alice, &mut bob] {
+            client
+                .read_line_assert_contains("** slow mode is now on: one message every 5 seconds **")
+                .await?;
+        }
+
+        // A regular user's second message is rejected privately
+        bob.send_line("first").await?;
+        for client in [&mut alice, &mut bob] {
+            client.read_line_assert_contains("bob: first").await?;
+        }
+        bob.send_line("second").await?;
+        bob.read_line_assert_contains("Slow mode: wait 5 more seconds")
+            .await?;
+        assert!(alice.read_line_assert_contains("").await.is_err());
+
+        // Operators are exempt
+        for msg in ["one", "two"] {
+            alice.send_line(msg).await?;
+            for client in [&mut alice, &mut bob] {
+                client
+                    .read_line_assert_contains(&format!("alice: {msg}"))
+                    .await?;
+            }
+        }
+
+        alice.send_line("/slowmode 0").await?;
+        for client in [&mut alice, &mut bob] {
+            client
+                .read_line_assert_contains("** slow mode is now off **")
+                .await?;
+        }
+
+        bob.send_line("second").await?;
+        alice.read_line_assert_contains("bob: second").await?;
+
+        Ok(())
+    })
+}
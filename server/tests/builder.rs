@@ -0,0 +1,102 @@
+mod common;
+
+use crate::common::{test_client::TestClient, tokio_test};
+use anyhow::Result;
+use prattle_server::{
+    config::{Config, OverflowPolicy},
+    server::ServerBuilder,
+    shutdown_signal::ShutdownHandle,
+};
+use std::time::Duration;
+
+#[test]
+fn connections_beyond_max_clients_are_rejected() -> Result<()> {
+    tokio_test(async {
+        let server = ServerBuilder::new()
+            .bind_addr("127.0.0.1:0")
+            .max_clients(2)
+            .banner("Built with ServerBuilder")
+            .bind()
+            .await?;
+        let addr = server.local_addr()?.to_string();
+        let shutdown = ShutdownHandle::new();
+        tokio::spawn(server.run(shutdown.signal()));
+
+        let mut alice = TestClient::connect(&addr).await?;
+        assert_eq!(
+            alice.read_username_prompt().await?,
+            ["Built with ServerBuilder"]
+        );
+        alice.send_line("alice").await?;
+        alice.read_until_line_contains("alice joined").await?;
+
+        // Clients that have not chosen a username yet still count
+        let mut pending = TestClient::connect(&addr).await?;
+        pending.read_username_prompt().await?;
+
+        let mut extra = TestClient::connect(&addr).await?;
+        extra.read_line_assert_contains("Server full").await?;
+
+        // A slot opens up once a client leaves
+        drop(pending);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut bob = TestClient::connect(&addr).await?;
+        bob.read_username_prompt().await?;
+        bob.send_line("bob").await?;
+        bob.read_until_line_contains("bob joined").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn clients_that_overflow_a_small_channel_cap_are_disconnected() -> Result<()> {
+    tokio_test(async {
+        // A long write timeout ensures that the stalled client is disconnected by the queue cap
+        let server = ServerBuilder::new()
+            .config(Config {
+                write_timeout: Duration::from_mins(1),
+                overflow_policy: OverflowPolicy::Disconnect,
+                ..Config::default()
+            })
+            .bind_addr("127.0.0.1:0")
+            .channel_cap(60)
+            .bind()
+            .await?;
+        let addr = server.local_addr()?.to_string();
+        let shutdown = ShutdownHandle::new();
+        tokio::spawn(server.run(shutdown.signal()));
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut stalled = TestClient::connect_with_username("stalled", &addr).await?;
+        alice.read_line_assert_contains("stalled joined").await?;
+
+        // Send enough data (~6 MB) to fill the stalled client's socket buffers, after which its
+        // queue overflows. Alice reads back each batch before sending the next, so her own queue
+        // stays within the cap.
+        let filler = "x".repeat(1000);
+
+        for batch in 0..120 {
+            for i in 0..50 {
+                alice.send_line(&format!("{batch}-{i} {filler}")).await?;
+            }
+
+            alice
+                .read_until_line_contains(&format!("alice: {batch}-49 "))
+                .await?;
+        }
+
+        // Once the stalled client catches up on what was already written, it learns that it was
+        // disconnected for falling behind
+        let mut line = String::new();
+        while !line.contains("too slow") {
+            line = stalled.read_line_assert_contains("").await?;
+        }
+        stalled.graceful_disconnect().await?;
+        alice
+            .read_until_line_contains("stalled lost connection")
+            .await?;
+
+        Ok(())
+    })
+}
@@ -0,0 +1,95 @@
+use anyhow::Result;
+use prattle_server::{config::Config, server::Server, shutdown_signal::ShutdownHandle};
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, IsCa, KeyPair};
+use rustls::{ServerConfig, pki_types::PrivateKeyDer};
+use std::{env, fs, process, sync::Arc, time::Duration};
+use tokio::io::AsyncBufReadExt;
+
+/// The amount of time to wait when connecting to or reading from the server.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runs `f` to completion on a current-thread Tokio runtime.
+fn tokio_test<F: Future<Output = Result<()>>>(f: F) -> Result<()> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(f)
+}
+
+/// Generates a CA and a certificate for `hostname` signed by it, returning the CA certificate in
+/// PEM format and a server config using the signed certificate.
+fn ca_signed_config(hostname: &str) -> Result<(String, Arc<ServerConfig>)> {
+    let mut ca_params = CertificateParams::new(Vec::<String>::new())?;
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate()?)?;
+
+    let key = KeyPair::generate()?;
+    let cert = CertificateParams::new(vec![hostname.to_string()])?.signed_by(&key, &ca)?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![cert.der().clone()],
+            PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+        )?;
+
+    Ok((ca.pem(), Arc::new(config)))
+}
+
+/// Spawns a server using `tls_config`, then tries to connect to it as `localhost` while trusting
+/// the CA certificate in `ca_pem`. Returns the first line received if the connection succeeded.
+async fn connect_trusting(ca_pem: &str, tls_config: Arc<ServerConfig>) -> Result<String> {
+    let server = Server::bind("127.0.0.1:0", tls_config, Config::default()).await?;
+    let addr = format!("localhost:{}", server.local_addr()?.port());
+    let shutdown = ShutdownHandle::new();
+    tokio::spawn(server.run(shutdown.signal()));
+
+    let ca_path = env::temp_dir().join(format!(
+        "prattle-ca-{}-{}.crt",
+        process::id(),
+        addr.replace(':', "-")
+    ));
+    fs::write(&ca_path, ca_pem)?;
+    let connect_res =
+        prattle_client::connect_with_ca(Some(&ca_path.to_string_lossy()), &addr, TIMEOUT).await;
+    fs::remove_file(&ca_path)?;
+
+    let (mut reader, _writer) = connect_res?;
+    let mut line = String::new();
+    tokio::time::timeout(TIMEOUT, reader.read_line(&mut line)).await??;
+
+    shutdown.trigger();
+    Ok(line)
+}
+
+#[test]
+fn ca_signed_certs_for_the_right_hostname_are_accepted() -> Result<()> {
+    tokio_test(async {
+        let (ca_pem, tls_config) = ca_signed_config("localhost")?;
+        let line = connect_trusting(&ca_pem, tls_config).await?;
+        assert!(line.contains("Choose a username"), "got: {line}");
+
+        Ok(())
+    })
+}
+
+#[test]
+fn ca_signed_certs_for_the_wrong_hostname_are_rejected() -> Result<()> {
+    tokio_test(async {
+        let (ca_pem, tls_config) = ca_signed_config("chat.example.com")?;
+        assert!(connect_trusting(&ca_pem, tls_config).await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn certs_signed_by_an_untrusted_ca_are_rejected() -> Result<()> {
+    tokio_test(async {
+        let (_, tls_config) = ca_signed_config("localhost")?;
+        let (other_ca_pem, _) = ca_signed_config("localhost")?;
+        assert!(connect_trusting(&other_ca_pem, tls_config).await.is_err());
+
+        Ok(())
+    })
+}
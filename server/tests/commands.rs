@@ -1,7 +1,9 @@
 mod common;
 
 use crate::common::{test_client::TestClient, test_server, tokio_test};
-use anyhow::Result;
+use anyhow::{Context, Result};
+use prattle_server::config::Config;
+use std::collections::HashSet;
 
 #[test]
 fn quit_command_sends_goodbye_message_and_broadcast() -> Result<()> {
@@ -30,6 +32,29 @@ fn quit_command_sends_goodbye_message_and_broadcast() -> Result<()> {
     })
 }
 
+#[test]
+fn quit_message_is_shown_in_the_leave_broadcast() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // The parting message is sanitized like other text
+        bob.send_line("/quit gone \x1b[31mfishing").await?;
+        bob.read_line_assert_contains("Goodbye").await?;
+        bob.graceful_disconnect().await?;
+
+        assert_eq!(
+            alice.read_line_assert_contains("bob left").await?,
+            "* bob left (gone fishing)\n"
+        );
+
+        Ok(())
+    })
+}
+
 #[test]
 fn help_command_lists_usage() -> Result<()> {
     tokio_test(async {
@@ -45,7 +70,53 @@ fn help_command_lists_usage() -> Result<()> {
         client1.send_line("/help").await?;
 
         // Should see the help block
-        let help_words = ["", "quit", "help", "who", "action", "", "message", ""];
+        let help_words = [
+            "",
+            "quit",
+            "help",
+            "who",
+            "list",
+            "count",
+            "whois",
+            "whoami",
+            "version",
+            "token",
+            "seen",
+            "time",
+            "log",
+            "away",
+            "echo",
+            "dnd",
+            "timestamps",
+            "quiet",
+            "color",
+            "sig",
+            "roll",
+            "action",
+            "msg",
+            "admin",
+            "op",
+            "deop",
+            "kick",
+            "mute",
+            "unmute",
+            "announce",
+            "ping-all",
+            "stats",
+            "stats-reset",
+            "lock",
+            "unlock",
+            "slowmode",
+            "commands",
+            "commands-detail",
+            "shrug",
+            "tableflip",
+            "unflip",
+            "lenny",
+            "",
+            "message",
+            "",
+        ];
         for word in help_words {
             client1.read_line_assert_contains(word).await?;
         }
@@ -106,6 +177,40 @@ fn who_command_lists_online_users() -> Result<()> {
     })
 }
 
+#[test]
+fn who_json_lists_online_users_as_json() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut client1 = TestClient::connect_with_username("alice", &addr).await?;
+        let _client2 = TestClient::connect_with_username("bob", &addr).await?;
+        client1.read_line_assert_contains("bob joined").await?;
+
+        // Both /who json and the /list alias produce a single parseable line
+        for input in ["/who json", "/list json"] {
+            client1.send_line(input).await?;
+            let line = client1.read_line_assert_contains("users").await?;
+            let listing = serde_json::from_str::<serde_json::Value>(&line)?;
+            let users = listing["users"]
+                .as_array()
+                .context("expected a users array")?
+                .iter()
+                .map(|user| user.as_str().context("expected a string"))
+                .collect::<Result<HashSet<_>>>()?;
+
+            assert_eq!(users, HashSet::from(["alice", "bob"]));
+        }
+
+        // The plain /list alias stays human-readable
+        client1.send_line("/list").await?;
+        client1
+            .read_line_assert_contains_all(&["Currently online:", "alice", "bob"])
+            .await?;
+
+        Ok(())
+    })
+}
+
 #[test]
 fn action_command_broadcasts_to_all_clients() -> Result<()> {
     tokio_test(async {
@@ -134,3 +239,702 @@ fn action_command_broadcasts_to_all_clients() -> Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn shrug_broadcasts_the_emote() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut client1 = TestClient::connect_with_username("alice", &addr).await?;
+        let mut client2 = TestClient::connect_with_username("bob", &addr).await?;
+        client1.read_line_assert_contains("bob joined").await?;
+
+        client1.send_line("/shrug").await?;
+        for client in [&mut client1, &mut client2] {
+            let line = client.read_line_assert_contains("alice:").await?;
+            assert_eq!(line, "alice: ¯\\_(ツ)_/¯\n");
+        }
+
+        client1.send_line("/shrug oh well").await?;
+        for client in [&mut client1, &mut client2] {
+            let line = client.read_line_assert_contains("alice:").await?;
+            assert_eq!(line, "alice: oh well ¯\\_(ツ)_/¯\n");
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn commands_lists_every_implemented_command() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+        let mut client = TestClient::connect_with_username("alice", &addr).await?;
+
+        client.send_line("/commands").await?;
+        let line = client.read_line_assert_contains("commands: ").await?;
+        let names = line
+            .trim_end()
+            .strip_prefix("commands: ")
+            .context("expected the line to start with \"commands: \"")?
+            .split(',')
+            .collect::<Vec<_>>();
+
+        // The list should match the manifest derived from the same command table
+        client.send_line("/commands-detail").await?;
+        let manifest = serde_json::from_str::<serde_json::Value>(
+            &client.read_line_assert_contains("[").await?,
+        )?;
+        let manifest_names = manifest
+            .as_array()
+            .context("expected a JSON array")?
+            .iter()
+            .map(|entry| {
+                entry["name"]
+                    .as_str()
+                    .map(|name| name.trim_start_matches('/'))
+            })
+            .collect::<Option<Vec<_>>>()
+            .context("expected every entry to have a name")?;
+
+        assert_eq!(names, manifest_names);
+
+        // Core commands should be included
+        for name in ["quit", "help", "who", "action", "commands", "shrug"] {
+            assert!(names.contains(&name), "expected {name} to be listed");
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn commands_detail_returns_a_json_manifest() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut client1 = TestClient::connect_with_username("alice", &addr).await?;
+        let mut client2 = TestClient::connect_with_username("bob", &addr).await?;
+        client1.read_line_assert_contains("bob joined").await?;
+
+        client1.send_line("/commands-detail").await?;
+        let line = client1.read_line_assert_contains("[").await?;
+
+        // The manifest should be a JSON array of objects with a name, args, and description
+        let manifest = serde_json::from_str::<serde_json::Value>(&line)?;
+        let entries = manifest.as_array().context("expected a JSON array")?;
+
+        for name in ["/quit", "/help", "/who", "/action", "/commands-detail"] {
+            let entry = entries
+                .iter()
+                .find(|entry| entry["name"] == name)
+                .with_context(|| format!("expected manifest to include {name}"))?;
+
+            assert!(entry["args"].is_string(), "expected args for {name}");
+            assert!(
+                entry["desc"].as_str().is_some_and(|desc| !desc.is_empty()),
+                "expected a non-empty description for {name}"
+            );
+        }
+
+        assert_eq!(
+            entries
+                .iter()
+                .find(|entry| entry["name"] == "/action")
+                .map(|entry| &entry["args"]),
+            Some(&serde_json::json!("<action>"))
+        );
+
+        // Client 2 should not have seen Client 1's manifest
+        assert!(client2.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn away_status_is_shown_in_who_and_cleared_by_messages() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut client1 = TestClient::connect_with_username("alice", &addr).await?;
+        let mut client2 = TestClient::connect_with_username("bob", &addr).await?;
+        client1.read_line_assert_contains("bob joined").await?;
+
+        // Client 1 goes away with a message
+        client1.send_line("/away lunch").await?;
+        client1.read_line_assert_contains("marked as away").await?;
+
+        // Client 2 sees the away status in the /who listing
+        client2.send_line("/who").await?;
+        client2
+            .read_line_assert_contains_all(&["Currently online:", "alice (away: lunch)", "bob"])
+            .await?;
+
+        // Sending a message clears the away status and broadcasts that Client 1 is back
+        client1.send_line("I'm back!").await?;
+        client2.read_line_assert_contains("alice is back").await?;
+        client2
+            .read_line_assert_contains("alice: I'm back!")
+            .await?;
+        client2.send_line("/who").await?;
+        let who_listing = client2
+            .read_line_assert_contains("Currently online")
+            .await?;
+        assert!(!who_listing.contains("away"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn away_without_message_toggles_away_status() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut client1 = TestClient::connect_with_username("alice", &addr).await?;
+        let mut client2 = TestClient::connect_with_username("bob", &addr).await?;
+        client1.read_line_assert_contains("bob joined").await?;
+
+        client1.send_line("/away").await?;
+        client1.read_line_assert_contains("marked as away").await?;
+        client2.send_line("/who").await?;
+        client2.read_line_assert_contains("alice (away)").await?;
+
+        // Control characters are stripped from the away message like from chat messages
+        client1.send_line("/away \x1b[31mlunch\x07").await?;
+        client1.read_line_assert_contains("marked as away").await?;
+        client2.send_line("/who").await?;
+        client2
+            .read_line_assert_contains("alice (away: lunch)")
+            .await?;
+
+        // Sending /away again marks Client 1 as back
+        client1.send_line("/away").await?;
+        client1.read_line_assert_contains("alice is back").await?;
+        client2.read_line_assert_contains("alice is back").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn private_messages_to_away_users_get_an_auto_reply() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line("/away lunch").await?;
+        alice.read_line_assert_contains("marked as away").await?;
+
+        // The message is still delivered, and the sender is told why there may be no reply
+        bob.send_line("/msg alice are you there?").await?;
+        bob.read_line_assert_contains("To alice (private): are you there?")
+            .await?;
+        let auto_reply = bob.read_line_assert_contains("alice is away").await?;
+        assert_eq!(auto_reply, "alice is away: lunch\n");
+        alice
+            .read_line_assert_contains("bob (private): are you there?")
+            .await?;
+
+        // Once back, no auto-reply is sent
+        alice.send_line("/away").await?;
+        alice.read_line_assert_contains("alice is back").await?;
+        bob.read_line_assert_contains("alice is back").await?;
+        bob.send_line("/msg alice welcome back").await?;
+        bob.read_line_assert_contains("To alice (private): welcome back")
+            .await?;
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn roll_command_broadcasts_results() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line("/roll 3d6").await?;
+
+        for client in [&mut alice, &mut bob] {
+            let line = client
+                .read_line_assert_contains("* alice rolls 3d6: ")
+                .await?;
+
+            // Check that the individual results are in range and add up to the total
+            let (results, total) = line
+                .trim_end()
+                .trim_start_matches("* alice rolls 3d6: ")
+                .trim_end_matches(')')
+                .split_once(" (total ")
+                .context("Missing total")?;
+            let results = results
+                .split(", ")
+                .map(str::parse::<u32>)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            assert_eq!(results.len(), 3);
+            assert!(results.iter().all(|result| (1..=6).contains(result)));
+            assert_eq!(results.iter().sum::<u32>(), total.parse::<u32>()?);
+        }
+
+        // Invalid specs are answered privately with usage information
+        alice.send_line("/roll 0d6").await?;
+        alice.read_line_assert_contains("Usage: /roll").await?;
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn time_command_replies_privately_with_uptime() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line("/time").await?;
+        let line = alice
+            .read_line_assert_contains_all(&["Server time: ", " UTC, up ", "s (since "])
+            .await?;
+
+        // The uptime is a number of seconds (or more) since the server started
+        let uptime = line
+            .split_once(", up ")
+            .and_then(|(_, rest)| rest.split_once(" (since "))
+            .context("Missing uptime")?
+            .0;
+        assert!(uptime.ends_with('s') && uptime.starts_with(|c: char| c.is_ascii_digit()));
+
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn count_replies_privately_with_the_number_of_users_online() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        alice.send_line("/count").await?;
+        assert_eq!(
+            alice.read_line_assert_contains("online").await?,
+            "1 user online\n"
+        );
+
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        let mut charlie = TestClient::connect_with_username("charlie", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+        alice.read_line_assert_contains("charlie joined").await?;
+        bob.read_line_assert_contains("charlie joined").await?;
+
+        charlie.send_line("/count").await?;
+        assert_eq!(
+            charlie.read_line_assert_contains("online").await?,
+            "3 users online\n"
+        );
+
+        assert!(alice.read_line_assert_contains("").await.is_err());
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn whoami_replies_privately_with_the_stored_username() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // Surrounding whitespace is trimmed from the chosen username
+        let mut bob = TestClient::connect(&addr).await?;
+        bob.read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        bob.send_line("  bob  ").await?;
+        bob.read_line_assert_contains_all(&["bob", "welcome"])
+            .await?;
+        bob.read_line_assert_contains("bob joined").await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        bob.send_line("/whoami").await?;
+        assert_eq!(
+            bob.read_line_assert_contains("You are").await?,
+            "You are bob\n"
+        );
+
+        alice.send_line("/whoami").await?;
+        assert_eq!(
+            alice.read_line_assert_contains("You are").await?,
+            "You are alice\n"
+        );
+
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn seen_reports_online_departed_and_unknown_users() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line("/seen bob").await?;
+        assert_eq!(
+            alice.read_line_assert_contains("bob").await?,
+            "bob is online now\n"
+        );
+
+        bob.send_line("/quit").await?;
+        bob.read_line_assert_contains("Goodbye").await?;
+        bob.graceful_disconnect().await?;
+        alice.read_line_assert_contains("bob left").await?;
+
+        alice.send_line("/seen bob").await?;
+        assert_eq!(
+            alice.read_line_assert_contains("bob").await?,
+            "bob was last seen less than a minute ago\n"
+        );
+
+        alice.send_line("/seen charlie").await?;
+        assert_eq!(
+            alice.read_line_assert_contains("charlie").await?,
+            "I've never seen anyone named charlie\n"
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn signatures_are_appended_to_messages_until_cleared() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Setting a signature is confirmed privately
+        alice.send_line("/sig (she/her)").await?;
+        alice
+            .read_line_assert_contains("Signature set to: (she/her)")
+            .await?;
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        // Messages get the signature, but actions don't
+        alice.send_line("hello").await?;
+        bob.read_line_assert_contains("alice: hello -- (she/her)\n")
+            .await?;
+        alice
+            .read_line_assert_contains("alice: hello -- (she/her)")
+            .await?;
+
+        alice.send_line("/action waves").await?;
+        bob.read_line_assert_contains("* alice waves\n").await?;
+        alice.read_line_assert_contains("* alice waves\n").await?;
+
+        // Signatures that are too long are rejected, keeping the old one
+        alice.send_line(&format!("/sig {}", "x".repeat(33))).await?;
+        alice
+            .read_line_assert_contains("Signature too long")
+            .await?;
+
+        // Clearing the signature is also confirmed privately
+        alice.send_line("/sig").await?;
+        alice.read_line_assert_contains("Signature cleared").await?;
+
+        alice.send_line("bye").await?;
+        bob.read_line_assert_contains("alice: bye\n").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn color_command_announces_valid_colors_and_rejects_others() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Unknown colors are rejected privately
+        alice.send_line("/color chartreuse").await?;
+        alice
+            .read_line_assert_contains_all(&["Unknown color: chartreuse", "red", "cyan"])
+            .await?;
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        // Valid colors (ignoring case) are announced to everyone for clients to track
+        alice.send_line("/color Blue").await?;
+        bob.read_line_assert_contains("\x05COLOR alice blue\n")
+            .await?;
+        alice
+            .read_until_line_contains("Your name is now shown in blue")
+            .await?;
+
+        // Users who join later are told about colors chosen before they joined
+        let mut charlie = TestClient::connect(&addr).await?;
+        charlie.read_line_assert_contains("Choose").await?;
+        charlie.send_line("charlie").await?;
+        charlie.read_line_assert_contains("welcome").await?;
+        charlie
+            .read_line_assert_contains("\x05COLOR alice blue\n")
+            .await?;
+        charlie.read_line_assert_contains("charlie joined").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn dnd_stops_broadcasts_until_turned_off() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line("/dnd on").await?;
+        alice
+            .read_line_assert_contains("Do not disturb is now on")
+            .await?;
+
+        // Neither messages nor notices reach alice, but her commands are still answered
+        bob.send_line("Anyone there?").await?;
+        bob.read_line_assert_contains("bob: Anyone there?").await?;
+        let charlie = TestClient::connect_with_username("charlie", &addr).await?;
+        bob.read_line_assert_contains("charlie joined").await?;
+        alice.send_line("/time").await?;
+        alice.read_line_assert_contains("Server time").await?;
+
+        alice.send_line("/dnd off").await?;
+        alice
+            .read_line_assert_contains("Do not disturb is now off")
+            .await?;
+
+        // Messages sent during do not disturb are not replayed, but new ones arrive again
+        bob.send_line("Welcome back").await?;
+        alice.read_line_assert_contains("bob: Welcome back").await?;
+
+        drop(charlie);
+        Ok(())
+    })
+}
+
+/// Whether `line` starts with a `[HH:MM:SS] ` timestamp.
+fn is_timestamped(line: &str) -> bool {
+    line.len() > 11
+        && line.as_bytes()[0] == b'['
+        && line.get(9..11) == Some("] ")
+        && line[1..9]
+            .char_indices()
+            .all(|(i, c)| if i % 3 == 2 { c == ':' } else { c.is_ascii_digit() })
+}
+
+#[test]
+fn timestamps_are_shown_only_to_clients_that_turn_them_on() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        bob.send_line("/timestamps on").await?;
+        bob.read_line_assert_contains("Timestamps are now on")
+            .await?;
+
+        alice.send_line("hello").await?;
+        assert!(!is_timestamped(
+            &alice.read_line_assert_contains("alice: hello").await?
+        ));
+        assert!(is_timestamped(
+            &bob.read_line_assert_contains("alice: hello").await?
+        ));
+
+        bob.send_line("/timestamps off").await?;
+        bob.read_line_assert_contains("Timestamps are now off")
+            .await?;
+
+        alice.send_line("again").await?;
+        alice.read_line_assert_contains("alice: again").await?;
+        assert!(!is_timestamped(
+            &bob.read_line_assert_contains("alice: again").await?
+        ));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn timestamps_can_be_on_by_default() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) =
+            test_server::spawn_with_config(Config { timestamps: true, ..Config::default() })
+                .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        alice.send_line("hello").await?;
+        assert!(is_timestamped(
+            &alice.read_line_assert_contains("alice: hello").await?
+        ));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn private_messages_reach_only_the_recipient() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+        let mut carol = TestClient::connect_with_username("carol", &addr).await?;
+        alice.read_line_assert_contains("carol joined").await?;
+        bob.read_line_assert_contains("carol joined").await?;
+
+        alice.send_line("/msg bob lunch at noon?").await?;
+        alice
+            .read_line_assert_contains("To bob (private): lunch at noon?")
+            .await?;
+        bob.read_line_assert_contains("alice (private): lunch at noon?")
+            .await?;
+
+        // Without offline messages, only online users can be messaged
+        alice.send_line("/msg dave are you there?").await?;
+        alice.read_line_assert_contains("No such user").await?;
+        alice.send_line("/msg bob").await?;
+        alice
+            .read_line_assert_contains("Usage: /msg <user> <message>")
+            .await?;
+
+        // Carol never saw any of it
+        bob.send_line("public").await?;
+        carol.read_line_assert_contains("bob: public").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn private_messages_to_offline_users_are_delivered_when_they_join() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) =
+            test_server::spawn_with_config(Config { offline_msgs: true, ..Config::default() })
+                .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        alice.send_line("/msg bob lunch at noon?").await?;
+        alice
+            .read_line_assert_contains("bob is offline; your message will be delivered")
+            .await?;
+        alice.send_line("/msg bob or one?").await?;
+        alice.read_line_assert_contains("bob is offline").await?;
+
+        let mut bob = TestClient::connect(&addr).await?;
+        bob.read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        bob.send_line("bob").await?;
+        bob.read_line_assert_contains("welcome").await?;
+        bob.read_line_assert_contains("You have 2 offline messages")
+            .await?;
+        bob.read_line_assert_contains("UTC] alice (private): lunch at noon?")
+            .await?;
+        bob.read_line_assert_contains("alice (private): or one?")
+            .await?;
+        bob.read_line_assert_contains("bob joined").await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Messages are only delivered once
+        bob.send_line("/quit").await?;
+        bob.graceful_disconnect().await?;
+        alice.read_line_assert_contains("bob left").await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        bob.send_line("/whoami").await?;
+        bob.read_line_assert_contains("You are bob").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn version_replies_privately_with_the_crate_version() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line("/version").await?;
+        assert_eq!(
+            alice.read_line_assert_contains("Prattle server").await?,
+            format!("Prattle server v{}\n", env!("CARGO_PKG_VERSION"))
+        );
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn quiet_joins_hides_join_and_leave_notices_but_not_messages() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line("/quiet joins on").await?;
+        alice
+            .read_line_assert_contains("Join and leave notices are now hidden")
+            .await?;
+
+        let mut charlie = TestClient::connect_with_username("charlie", &addr).await?;
+        bob.read_line_assert_contains("charlie joined").await?;
+        charlie.send_line("/quit").await?;
+        charlie.graceful_disconnect().await?;
+        bob.read_line_assert_contains("charlie left").await?;
+
+        // The next line alice sees is bob's message, not charlie's comings and goings
+        bob.send_line("quiet in here").await?;
+        alice
+            .read_line_assert_contains("bob: quiet in here")
+            .await?;
+
+        alice.send_line("/quiet joins off").await?;
+        alice
+            .read_line_assert_contains("Join and leave notices are now shown")
+            .await?;
+        let _dave = TestClient::connect_with_username("dave", &addr).await?;
+        alice.read_line_assert_contains("dave joined").await?;
+
+        Ok(())
+    })
+}
@@ -11,10 +11,15 @@ const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
 /// The amount of time to wait when reading from the server.
 const READ_TIMEOUT: Duration = Duration::from_secs(1);
 
+/// The prefix of the line telling a joining client its username, which the helpers below skip
+/// (like the real client hides it) and remember, so that tests don't all have to expect it.
+const USERNAME_PREFIX: &str = "\x05USERNAME ";
+
 /// Helper struct to manage a test client connection.
 pub struct TestClient {
     reader: prattle_client::ClientReader,
     writer: prattle_client::ClientWriter,
+    username: Option<String>,
 }
 
 impl TestClient {
@@ -23,7 +28,7 @@ impl TestClient {
         let (reader, writer) =
             prattle_client::connect(prattle_server::tls::CERT_PATH, addr, CONNECT_TIMEOUT).await?;
 
-        Ok(Self { reader, writer })
+        Ok(Self { reader, writer, username: None })
     }
 
     /// Connects to the server and completes username selection.
@@ -31,9 +36,7 @@ impl TestClient {
         let mut client = Self::connect(addr).await?;
 
         // Read the "Choose a username:" prompt
-        client
-            .read_line_assert_contains_all(&["Choose", "username"])
-            .await?;
+        client.read_username_prompt().await?;
 
         // Send username
         client.send_line(username).await?;
@@ -53,9 +56,45 @@ impl TestClient {
     pub async fn send_line(&mut self, msg: &str) -> Result<()> {
         self.writer.write_all(msg.as_bytes()).await?;
         self.writer.write_all(b"\n").await?;
+        self.writer.flush().await?;
         Ok(())
     }
 
+    /// Sends raw bytes to the server without a trailing newline.
+    #[allow(dead_code)] // Not actually dead code
+    pub async fn send_raw(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writer.write_all(bytes).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// The username the server last told this client it joined with, if any.
+    #[allow(dead_code)] // Not actually dead code
+    pub fn username(&self) -> Option<&str> { self.username.as_deref() }
+
+    /// Reads the next line from the server into `line` within `timeout`, skipping and remembering
+    /// username lines.
+    async fn read_line(&mut self, line: &mut String, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let start = line.len();
+
+            tokio::time::timeout(remaining, self.reader.read_line(line))
+                .await
+                .context("Timeout reading line")??;
+
+            match line[start..].strip_prefix(USERNAME_PREFIX) {
+                Some(username) => {
+                    self.username = Some(username.trim_end().to_string());
+                    line.truncate(start);
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
     /// Reads a line from the server with a timeout and asserts that it contains the specified
     /// substring.
     pub async fn read_line_assert_contains(&mut self, expected: &str) -> Result<String> {
@@ -66,10 +105,7 @@ impl TestClient {
     /// substrings.
     pub async fn read_line_assert_contains_all(&mut self, expected: &[&str]) -> Result<String> {
         let mut line = String::new();
-
-        tokio::time::timeout(READ_TIMEOUT, self.reader.read_line(&mut line))
-            .await
-            .context("Timeout reading line")??;
+        self.read_line(&mut line, READ_TIMEOUT).await?;
 
         for substr in expected {
             assert!(
@@ -81,6 +117,34 @@ impl TestClient {
         Ok(line)
     }
 
+    /// Reads the "Choose a username:" prompt, first consuming the banner if the server sends one.
+    /// Returns the lines of the banner, if any.
+    pub async fn read_username_prompt(&mut self) -> Result<Vec<String>> {
+        let mut banner = Vec::new();
+        let mut line = String::new();
+
+        // The banner is separated from the prompt by a blank line
+        loop {
+            line.clear();
+            self.read_line(&mut line, READ_TIMEOUT).await?;
+
+            if banner.is_empty() && line.contains("Choose") && line.contains("username") {
+                return Ok(banner);
+            }
+
+            if line.trim_end_matches(['\r', '\n']).is_empty() {
+                break;
+            }
+
+            banner.push(line.trim_end_matches(['\r', '\n']).to_string());
+        }
+
+        self.read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+
+        Ok(banner)
+    }
+
     /// Reads lines from the server until one contains the expected substring, or times out.
     ///
     /// This is useful when other messages might be arbitrarily interleaved (e.g., "left the server"
@@ -93,9 +157,9 @@ impl TestClient {
         loop {
             let remaining = deadline.saturating_duration_since(Instant::now());
 
-            tokio::time::timeout(remaining, self.reader.read_line(&mut line))
+            self.read_line(&mut line, remaining)
                 .await
-                .context("Timeout reading lines until match")??;
+                .context("Timeout reading lines until match")?;
 
             if line.contains(expected) {
                 break Ok(line);
@@ -1,69 +1,67 @@
 use crate::common::TEST_LOG_LEVEL;
 use anyhow::Result;
-use std::time::Duration;
-use tokio::{
-    net::TcpListener,
-    sync::oneshot::{self, Sender},
-    task::JoinHandle,
-};
+use prattle_server::{config::Config, shutdown_signal::ShutdownHandle};
+use tokio::task::JoinHandle;
 
-/// Spawns the server on a random available port, returning the address, a `Sender` to send the
-/// shutdown signal, and a `JoinHandle` to the server task.
+/// Spawns the server on a random available port, returning the address, a `ShutdownHandle` to
+/// trigger shutdown, and a `JoinHandle` to the server task.
 #[allow(dead_code)] // Not actually dead code
-pub async fn spawn_with_shutdown() -> Result<(String, Sender<()>, JoinHandle<()>)> {
-    let (shutdown_tx, shutdown_rx) = oneshot::channel();
-
-    let (addr, handle) = inner_spawn_with_shutdown(async {
-        shutdown_rx.await.ok();
-    })
-    .await?;
+pub async fn spawn_with_shutdown() -> Result<(String, ShutdownHandle, JoinHandle<()>)> {
+    spawn_with_config(Config::default()).await
+}
 
-    Ok((addr, shutdown_tx, handle))
+/// Spawns the server with the options in `config` on a random available port, returning the
+/// address, a `ShutdownHandle` to trigger shutdown, and a `JoinHandle` to the server task.
+#[allow(dead_code)] // Not actually dead code
+pub async fn spawn_with_config(config: Config) -> Result<(String, ShutdownHandle, JoinHandle<()>)> {
+    let shutdown = ShutdownHandle::new();
+    let (addr, handle) = inner_spawn_with_shutdown(config, shutdown.clone()).await?;
+    Ok((addr, shutdown, handle))
 }
 
 /// Spawns the server with the default signal handler on a random available port and returns the
 /// address.
 #[allow(dead_code)] // Not actually dead code
 pub async fn spawn() -> Result<String> {
-    Ok(
-        inner_spawn_with_shutdown(prattle_server::shutdown_signal::listen()?)
-            .await?
-            .0,
+    Ok(inner_spawn_with_shutdown(
+        Config::default(),
+        prattle_server::shutdown_signal::listen_handle()?,
     )
+    .await?
+    .0)
 }
 
-/// Spawns the server with `shutdown_signal` as the shutdown signal on a random available port and
-/// returns the address and a `JoinHandle` to the server task.
+/// Spawns the server with the options in `config`, shutting down (or forcing shutdown) when
+/// `shutdown` is triggered, on a random available port and returns the address and a `JoinHandle`
+/// to the server task.
 async fn inner_spawn_with_shutdown(
-    shutdown_signal: impl Future<Output = ()> + Send + 'static,
+    config: Config,
+    shutdown: ShutdownHandle,
 ) -> Result<(String, JoinHandle<()>)> {
     // Ignore the error if the tracing subscriber was already initialized in another test
     let _ = prattle_server::logger::init_with_default(TEST_LOG_LEVEL);
 
-    // Bind to port 0 to get a random available port and immediately drop the listener so the port
-    // is available for the server to bind
-    let addr = TcpListener::bind("127.0.0.1:0")
-        .await?
-        .local_addr()?
-        .to_string();
-
-    // Clone addr for the spawned task
-    let server_addr = addr.clone();
+    // Bind to port 0 to get a random available port, binding before spawning so the server is
+    // ready to accept connections as soon as this function returns
+    let server = prattle_server::server::Server::bind(
+        "127.0.0.1:0",
+        prattle_server::tls::create_config()?,
+        config,
+    )
+    .await?;
 
-    // Create TLS configuration for the test server
-    let tls_config = prattle_server::tls::create_config()?;
+    let addr = server.local_addr()?.to_string();
 
     // Spawn the server in a background task
     let handle = tokio::spawn(async move {
-        if let Err(e) = prattle_server::server::run(&server_addr, tls_config, shutdown_signal).await
+        if let Err(e) = server
+            .run_with_force(shutdown.signal(), shutdown.force_signal())
+            .await
         {
             // `eprintln!` instead of `error!` because logging may be off in tests
             eprintln!("Error running test server: {e}");
         }
     });
 
-    // Give the server a moment to start and bind
-    tokio::time::sleep(Duration::from_millis(100)).await;
-
     Ok((addr, handle))
 }
@@ -2,6 +2,11 @@ mod common;
 
 use crate::common::{test_client::TestClient, test_server, tokio_test};
 use anyhow::Result;
+use prattle_server::config::{
+    Config, ConfirmUsername, MAX_USERNAME_ATTEMPTS, MAX_USERNAME_LEN, Messages,
+};
+use std::time::Duration;
+use tokio::io::AsyncBufReadExt;
 
 #[test]
 fn client_can_connect() -> Result<()> {
@@ -43,17 +48,124 @@ fn empty_usernames_are_rejected() -> Result<()> {
 }
 
 #[test]
-fn using_the_unknown_username_is_rejected() -> Result<()> {
+fn default_reserved_usernames_are_rejected_ignoring_case() -> Result<()> {
     tokio_test(async {
         let mut client = TestClient::connect(&test_server::spawn().await?).await?;
 
-        // Attempt to join with the literal string used for unknown usernames and expect an error
-        // message
+        for username in ["admin", "Server", "SYSTEM", "[UNKNOWN]", "prattlebot"] {
+            client
+                .read_line_assert_contains_all(&["Choose", "username"])
+                .await?;
+            client.send_line(username).await?;
+            client
+                .read_line_assert_contains("That username is reserved")
+                .await?;
+        }
+
+        // Names that merely contain a reserved name are fine
         client
             .read_line_assert_contains_all(&["Choose", "username"])
             .await?;
-        client.send_line("[unknown]").await?;
-        client.read_line_assert_contains("Invalid username").await?;
+        client.send_line("sysadmin").await?;
+        client
+            .read_line_assert_contains_all(&["sysadmin", "welcome"])
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn usernames_over_the_byte_limit_are_rejected() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+        let mut client = TestClient::connect(&addr).await?;
+
+        // The limit counts bytes, so a name with fewer characters can still be too long
+        for username in [
+            "a".repeat(MAX_USERNAME_LEN + 1),
+            "é".repeat(MAX_USERNAME_LEN / 2 + 1),
+        ] {
+            client
+                .read_line_assert_contains_all(&["Choose", "username"])
+                .await?;
+            client.send_line(&username).await?;
+            assert_eq!(
+                client.read_line_assert_contains("too long").await?,
+                format!("Username too long (max {MAX_USERNAME_LEN})\n")
+            );
+        }
+
+        for username in [
+            "a".repeat(MAX_USERNAME_LEN),
+            "é".repeat(MAX_USERNAME_LEN / 2),
+        ] {
+            TestClient::connect_with_username(&username, &addr).await?;
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn too_many_rejected_usernames_disconnect_the_client() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+        let _alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // Empty, reserved, and taken usernames all count as attempts
+        let bad_names = ["", "admin", "alice"].into_iter().cycle();
+
+        // A valid username on the last allowed attempt is still accepted
+        let mut bob = TestClient::connect(&addr).await?;
+        for username in bad_names.clone().take(MAX_USERNAME_ATTEMPTS - 1) {
+            bob.read_line_assert_contains_all(&["Choose", "username"])
+                .await?;
+            bob.send_line(username).await?;
+            bob.read_line_assert_contains("").await?;
+        }
+        bob.read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        bob.send_line("bob").await?;
+        bob.read_line_assert_contains_all(&["bob", "welcome"])
+            .await?;
+
+        // One more rejection ends the connection instead of prompting again
+        let mut charlie = TestClient::connect(&addr).await?;
+        for username in bad_names.take(MAX_USERNAME_ATTEMPTS) {
+            charlie
+                .read_line_assert_contains_all(&["Choose", "username"])
+                .await?;
+            charlie.send_line(username).await?;
+            charlie.read_line_assert_contains("").await?;
+        }
+        charlie
+            .read_line_assert_contains("Too many invalid attempts, disconnecting")
+            .await?;
+        charlie.graceful_disconnect().await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn configured_reserved_usernames_are_rejected() -> Result<()> {
+    tokio_test(async {
+        let mut config = Config::default();
+        config.reserved_names.push(String::from("root"));
+        let (addr, _, _) = test_server::spawn_with_config(config).await?;
+
+        let mut client = TestClient::connect(&addr).await?;
+
+        for username in ["root", "Root", "admin"] {
+            client
+                .read_line_assert_contains_all(&["Choose", "username"])
+                .await?;
+            client.send_line(username).await?;
+            client
+                .read_line_assert_contains("That username is reserved")
+                .await?;
+        }
 
         // Now send a valid username and expect the welcome/join messages
         client
@@ -84,8 +196,10 @@ fn duplicate_usernames_are_rejected() -> Result<()> {
             .await?;
         client2.send_line("alice").await?;
 
-        // Expect rejection
-        client2.read_line_assert_contains("taken").await?;
+        // Expect rejection with suggestions for free alternatives
+        client2
+            .read_line_assert_contains("Username taken (try alice2, alice_, or alice3)")
+            .await?;
 
         // Send a different username and expect success
         client2
@@ -103,6 +217,59 @@ fn duplicate_usernames_are_rejected() -> Result<()> {
     })
 }
 
+#[test]
+fn simultaneous_claims_of_the_same_username_admit_exactly_one_client() -> Result<()> {
+    // A multi-threaded runtime lets the server's handlers actually race with each other
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(4)
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let addr = test_server::spawn().await?;
+
+            let mut clients = Vec::new();
+            for _ in 0..20 {
+                let mut client = TestClient::connect(&addr).await?;
+                client
+                    .read_line_assert_contains_all(&["Choose", "username"])
+                    .await?;
+                clients.push(client);
+            }
+
+            // Every client sends the same free username at once, then reads the outcome
+            let mut attempts = tokio::task::JoinSet::new();
+            for mut client in clients {
+                attempts.spawn(async move {
+                    client.send_line("alice").await?;
+                    let reply = client.read_line_assert_contains("").await?;
+                    anyhow::Ok((reply, client))
+                });
+            }
+
+            // Clients are returned with their replies so that none disconnect (which would free
+            // the username) until every attempt has been answered
+            let outcomes = attempts.join_all().await;
+
+            let mut admitted = 0;
+            let mut rejected = 0;
+
+            for outcome in outcomes {
+                let (reply, _client) = outcome?;
+
+                if reply.contains("welcome") {
+                    admitted += 1;
+                } else {
+                    assert!(reply.starts_with("Username taken"), "got: {reply}");
+                    rejected += 1;
+                }
+            }
+
+            assert_eq!((admitted, rejected), (1, 19));
+
+            Ok(())
+        })
+}
+
 #[test]
 fn join_message_broadcasts_to_all_clients() -> Result<()> {
     tokio_test(async {
@@ -116,3 +283,1032 @@ fn join_message_broadcasts_to_all_clients() -> Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn leave_notices_distinguish_quitting_from_lost_connections() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Quitting normally
+        bob.send_line("/quit").await?;
+        bob.read_line_assert_contains("Goodbye").await?;
+        bob.graceful_disconnect().await?;
+        assert_eq!(
+            alice.read_line_assert_contains("bob").await?,
+            "* bob left\n"
+        );
+
+        // Dropping the connection without quitting
+        let charlie = TestClient::connect_with_username("charlie", &addr).await?;
+        alice.read_line_assert_contains("charlie joined").await?;
+        drop(charlie);
+        assert_eq!(
+            alice.read_line_assert_contains("charlie").await?,
+            "* charlie lost connection\n"
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn pending_connections_beyond_the_cap_are_rejected() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            max_pending_connections: 2,
+            ..Config::default()
+        })
+        .await?;
+
+        // Fill the pending slots with clients that stall at the username prompt
+        let mut stalled1 = TestClient::connect(&addr).await?;
+        let mut stalled2 = TestClient::connect(&addr).await?;
+        stalled1
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        stalled2
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+
+        // Extra connections are told the server is busy and closed before username selection
+        for _ in 0..3 {
+            let mut extra = TestClient::connect(&addr).await?;
+            extra.read_line_assert_contains("Server busy").await?;
+            extra.graceful_disconnect().await?;
+        }
+
+        // Choosing a username frees a pending slot for a new connection
+        stalled1.send_line("alice").await?;
+        stalled1
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+        TestClient::connect_with_username("bob", &addr).await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rapid_connections_from_one_address_are_throttled() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            max_connects_per_ip: Some(3),
+            connect_window: Duration::from_mins(1),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut clients = Vec::new();
+
+        for _ in 0..3 {
+            let mut client = TestClient::connect(&addr).await?;
+            client
+                .read_line_assert_contains_all(&["Choose", "username"])
+                .await?;
+            clients.push(client);
+        }
+
+        // Later connections are closed before the TLS handshake while the limiter is tripped,
+        // even after earlier clients leave
+        for _ in 0..3 {
+            assert!(
+                TestClient::connect(&addr).await.is_err(),
+                "Expected the connection to be refused"
+            );
+        }
+
+        drop(clients);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(
+            TestClient::connect(&addr).await.is_err(),
+            "Expected the connection to be refused"
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn connections_from_denied_addresses_are_refused() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            deny_ips: vec!["127.0.0.0/8".parse()?],
+            ..Config::default()
+        })
+        .await?;
+
+        assert!(
+            TestClient::connect(&addr).await.is_err(),
+            "Expected the connection to be refused"
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn allow_list_admits_only_matching_addresses() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            allow_ips: vec!["127.0.0.1".parse()?],
+            ..Config::default()
+        })
+        .await?;
+        TestClient::connect_with_username("alice", &addr).await?;
+
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            allow_ips: vec!["10.0.0.0/8".parse()?, "::1".parse()?],
+            ..Config::default()
+        })
+        .await?;
+        assert!(
+            TestClient::connect(&addr).await.is_err(),
+            "Expected the connection to be refused"
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn join_and_leave_notices_include_the_online_count_when_enabled() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) =
+            test_server::spawn_with_config(Config { online_count: true, ..Config::default() })
+                .await?;
+
+        let mut alice = TestClient::connect(&addr).await?;
+        alice
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        alice.send_line("alice").await?;
+        alice
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+        assert_eq!(
+            alice.read_line_assert_contains("alice joined").await?,
+            "* alice joined the server (1 online)\n"
+        );
+
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        let _charlie = TestClient::connect_with_username("charlie", &addr).await?;
+        assert_eq!(
+            alice.read_line_assert_contains("bob joined").await?,
+            "* bob joined the server (2 online)\n"
+        );
+        assert_eq!(
+            alice.read_line_assert_contains("charlie joined").await?,
+            "* charlie joined the server (3 online)\n"
+        );
+
+        bob.read_line_assert_contains("charlie joined").await?;
+        bob.send_line("/quit see you").await?;
+        bob.graceful_disconnect().await?;
+        assert_eq!(
+            alice.read_line_assert_contains("bob left").await?,
+            "* bob left (see you) (2 online)\n"
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn names_on_join_sends_online_users_after_welcome() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) =
+            test_server::spawn_with_config(Config { names_on_join: true, ..Config::default() })
+                .await?;
+
+        let mut client1 = TestClient::connect(&addr).await?;
+        client1
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        client1.send_line("alice").await?;
+        client1
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+        client1
+            .read_line_assert_contains_all(&["Currently online:", "alice"])
+            .await?;
+        client1
+            .read_line_assert_contains("alice joined the server")
+            .await?;
+
+        // A second client sees both users listed right after the welcome message
+        let mut client2 = TestClient::connect(&addr).await?;
+        client2
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        client2.send_line("bob").await?;
+        client2
+            .read_line_assert_contains_all(&["bob", "welcome"])
+            .await?;
+        client2
+            .read_line_assert_contains_all(&["Currently online:", "alice", "bob"])
+            .await?;
+        client2
+            .read_line_assert_contains("bob joined the server")
+            .await?;
+
+        // The listing is only sent to the joining client
+        client1.read_line_assert_contains("bob joined").await?;
+        assert!(client1.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn no_password_is_requested_by_default() -> Result<()> {
+    tokio_test(async {
+        let mut client = TestClient::connect(&test_server::spawn().await?).await?;
+
+        // The first prompt is for a username
+        client
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn correct_password_proceeds_to_username_selection() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            password: Some("hunter2".to_string()),
+            ..Config::default()
+        })
+        .await?;
+        let mut client = TestClient::connect(&addr).await?;
+
+        client.read_line_assert_contains("Password:").await?;
+        client.send_line("hunter2").await?;
+        client
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        client.send_line("alice").await?;
+        client
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn wrong_passwords_are_retried_then_disconnected() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            password: Some("hunter2".to_string()),
+            ..Config::default()
+        })
+        .await?;
+
+        // A wrong password can be followed by the correct one
+        let mut client1 = TestClient::connect(&addr).await?;
+        client1.read_line_assert_contains("Password:").await?;
+        client1.send_line("hunter3").await?;
+        client1
+            .read_line_assert_contains("Invalid password")
+            .await?;
+        client1.read_line_assert_contains("Password:").await?;
+        client1.send_line("hunter2").await?;
+        client1
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+
+        // Too many wrong passwords result in being disconnected
+        let mut client2 = TestClient::connect(&addr).await?;
+        for wrong_password in ["", "hunter", "hunter22"] {
+            client2.read_line_assert_contains("Password:").await?;
+            client2.send_line(wrong_password).await?;
+            client2
+                .read_line_assert_contains("Invalid password")
+                .await?;
+        }
+        client2.graceful_disconnect().await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn rapid_reconnects_within_leave_grace_produce_one_join_notice() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            leave_grace: Duration::from_millis(800),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // Bob flaps, alternating between quitting and dropping the connection (without expecting
+        // his own join notice, which is suppressed after the first time)
+        for i in 0..5 {
+            let mut bob = TestClient::connect(&addr).await?;
+            bob.read_username_prompt().await?;
+            bob.send_line("bob").await?;
+            bob.read_line_assert_contains_all(&["bob", "welcome"])
+                .await?;
+
+            if i % 2 == 0 {
+                bob.send_line("/quit").await?;
+                bob.read_until_line_contains("Goodbye").await?;
+                bob.graceful_disconnect().await?;
+            } else {
+                drop(bob);
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        // Only the first join is announced, and the leave is announced once the flapping stops
+        alice.read_line_assert_contains("* bob joined").await?;
+        alice.read_line_assert_contains("* bob left").await?;
+        assert!(alice.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn reconnecting_within_leave_grace_suppresses_leave_and_join_notices() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            leave_grace: Duration::from_millis(1500),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Bob drops and reconnects within the grace period
+        drop(bob);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let mut bob = TestClient::connect(&addr).await?;
+        bob.read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        bob.send_line("bob").await?;
+        bob.read_line_assert_contains_all(&["bob", "welcome"])
+            .await?;
+
+        // Nobody sees any leave or join notices, even after the grace period has passed
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(alice.read_line_assert_contains("").await.is_err());
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        // The reconnected client is a regular participant
+        bob.send_line("back again").await?;
+        for client in [&mut alice, &mut bob] {
+            client.read_line_assert_contains("bob: back again").await?;
+        }
+
+        // Leaving without reconnecting results in a deferred leave notice
+        bob.send_line("/quit").await?;
+        bob.read_line_assert_contains("Goodbye").await?;
+        bob.graceful_disconnect().await?;
+        assert!(alice.read_line_assert_contains("").await.is_err());
+        alice.read_line_assert_contains("* bob left").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn overlong_input_during_username_selection_is_rejected() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+        let mut alice = TestClient::connect(&addr).await?;
+        alice
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+
+        // Stream a large payload without a newline, which the server rejects without buffering
+        alice.send_raw(&vec![b'a'; 1024 * 1024]).await?;
+        alice.read_line_assert_contains("Input too long").await?;
+
+        // Once the line ends, username selection continues as usual
+        alice.send_line("").await?;
+        alice
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        alice.send_line("alice").await?;
+        alice
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn disconnecting_before_choosing_a_username_is_not_announced() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // Connect, read the prompt, and drop the connection without choosing a username
+        let mut quitter = TestClient::connect(&addr).await?;
+        quitter
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        drop(quitter);
+
+        // No join or leave notices were broadcast
+        assert!(alice.read_line_assert_contains("").await.is_err());
+
+        // The connection was never added to the online users
+        alice.send_line("/who").await?;
+        let who = alice.read_line_assert_contains("Currently online:").await?;
+        assert_eq!(who.trim_end(), "Currently online: alice");
+
+        Ok(())
+    })
+}
+
+#[test]
+fn transformed_usernames_are_confirmed_before_the_welcome() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            confirm_username: ConfirmUsername::IfChanged,
+            ..Config::default()
+        })
+        .await?;
+
+        // Surrounding whitespace is trimmed, so the final form is confirmed
+        let mut alice = TestClient::connect(&addr).await?;
+        alice
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        alice.send_line("  alice\t").await?;
+        let confirmation = alice
+            .read_line_assert_contains("You are now known as")
+            .await?;
+        assert_eq!(confirmation, "You are now known as alice\n");
+        alice
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+
+        // A username that was used as typed is not confirmed
+        let mut bob = TestClient::connect(&addr).await?;
+        bob.read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        bob.send_line("bob").await?;
+        bob.read_line_assert_contains_all(&["bob", "welcome"])
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn joining_clients_are_told_their_stored_username_regardless_of_the_welcome() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            messages: Messages {
+                welcome: "Greetings. Welcome to Acme Chat.".to_string(),
+                ..Messages::default()
+            },
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect(&addr).await?;
+        alice.read_username_prompt().await?;
+        alice.send_line("  alice\t").await?;
+        alice.read_line_assert_contains("Acme Chat").await?;
+        alice.read_line_assert_contains("* alice joined").await?;
+        assert_eq!(alice.username(), Some("alice"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prompt_and_welcome_can_be_customized() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            messages: Messages {
+                banner: None,
+                prompt: "Who goes there?".to_string(),
+                welcome: "Greetings, {username}. Welcome to Acme Chat.".to_string(),
+            },
+            ..Config::default()
+        })
+        .await?;
+
+        let mut client = TestClient::connect(&addr).await?;
+        let prompt = client.read_line_assert_contains("Who goes there?").await?;
+        assert_eq!(prompt, "Who goes there?\n");
+        client.send_line("alice").await?;
+        let welcome = client.read_line_assert_contains("Greetings").await?;
+        assert_eq!(welcome, "Greetings, alice. Welcome to Acme Chat.\n");
+        client
+            .read_line_assert_contains("alice joined the server")
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn bot_welcome_is_sent_privately_to_the_joining_client() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            bot_welcome: Some("Hi {username}! Try /who to see who's here.".to_string()),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect(&addr).await?;
+        alice
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        alice.send_line("alice").await?;
+        alice
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+        let bot_welcome = alice.read_line_assert_contains("PrattleBot").await?;
+        assert_eq!(
+            bot_welcome,
+            "PrattleBot (private): Hi alice! Try /who to see who's here.\n"
+        );
+        alice
+            .read_line_assert_contains("alice joined the server")
+            .await?;
+
+        // Bob gets his own welcome, while Alice only sees that he joined
+        let mut bob = TestClient::connect(&addr).await?;
+        bob.read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        bob.send_line("bob").await?;
+        bob.read_line_assert_contains_all(&["bob", "welcome"])
+            .await?;
+        bob.read_line_assert_contains("PrattleBot (private): Hi bob!")
+            .await?;
+        alice
+            .read_line_assert_contains("bob joined the server")
+            .await?;
+
+        alice.send_line("still there?").await?;
+        alice
+            .read_line_assert_contains("alice: still there?")
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn usernames_sent_before_the_prompt_is_read_are_accepted() -> Result<()> {
+    tokio_test(async {
+        let mut client = TestClient::connect(&test_server::spawn().await?).await?;
+
+        // Send the username immediately, without waiting for the prompt
+        client.send_line("alice").await?;
+
+        client
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        client
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+        client
+            .read_line_assert_contains("alice joined the server")
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn prompt_once_does_not_repeat_the_prompt_after_rejections() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) =
+            test_server::spawn_with_config(Config { prompt_once: true, ..Config::default() })
+                .await?;
+
+        // A script pipelines a rejected username and then a valid one in a single write
+        let mut client = TestClient::connect(&addr).await?;
+        client.send_raw(b"\nalice\n").await?;
+
+        client
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        client.read_line_assert_contains("cannot be empty").await?;
+        client
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+        client
+            .read_line_assert_contains("alice joined the server")
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn banner_is_shown_before_the_username_prompt() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            messages: Messages {
+                banner: Some("Acme Chat v1.2\nAuthorized users only\n".to_string()),
+                ..Messages::default()
+            },
+            ..Config::default()
+        })
+        .await?;
+
+        let mut client = TestClient::connect(&addr).await?;
+        assert_eq!(
+            client.read_username_prompt().await?,
+            ["Acme Chat v1.2", "Authorized users only"]
+        );
+
+        // The usual flow continues after the prompt
+        client.send_line("alice").await?;
+        client
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+
+        // Helpers that expect the prompt consume the banner
+        TestClient::connect_with_username("bob", &addr).await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn duplicate_usernames_take_over_the_existing_session_when_allowed() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) =
+            test_server::spawn_with_config(Config { allow_takeover: true, ..Config::default() })
+                .await?;
+
+        let mut stale = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        stale.read_line_assert_contains("bob joined").await?;
+
+        // Joining with the same username disconnects the old session
+        let mut alice = TestClient::connect(&addr).await?;
+        alice
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        alice.send_line("alice").await?;
+        alice
+            .read_line_assert_contains_all(&["alice", "welcome"])
+            .await?;
+
+        // The old session may or may not see the reconnection notice first
+        stale.read_until_line_contains("taken over").await?;
+        stale.graceful_disconnect().await?;
+
+        // Others see a reconnection instead of a leave and a join
+        for client in [&mut alice, &mut bob] {
+            client
+                .read_line_assert_contains("* alice reconnected")
+                .await?;
+        }
+        assert!(bob.read_line_assert_contains("").await.is_err());
+
+        // The new session owns the username
+        bob.send_line("/who").await?;
+        let who = bob.read_line_assert_contains("Currently online:").await?;
+        assert_eq!(who.matches("alice").count(), 1);
+
+        alice.send_line("I'm back").await?;
+        bob.read_line_assert_contains("alice: I'm back").await?;
+
+        Ok(())
+    })
+}
+
+/// Sends `/token` as `client` and returns the reconnect token from the reply.
+async fn request_reconnect_token(client: &mut TestClient) -> Result<String> {
+    client.send_line("/token").await?;
+    let reply = client
+        .read_line_assert_contains("Your reconnect token is ")
+        .await?;
+
+    reply
+        .split_whitespace()
+        .nth(4)
+        .map(String::from)
+        .ok_or_else(|| anyhow::anyhow!("No token in {reply:?}"))
+}
+
+#[test]
+fn reconnect_tokens_reclaim_the_username_once() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            reconnect_window: Some(Duration::from_mins(1)),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        let token = request_reconnect_token(&mut alice).await?;
+        alice.send_line("/quit").await?;
+        alice.graceful_disconnect().await?;
+        bob.read_line_assert_contains("alice left").await?;
+
+        // The token is sent in place of a username
+        let mut alice = TestClient::connect(&addr).await?;
+        alice
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        alice.send_line(&format!("/token {token}")).await?;
+        alice
+            .read_line_assert_contains_all(&["Hi alice", "welcome"])
+            .await?;
+        for client in [&mut alice, &mut bob] {
+            client.read_line_assert_contains("alice joined").await?;
+        }
+
+        // Rejoining issues a new token, and the old one is used up
+        let new_token = request_reconnect_token(&mut alice).await?;
+        assert_ne!(new_token, token);
+        alice.send_line("/quit").await?;
+        alice.graceful_disconnect().await?;
+
+        let mut imposter = TestClient::connect(&addr).await?;
+        imposter
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        imposter.send_line(&format!("/token {token}")).await?;
+        imposter
+            .read_line_assert_contains("Invalid or expired token")
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn unknown_and_expired_reconnect_tokens_are_rejected() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            reconnect_window: Some(Duration::from_millis(200)),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let token = request_reconnect_token(&mut alice).await?;
+        alice.send_line("/quit").await?;
+        alice.graceful_disconnect().await?;
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+
+        let mut client = TestClient::connect(&addr).await?;
+        client
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+
+        for request in [
+            format!("/token {token}"),
+            String::from("/token not-a-token"),
+        ] {
+            client.send_line(&request).await?;
+            client
+                .read_line_assert_contains("Invalid or expired token")
+                .await?;
+            client
+                .read_line_assert_contains_all(&["Choose", "username"])
+                .await?;
+        }
+
+        // The username itself is still free to choose
+        client.send_line("alice").await?;
+        client
+            .read_line_assert_contains_all(&["Hi alice", "welcome"])
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn reconnect_tokens_wait_for_the_old_session_to_leave() -> Result<()> {
+    tokio_test(async {
+        // Even when takeovers are allowed, a token never replaces a session that is still online
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            allow_takeover: true,
+            reconnect_window: Some(Duration::from_mins(1)),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut stale = TestClient::connect_with_username("alice", &addr).await?;
+        let token = request_reconnect_token(&mut stale).await?;
+
+        let mut alice = TestClient::connect(&addr).await?;
+        alice
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        alice.send_line(&format!("/token {token}")).await?;
+        alice
+            .read_line_assert_contains("That username is still in use")
+            .await?;
+        assert!(stale.read_line_assert_contains("").await.is_err());
+
+        // The rejected attempt didn't use up the token, so it works once the old session is gone
+        stale.send_line("/quit").await?;
+        stale.graceful_disconnect().await?;
+
+        alice
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        alice.send_line(&format!("/token {token}")).await?;
+        alice
+            .read_line_assert_contains_all(&["Hi alice", "welcome"])
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn reconnect_tokens_are_disabled_by_default() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        alice.send_line("/token").await?;
+        alice
+            .read_line_assert_contains("Reconnect tokens are disabled")
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn quiet_marker_skips_the_welcome() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // The only line after choosing a username is the join notice, which serves as confirmation
+        let mut bot = TestClient::connect(&addr).await?;
+        bot.read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+        bot.send_line("bot +quiet").await?;
+        let line = bot.read_line_assert_contains("joined").await?;
+        assert_eq!(line, "* bot joined the server\n");
+        assert!(bot.read_line_assert_contains("").await.is_err());
+        assert_eq!(bot.username(), None);
+
+        // Others see the usual join notice, without the marker
+        alice.read_line_assert_contains("* bot joined").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn insecure_mode_connects_without_the_pinned_cert() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+        let timeout = Duration::from_secs(2);
+
+        // A missing pinned cert fails by default
+        assert!(
+            prattle_client::connect("nonexistent.crt", &addr, timeout)
+                .await
+                .is_err()
+        );
+
+        // So does a cert that doesn't match the server's
+        let other_cert_path =
+            std::env::temp_dir().join(format!("prattle-other-cert-{}.crt", std::process::id()));
+        let other_cert = rcgen::generate_simple_self_signed(vec![String::from("localhost")])?;
+        std::fs::write(&other_cert_path, other_cert.cert.pem())?;
+        let other_cert_path_str = other_cert_path.to_string_lossy().into_owned();
+        let connect_res = prattle_client::connect(&other_cert_path_str, &addr, timeout).await;
+        std::fs::remove_file(&other_cert_path)?;
+        assert!(connect_res.is_err());
+
+        // Insecure mode accepts the server's cert without having it locally
+        let (mut reader, _writer) = prattle_client::connect_insecure(&addr, timeout).await?;
+        let mut line = String::new();
+        tokio::time::timeout(timeout, reader.read_line(&mut line)).await??;
+        assert!(line.contains("Choose a username"), "got: {line}");
+
+        Ok(())
+    })
+}
+
+#[test]
+fn connecting_with_retries_waits_for_the_server_to_start() -> Result<()> {
+    tokio_test(async {
+        // Ensure the pinned cert exists before the client tries to read it
+        let tls_config = prattle_server::tls::create_config()?;
+
+        // Find a free port, then leave it unbound so that connections are refused at first
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")?
+            .local_addr()?
+            .to_string();
+
+        let client_addr = addr.clone();
+        let connecting = tokio::spawn(async move {
+            prattle_client::connect_with_retries(
+                prattle_server::tls::CERT_PATH,
+                &client_addr,
+                Duration::from_secs(1),
+                20,
+            )
+            .await
+        });
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!connecting.is_finished());
+
+        let server =
+            prattle_server::server::Server::bind(&addr, tls_config, Config::default()).await?;
+        let shutdown = prattle_server::shutdown_signal::ShutdownHandle::new();
+        tokio::spawn(server.run(shutdown.signal()));
+
+        let (mut reader, _writer) = connecting.await??;
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(1), reader.read_line(&mut line)).await??;
+        assert!(line.contains("Choose a username"), "got: {line}");
+
+        shutdown.trigger();
+        Ok(())
+    })
+}
+
+#[test]
+fn connecting_with_retries_gives_up_immediately_on_fatal_errors() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let res = prattle_client::connect_with_retries(
+            "nonexistent.crt",
+            &addr,
+            Duration::from_secs(1),
+            20,
+        )
+        .await;
+        assert!(res.is_err_and(|e| e.to_string().contains("nonexistent.crt")));
+
+        // Addresses that can't be resolved aren't retried either
+        let res = prattle_client::connect_with_retries(
+            prattle_server::tls::CERT_PATH,
+            "no-such-host.invalid:8000",
+            Duration::from_secs(1),
+            20,
+        )
+        .await;
+        assert!(res.is_err_and(|e| e.to_string().contains("after 1 attempt")));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn clients_that_stall_partway_through_a_line_are_disconnected() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            read_timeout: Duration::from_millis(200),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Being idle between lines is fine
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        bob.send_line("still here").await?;
+        alice.read_line_assert_contains("bob: still here").await?;
+        bob.read_line_assert_contains("bob: still here").await?;
+
+        // Half a line that is never finished is not
+        bob.send_raw(b"this message never e").await?;
+        bob.read_line_assert_contains("took too long to finish a line")
+            .await?;
+        bob.graceful_disconnect().await?;
+        alice
+            .read_line_assert_contains("bob lost connection")
+            .await?;
+
+        Ok(())
+    })
+}
@@ -0,0 +1,20 @@
+//! Kept in its own test binary, so that it runs in a fresh process where nothing has installed a
+//! crypto provider yet.
+
+use anyhow::Result;
+use prattle_server::tls;
+use rustls::crypto::CryptoProvider;
+
+#[test]
+fn config_creation_installs_a_crypto_provider_when_none_is_set() -> Result<()> {
+    assert!(CryptoProvider::get_default().is_none());
+
+    tls::create_config()?;
+    assert!(CryptoProvider::get_default().is_some());
+
+    // Installing again is harmless
+    tls::install_crypto_provider()?;
+    tls::create_config()?;
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use crate::common::{test_client::TestClient, tokio_test};
+use anyhow::Result;
+use prattle_server::{config::Config, server::Server, shutdown_signal::ShutdownHandle, tls};
+use std::time::Duration;
+
+#[test]
+fn draining_refuses_new_connections_but_keeps_serving_existing_clients() -> Result<()> {
+    tokio_test(async {
+        let server = Server::bind("127.0.0.1:0", tls::create_config()?, Config::default()).await?;
+        let addr = server.local_addr()?.to_string();
+        let drain = server.drain_handle();
+
+        let shutdown = ShutdownHandle::new();
+        let server_handle = tokio::spawn(server.run(shutdown.signal()));
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        drain.trigger();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // New connections are refused
+        assert!(TestClient::connect(&addr).await.is_err());
+
+        // Existing clients keep chatting, well past when a shutdown would have disconnected them
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        alice.send_line("still here?").await?;
+        for client in [&mut alice, &mut bob] {
+            client
+                .read_line_assert_contains("alice: still here?")
+                .await?;
+        }
+        bob.send_line("yep").await?;
+        for client in [&mut alice, &mut bob] {
+            client.read_line_assert_contains("bob: yep").await?;
+        }
+
+        // Clients can still leave on their own
+        bob.send_line("/quit").await?;
+        bob.read_line_assert_contains("Goodbye").await?;
+        bob.graceful_disconnect().await?;
+        alice.read_line_assert_contains("bob left").await?;
+
+        // A hard shutdown is still possible afterward
+        shutdown.trigger();
+        alice.read_line_assert_contains("shutting down").await?;
+        alice.graceful_disconnect().await?;
+        server_handle.await??;
+
+        Ok(())
+    })
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use crate::common::{test_client::TestClient, tokio_test};
+use anyhow::{Context, Result};
+use prattle_server::{config::Config, server::Server, shutdown_signal::ShutdownHandle, tls};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Sends an HTTP GET request for `path` to `addr` and returns the full response.
+async fn http_get(addr: &str, path: &str) -> Result<String> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream
+        .write_all(format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\n\r\n").as_bytes())
+        .await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+#[test]
+fn health_endpoint_reports_ok_and_user_count_while_running() -> Result<()> {
+    tokio_test(async {
+        let server = Server::bind(
+            "127.0.0.1:0",
+            tls::create_config()?,
+            Config { health_addr: Some("127.0.0.1:0".to_string()), ..Config::default() },
+        )
+        .await?;
+        let addr = server.local_addr()?.to_string();
+        let health_addr = server
+            .health_addr()?
+            .context("expected the health check endpoint to be enabled")?
+            .to_string();
+        assert_eq!(server.metrics_addr()?, None);
+
+        let shutdown = ShutdownHandle::new();
+        let server_handle = tokio::spawn(server.run(shutdown.signal()));
+
+        let response = http_get(&health_addr, "/healthz").await?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nOK users=0\n"));
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        let response = http_get(&health_addr, "/healthz").await?;
+        assert!(response.ends_with("\r\n\r\nOK users=2\n"));
+
+        // Other paths are not found
+        let response = http_get(&health_addr, "/metrics").await?;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        // The endpoint stops along with the server
+        drop(alice);
+        drop(bob);
+        shutdown.trigger();
+        server_handle.await??;
+
+        // Give the aborted endpoint task a moment to be dropped
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(TcpStream::connect(&health_addr).await.is_err());
+
+        Ok(())
+    })
+}
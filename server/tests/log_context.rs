@@ -0,0 +1,155 @@
+mod common;
+
+use crate::common::{test_client::TestClient, tokio_test};
+use anyhow::{Context, Result};
+use prattle_server::{config::Config, server::Server, shutdown_signal::ShutdownHandle, tls};
+use serde_json::Value;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{Level, Subscriber};
+
+/// A log destination that collects everything written to it.
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    /// Parses every line logged so far as JSON.
+    fn lines(&self) -> Result<Vec<Value>> {
+        let bytes = self
+            .0
+            .lock()
+            .map_err(|_| anyhow::anyhow!("captured logs lock poisoned"))?
+            .clone();
+
+        Ok(String::from_utf8(bytes)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?)
+    }
+}
+
+impl io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .map_err(|_| io::Error::other("captured logs lock poisoned"))?
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+/// Finds the first captured log line whose message contains `text`.
+fn find_line<'a>(lines: &'a [Value], text: &str) -> Result<&'a Value> {
+    lines
+        .iter()
+        .find(|line| {
+            line["fields"]["message"]
+                .as_str()
+                .is_some_and(|message| message.contains(text))
+        })
+        .with_context(|| format!("expected a log line containing {text:?}"))
+}
+
+/// Creates a subscriber that writes JSON logs at `INFO` and above to `logs`.
+fn capturing_subscriber(logs: &CapturedLogs) -> impl Subscriber + Send + Sync + use<> {
+    let writer = logs.clone();
+
+    tracing_subscriber::fmt()
+        .json()
+        .with_max_level(Level::INFO)
+        .with_writer(move || writer.clone())
+        .finish()
+}
+
+#[test]
+fn client_logs_carry_the_client_address_and_username() -> Result<()> {
+    let logs = CapturedLogs::default();
+
+    // Tests run on a single-threaded runtime, so a thread-local subscriber sees every task
+    let _guard = tracing::subscriber::set_default(capturing_subscriber(&logs));
+
+    tokio_test(async {
+        let server = Server::bind("127.0.0.1:0", tls::create_config()?, Config::default()).await?;
+        let addr = server.local_addr()?.to_string();
+
+        let shutdown = ShutdownHandle::new();
+        let server_handle = tokio::spawn(server.run(shutdown.signal()));
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        alice.send_line("/quit").await?;
+        alice.read_line_assert_contains("Goodbye").await?;
+        alice.graceful_disconnect().await?;
+
+        // Give the handler a moment to finish logging
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.trigger();
+        server_handle.await??;
+
+        Ok(())
+    })?;
+
+    let lines = logs.lines()?;
+
+    // Lines logged before the username is chosen only carry the address
+    let handshake = find_line(&lines, "TLS handshake completed")?;
+    assert_eq!(handshake["span"]["name"], "client");
+    let client_addr = handshake["span"]["client_addr"]
+        .as_str()
+        .context("expected the client address in the span")?;
+    client_addr.parse::<SocketAddr>()?;
+    assert!(handshake["span"].get("username").is_none());
+
+    // Later lines from the same connection also carry the username
+    let goodbye = find_line(&lines, "alice closed connection gracefully")?;
+    assert_eq!(goodbye["span"]["client_addr"], client_addr);
+    assert_eq!(goodbye["span"]["username"], "alice");
+
+    // Lines from the accept loop are not in a client span
+    let accepted = find_line(&lines, "New connection from")?;
+    assert!(accepted.get("span").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn handshakes_log_the_negotiated_protocol_and_cipher_suite() -> Result<()> {
+    let logs = CapturedLogs::default();
+    let _guard = tracing::subscriber::set_default(capturing_subscriber(&logs));
+
+    tokio_test(async {
+        let server = Server::bind("127.0.0.1:0", tls::create_config()?, Config::default()).await?;
+        let addr = server.local_addr()?.to_string();
+
+        let shutdown = ShutdownHandle::new();
+        let server_handle = tokio::spawn(server.run(shutdown.signal()));
+
+        let mut alice = TestClient::connect(&addr).await?;
+        alice.read_line_assert_contains("username").await?;
+
+        shutdown.trigger();
+        alice.read_until_line_contains("shutting down").await?;
+        alice.graceful_disconnect().await?;
+        server_handle.await??;
+
+        Ok(())
+    })?;
+
+    let lines = logs.lines()?;
+    let handshake = find_line(&lines, "TLS handshake completed")?["fields"]["message"]
+        .as_str()
+        .context("expected the handshake message")?;
+
+    // The default config negotiates TLS 1.3
+    assert!(
+        handshake.contains("using TLSv1_3 with TLS13_"),
+        "{handshake}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use anyhow::Result;
+use std::{env, fs, process};
+use tracing::{info, level_filters::LevelFilter};
+
+/// Runs in its own test binary because the global subscriber can only be installed once per
+/// process.
+#[test]
+fn logs_are_also_written_to_the_log_file() -> Result<()> {
+    let path = env::temp_dir().join(format!("prattle-log-test-{}.log", process::id()));
+    let _ = fs::remove_file(&path);
+
+    prattle_server::logger::init_with_log_file(LevelFilter::INFO, Some(&path))?;
+    info!("written to the log file");
+    tracing::debug!("filtered out by the level");
+
+    let contents = fs::read_to_string(&path);
+    fs::remove_file(&path)?;
+    let contents = contents?;
+
+    assert!(
+        contents.contains("written to the log file"),
+        "log file: {contents}"
+    );
+    assert!(!contents.contains("filtered out"), "log file: {contents}");
+
+    // Log files are plain text
+    assert!(!contents.contains('\x1b'), "log file: {contents}");
+
+    Ok(())
+}
+
+#[test]
+fn unopenable_log_files_are_an_error() {
+    assert!(
+        prattle_server::logger::init_with_log_file(
+            LevelFilter::INFO,
+            Some("/nonexistent-dir/prattle.log")
+        )
+        .is_err()
+    );
+}
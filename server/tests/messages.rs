@@ -1,7 +1,9 @@
 mod common;
 
 use crate::common::{test_client::TestClient, test_server, tokio_test};
-use anyhow::Result;
+use anyhow::{Context, Result};
+use prattle_server::config::Config;
+use std::time::Duration;
 
 #[test]
 fn client_messages_broadcast_to_all_clients() -> Result<()> {
@@ -110,3 +112,422 @@ fn empty_messages_are_ignored() -> Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn clients_that_stop_reading_are_disconnected_after_write_timeout() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            write_timeout: Duration::from_millis(500),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // This client joins but never reads anything else from the server
+        let _stalled = TestClient::connect_with_username("stalled", &addr).await?;
+        alice.read_line_assert_contains("stalled joined").await?;
+
+        // Send enough data (~6 MB) to fill the stalled client's socket buffers, which are typically
+        // capped at a few MB, reading back each batch so that only the stalled client falls behind
+        let filler = "x".repeat(1000);
+
+        for batch in 0..120 {
+            for i in 0..50 {
+                alice.send_line(&format!("{batch}-{i} {filler}")).await?;
+            }
+
+            alice
+                .read_until_line_contains(&format!("alice: {batch}-49 "))
+                .await?;
+        }
+
+        // The stalled client should be disconnected once a write to it times out
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        alice.send_line("/who").await?;
+        let who_listing = alice.read_until_line_contains("Currently online").await?;
+        assert!(!who_listing.contains("stalled"));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn clients_can_turn_off_echo_of_their_own_messages() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line("/echo off").await?;
+        alice.read_line_assert_contains("Echo is now off").await?;
+
+        // Alice no longer receives her own messages or actions, but bob still does
+        alice.send_line("Hello!").await?;
+        alice.send_line("/action waves").await?;
+        bob.read_line_assert_contains("alice: Hello!").await?;
+        bob.read_line_assert_contains("* alice waves").await?;
+        assert!(alice.read_line_assert_contains("").await.is_err());
+
+        // Messages from others are still received
+        bob.send_line("Hi alice!").await?;
+        alice.read_line_assert_contains("bob: Hi alice!").await?;
+        bob.read_line_assert_contains("bob: Hi alice!").await?;
+
+        // Turning echo back on restores the default behavior
+        alice.send_line("/echo on").await?;
+        alice.read_line_assert_contains("Echo is now on").await?;
+        alice.send_line("Back again").await?;
+        alice.read_line_assert_contains("alice: Back again").await?;
+        bob.read_line_assert_contains("alice: Back again").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn overlong_messages_are_rejected() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line(&"a".repeat(100_000)).await?;
+        alice.read_line_assert_contains("Input too long").await?;
+
+        // Nothing was broadcast and the client can keep chatting
+        assert!(bob.read_line_assert_contains("").await.is_err());
+        alice.send_line("Hello!").await?;
+        bob.read_line_assert_contains("alice: Hello!").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn a_lone_client_stays_connected_while_sending() -> Result<()> {
+    tokio_test(async {
+        let mut alice =
+            TestClient::connect_with_username("alice", &test_server::spawn().await?).await?;
+
+        // Messages, actions, and notices are broadcast with only the sender subscribed
+        for (msg, expected) in [
+            ("anyone here?", "alice: anyone here?"),
+            ("/action looks around", "alice looks around"),
+            ("/away", "marked as away"),
+            ("/away", "alice is back"),
+        ] {
+            alice.send_line(msg).await?;
+            alice.read_line_assert_contains(expected).await?;
+        }
+
+        // The client is still connected and served
+        alice.send_line("/who").await?;
+        alice
+            .read_line_assert_contains("Currently online: alice")
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn heartbeats_are_sent_to_idle_clients_when_enabled() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            heartbeat_interval: Some(Duration::from_millis(200)),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // Heartbeats keep arriving while the client is idle
+        for _ in 0..3 {
+            let line = alice.read_line_assert_contains("HEARTBEAT").await?;
+            assert_eq!(line, "\x05HEARTBEAT\n");
+        }
+
+        // Regular traffic is unaffected
+        alice.send_line("still here").await?;
+        alice.read_until_line_contains("alice: still here").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn heartbeats_are_off_by_default() -> Result<()> {
+    tokio_test(async {
+        let mut alice =
+            TestClient::connect_with_username("alice", &test_server::spawn().await?).await?;
+
+        // Nothing arrives while the client is idle
+        assert!(alice.read_line_assert_contains("").await.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn control_characters_cannot_forge_lines() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // A carriage return and "erase line" sequence would otherwise overwrite the action in bob's
+        // terminal with a fake message from bob
+        alice
+            .send_raw(b"/action waves\r\x1b[2Kbob: I'm bob!\n")
+            .await?;
+        let line = bob.read_line_assert_contains("* alice waves").await?;
+        assert_eq!(line, "* alice wavesbob: I'm bob!\n");
+        alice.read_line_assert_contains("* alice waves").await?;
+
+        // Escape sequences are stripped from messages too
+        alice.send_raw(b"\x1b[31mred\x1b[0m text\n").await?;
+        let line = bob.read_line_assert_contains("alice:").await?;
+        assert_eq!(line, "alice: red text\n");
+        alice.read_line_assert_contains("alice: red text").await?;
+
+        // Messages that are only control characters are ignored
+        alice.send_raw(b"\x1b[2J\x07\n").await?;
+        alice.send_line("Hello!").await?;
+        bob.read_line_assert_contains("alice: Hello!").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn crlf_line_endings_do_not_leak_carriage_returns() -> Result<()> {
+    tokio_test(async {
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // A telnet-style client ends every line, including the username, with "\r\n"
+        let mut carol = TestClient::connect(&addr).await?;
+        carol.read_username_prompt().await?;
+        carol.send_raw(b"carol\r\n").await?;
+        carol.read_line_assert_contains("welcome").await?;
+        carol.read_line_assert_contains("carol joined").await?;
+        assert_eq!(
+            alice.read_line_assert_contains("carol joined").await?,
+            "* carol joined the server\n"
+        );
+
+        for (input, expected) in [
+            (&b"hello\r\n"[..], "carol: hello\n"),
+            (b"/action waves\r\n", "* carol waves\n"),
+            (
+                b"/shrug oh well\r\n",
+                "carol: oh well \u{af}\\_(\u{30c4})_/\u{af}\n",
+            ),
+        ] {
+            carol.send_raw(input).await?;
+            assert_eq!(alice.read_line_assert_contains("carol").await?, expected);
+            carol.read_line_assert_contains("carol").await?;
+        }
+
+        carol.send_raw(b"/quit see you\r\n").await?;
+        carol.read_line_assert_contains("Goodbye").await?;
+        carol.graceful_disconnect().await?;
+        assert_eq!(
+            alice.read_line_assert_contains("carol left").await?,
+            "* carol left (see you)\n"
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn joining_clients_receive_a_truncated_backlog_when_history_exceeds_the_byte_cap() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _handle) =
+            test_server::spawn_with_config(Config { history_size: 50, ..Config::default() })
+                .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // Each broadcast line is 904 bytes, so 30 of them exceed the 16 KiB replay cap, which only
+        // fits the last 18
+        let filler = "x".repeat(893);
+        for i in 0..30 {
+            alice.send_line(&format!("{i:02} {filler}")).await?;
+            alice.read_line_assert_contains(&format!("{i:02} ")).await?;
+        }
+
+        let mut bob = TestClient::connect(&addr).await?;
+        bob.read_username_prompt().await?;
+        bob.send_line("bob").await?;
+        bob.read_line_assert_contains_all(&["bob", "welcome"])
+            .await?;
+
+        assert_eq!(
+            bob.read_line_assert_contains("showing").await?,
+            "(showing last 18 of 30 messages)\n"
+        );
+        for i in 12..30 {
+            assert_eq!(
+                bob.read_line_assert_contains("alice:").await?,
+                format!("alice: {i:02} {filler}\n")
+            );
+        }
+        bob.read_line_assert_contains("bob joined the server")
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn log_shows_the_requested_number_of_recent_messages() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _handle) =
+            test_server::spawn_with_config(Config { history_size: 10, ..Config::default() })
+                .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        for msg in ["one", "two", "three", "four", "five"] {
+            alice.send_line(msg).await?;
+            alice.read_line_assert_contains(msg).await?;
+            bob.read_line_assert_contains(msg).await?;
+        }
+
+        // Only the requested number of the most recent messages are shown, privately
+        bob.send_line("/log 3").await?;
+        assert_eq!(
+            bob.read_line_assert_contains("Last").await?,
+            "Last 3 messages:\n"
+        );
+        for msg in ["three", "four", "five"] {
+            assert_eq!(
+                bob.read_line_assert_contains(msg).await?,
+                format!("alice: {msg}\n")
+            );
+        }
+        assert!(alice.read_line_assert_contains("").await.is_err());
+
+        // Asking for more than were sent shows all of them
+        bob.send_line("/log").await?;
+        bob.read_line_assert_contains("Last 5 messages:").await?;
+        for msg in ["one", "two", "three", "four", "five"] {
+            bob.read_line_assert_contains(msg).await?;
+        }
+
+        bob.send_line("/log abc").await?;
+        bob.read_line_assert_contains("Usage: /log").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn history_is_replayed_in_full_when_it_fits() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _handle) =
+            test_server::spawn_with_config(Config { history_size: 2, ..Config::default() }).await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        for msg in ["one", "two", "three"] {
+            alice.send_line(msg).await?;
+            alice.read_line_assert_contains(msg).await?;
+        }
+
+        // Only the most recent lines are kept, without a note because none were left out
+        let mut bob = TestClient::connect(&addr).await?;
+        bob.read_username_prompt().await?;
+        bob.send_line("bob").await?;
+        bob.read_line_assert_contains_all(&["bob", "welcome"])
+            .await?;
+        bob.read_line_assert_contains("alice: two").await?;
+        bob.read_line_assert_contains("alice: three").await?;
+        bob.read_line_assert_contains("bob joined the server")
+            .await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn identical_messages_sent_in_quick_succession_are_broadcast_once_when_enabled() -> Result<()> {
+    tokio_test(async {
+        let (addr, _shutdown, _handle) = test_server::spawn_with_config(Config {
+            dedup_window: Some(Duration::from_millis(500)),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        alice.send_line("hello").await?;
+        alice.read_line_assert_contains("alice: hello").await?;
+        alice.send_line("hello").await?;
+        alice
+            .read_line_assert_contains("(duplicate ignored)")
+            .await?;
+
+        // Others only see the first copy
+        alice.send_line("hello again").await?;
+        bob.read_line_assert_contains("alice: hello\n").await?;
+        bob.read_line_assert_contains("alice: hello again").await?;
+
+        // The same message is sent as usual once the window has passed
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        alice.send_line("hello again").await?;
+        bob.read_line_assert_contains("alice: hello again").await?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn messages_reach_other_clients_promptly_after_a_burst() -> Result<()> {
+    tokio_test(async {
+        const DELIVERY_TIMEOUT: Duration = Duration::from_millis(250);
+
+        let addr = test_server::spawn().await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        // Alice doesn't read her own copies, so she would otherwise fill her own buffers and stall
+        alice.send_line("/echo off").await?;
+        alice.read_line_assert_contains("Echo is now off").await?;
+
+        // A burst of long lines fills the write buffers, after which each following message should
+        // still be delivered without waiting for another write to push it out
+        let filler = "x".repeat(1000);
+        for i in 0..200 {
+            alice.send_line(&format!("{i} {filler}")).await?;
+        }
+        bob.read_until_line_contains("alice: 199 ").await?;
+
+        for msg in ["one", "two", "three"] {
+            alice.send_line(msg).await?;
+            tokio::time::timeout(
+                DELIVERY_TIMEOUT,
+                bob.read_line_assert_contains(&format!("alice: {msg}")),
+            )
+            .await
+            .with_context(|| format!("{msg:?} was not delivered within {DELIVERY_TIMEOUT:?}"))??;
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use crate::common::{test_client::TestClient, tokio_test};
+use anyhow::{Context, Result};
+use prattle_server::{config::Config, server::Server, shutdown_signal::ShutdownHandle, tls};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Sends an HTTP GET request for `path` to `addr` and returns the full response.
+async fn http_get(addr: &str, path: &str) -> Result<String> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream
+        .write_all(format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\n\r\n").as_bytes())
+        .await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+/// Finds the value of the metric `name` in a Prometheus text exposition `body`.
+fn metric_value(body: &str, name: &str) -> Option<u64> {
+    body.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' ')?.parse().ok())
+}
+
+#[test]
+fn metrics_endpoint_reports_connected_users_and_totals() -> Result<()> {
+    tokio_test(async {
+        let server = Server::bind(
+            "127.0.0.1:0",
+            tls::create_config()?,
+            Config { metrics_addr: Some("127.0.0.1:0".to_string()), ..Config::default() },
+        )
+        .await?;
+        let addr = server.local_addr()?.to_string();
+        let metrics_addr = server
+            .metrics_addr()?
+            .context("expected the metrics endpoint to be enabled")?
+            .to_string();
+
+        let shutdown = ShutdownHandle::new();
+        let server_handle = tokio::spawn(server.run(shutdown.signal()));
+
+        let response = http_get(&metrics_addr, "/metrics").await?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain"));
+        assert_eq!(metric_value(&response, "prattle_connected_users"), Some(0));
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+        alice.send_line("hello").await?;
+        alice.read_line_assert_contains("alice: hello").await?;
+
+        // The gauge reflects the connected clients and the counters reflect the activity
+        let response = http_get(&metrics_addr, "/metrics").await?;
+        assert_eq!(metric_value(&response, "prattle_connected_users"), Some(2));
+        assert_eq!(
+            metric_value(&response, "prattle_connections_total"),
+            Some(2)
+        );
+        assert_eq!(metric_value(&response, "prattle_messages_total"), Some(1));
+        for name in [
+            "# TYPE prattle_connected_users gauge",
+            "# TYPE prattle_connections_total counter",
+            "# TYPE prattle_messages_total counter",
+        ] {
+            assert!(response.contains(name), "expected {name:?} in {response}");
+        }
+
+        // Other paths are not found
+        let response = http_get(&metrics_addr, "/").await?;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        // The endpoint stops along with the server
+        drop(alice);
+        drop(bob);
+        shutdown.trigger();
+        server_handle.await??;
+
+        // Give the aborted endpoint task a moment to be dropped
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(TcpStream::connect(&metrics_addr).await.is_err());
+
+        Ok(())
+    })
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "plaintext")]
+
+use anyhow::{Context, Result};
+use prattle_client::{PlaintextReader, connect_plaintext};
+use prattle_server::{config::Config, server::Server, shutdown_signal::ShutdownHandle};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+/// The amount of time to wait when connecting to or reading from the server.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runs `f` to completion on a current-thread Tokio runtime.
+fn tokio_test<F: Future<Output = Result<()>>>(f: F) -> Result<()> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(f)
+}
+
+/// Reads a line from `reader` and asserts that it contains `expected`.
+async fn read_line_assert_contains(reader: &mut PlaintextReader, expected: &str) -> Result<String> {
+    let mut line = String::new();
+
+    tokio::time::timeout(TIMEOUT, reader.read_line(&mut line))
+        .await
+        .context("Timeout reading line")??;
+
+    assert!(
+        line.contains(expected),
+        "Expected \"{expected}\", got: \"{line}\""
+    );
+    Ok(line)
+}
+
+#[test]
+fn full_session_over_plaintext() -> Result<()> {
+    tokio_test(async {
+        let server = Server::bind_plaintext("127.0.0.1:0", Config::default()).await?;
+        let addr = server.local_addr()?.to_string();
+        let shutdown = ShutdownHandle::new();
+        let server_handle = tokio::spawn(server.run(shutdown.signal()));
+
+        let (mut alice_reader, mut alice_writer) = connect_plaintext(&addr, TIMEOUT).await?;
+        read_line_assert_contains(&mut alice_reader, "Choose a username").await?;
+        alice_writer.write_all(b"alice\n").await?;
+        read_line_assert_contains(&mut alice_reader, "welcome").await?;
+        read_line_assert_contains(&mut alice_reader, "\x05USERNAME alice\n").await?;
+        read_line_assert_contains(&mut alice_reader, "alice joined").await?;
+
+        let (mut bob_reader, mut bob_writer) = connect_plaintext(&addr, TIMEOUT).await?;
+        read_line_assert_contains(&mut bob_reader, "Choose a username").await?;
+        bob_writer.write_all(b"bob\n").await?;
+        read_line_assert_contains(&mut bob_reader, "welcome").await?;
+        read_line_assert_contains(&mut bob_reader, "\x05USERNAME bob\n").await?;
+        read_line_assert_contains(&mut bob_reader, "bob joined").await?;
+        read_line_assert_contains(&mut alice_reader, "bob joined").await?;
+
+        bob_writer.write_all(b"hello without TLS\n").await?;
+        read_line_assert_contains(&mut alice_reader, "bob: hello without TLS").await?;
+        read_line_assert_contains(&mut bob_reader, "bob: hello without TLS").await?;
+
+        alice_writer.write_all(b"/who\n").await?;
+        let who = read_line_assert_contains(&mut alice_reader, "Currently online:").await?;
+        assert!(who.contains("alice") && who.contains("bob"), "got: {who}");
+
+        bob_writer.write_all(b"/quit\n").await?;
+        read_line_assert_contains(&mut bob_reader, "Goodbye").await?;
+        drop((bob_reader, bob_writer));
+        read_line_assert_contains(&mut alice_reader, "bob left").await?;
+
+        shutdown.trigger();
+        read_line_assert_contains(&mut alice_reader, "Server is shutting down").await?;
+        drop((alice_reader, alice_writer));
+        server_handle.await??;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn tls_clients_cannot_connect_to_a_plaintext_server() -> Result<()> {
+    tokio_test(async {
+        let server = Server::bind_plaintext("127.0.0.1:0", Config::default()).await?;
+        let addr = server.local_addr()?.to_string();
+        let shutdown = ShutdownHandle::new();
+        tokio::spawn(server.run(shutdown.signal()));
+
+        assert!(
+            prattle_client::connect_insecure(&addr, TIMEOUT)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    })
+}
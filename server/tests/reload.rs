@@ -0,0 +1,102 @@
+#![cfg(unix)]
+
+mod common;
+
+use crate::common::{test_client::TestClient, tokio_test};
+use anyhow::{Context, Result};
+use prattle_server::{
+    config::Config, reload_signal, server::Server, shutdown_signal::ShutdownHandle, tls,
+};
+use std::{fs, process::Command, time::Duration};
+use tokio::sync::Mutex;
+
+/// Held by each test while it changes the certificate files, which all tests here share.
+static CERT_FILES: Mutex<()> = Mutex::const_new(());
+
+#[test]
+fn sighup_reloads_certificate_without_dropping_connections() -> Result<()> {
+    tokio_test(async {
+        let _cert_files = CERT_FILES.lock().await;
+        let server = Server::bind("127.0.0.1:0", tls::create_config()?, Config::default()).await?;
+        let addr = server.local_addr()?.to_string();
+        reload_signal::listen(server.reload_handle())?;
+
+        let shutdown = ShutdownHandle::new();
+        let server_handle = tokio::spawn(server.run(shutdown.signal()));
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // Rotate the certificate on disk (regenerating it by removing the old files)
+        fs::remove_file(tls::CERT_PATH)?;
+        fs::remove_file("server.key")?;
+        tls::create_config()?;
+
+        // New handshakes still use the old certificate, which the client no longer trusts
+        assert!(TestClient::connect(&addr).await.is_err());
+
+        // Send SIGHUP to this process
+        let status = Command::new("kill")
+            .args(["-HUP", &std::process::id().to_string()])
+            .status()?;
+        assert!(status.success());
+
+        // Wait for the server to pick up the new certificate
+        let mut bob = None;
+        for _ in 0..20 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+
+            if let Ok(client) = TestClient::connect_with_username("bob", &addr).await {
+                bob = Some(client);
+                break;
+            }
+        }
+        let mut bob = bob.context("Server did not reload the certificate")?;
+
+        // The existing connection was not interrupted
+        alice.read_line_assert_contains("bob joined").await?;
+        alice.send_line("still here").await?;
+        bob.read_line_assert_contains("alice: still here").await?;
+
+        drop((alice, bob));
+        shutdown.trigger();
+        server_handle.await??;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn malformed_certificate_is_left_untouched_on_reload() -> Result<()> {
+    tokio_test(async {
+        let _cert_files = CERT_FILES.lock().await;
+
+        let server = Server::bind("127.0.0.1:0", tls::create_config()?, Config::default()).await?;
+        let addr = server.local_addr()?.to_string();
+        let reload = server.reload_handle();
+
+        let shutdown = ShutdownHandle::new();
+        let server_handle = tokio::spawn(server.run(shutdown.signal()));
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+
+        // Reload while a new certificate is only partly written
+        let original_cert = fs::read(tls::CERT_PATH)?;
+        fs::write(tls::CERT_PATH, b"-----BEGIN CERTIFICATE-----\n")?;
+        reload.trigger();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // The file was not replaced with a regenerated certificate
+        assert_eq!(fs::read(tls::CERT_PATH)?, b"-----BEGIN CERTIFICATE-----\n");
+
+        // New handshakes still use the original certificate
+        fs::write(tls::CERT_PATH, original_cert)?;
+        let _bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        drop(alice);
+        shutdown.trigger();
+        server_handle.await??;
+
+        Ok(())
+    })
+}
@@ -1,18 +1,19 @@
 mod common;
 
 use crate::common::{test_client::TestClient, test_server, tokio_test};
-use anyhow::{Result, anyhow};
+use anyhow::Result;
+use prattle_server::config::{CLIENT_DISCONNECT_TIMEOUT, Config};
 use std::time::Duration;
 
 #[test]
 fn shutdown_broadcasts_to_all_connected_clients() -> Result<()> {
     tokio_test(async {
-        let (addr, shutdown_tx, _) = test_server::spawn_with_shutdown().await?;
+        let (addr, shutdown, _) = test_server::spawn_with_shutdown().await?;
 
         // Connect three clients
         let mut client1 = TestClient::connect_with_username("alice", &addr).await?;
         let mut client2 = TestClient::connect_with_username("bob", &addr).await?;
-        let mut client3 = TestClient::connect_with_username("charlie", &addr).await?;
+        let client3 = TestClient::connect_with_username("charlie", &addr).await?;
 
         // Clear join messages
         client1.read_line_assert_contains("bob joined").await?;
@@ -20,24 +21,29 @@ fn shutdown_broadcasts_to_all_connected_clients() -> Result<()> {
         client2.read_line_assert_contains("charlie joined").await?;
 
         // Trigger shutdown
-        shutdown_tx
-            .send(())
-            .map_err(|()| anyhow!("Failed to send shutdown signal"))?;
+        shutdown.trigger();
+
+        // All clients should receive the shutdown message, and the first to disconnect should not
+        // cause a leave notice for the others
+        assert_eq!(
+            client1
+                .read_line_assert_contains("Server is shutting down")
+                .await?,
+            format!(
+                "Server is shutting down; you have {} seconds to finish up\n",
+                CLIENT_DISCONNECT_TIMEOUT.as_secs()
+            )
+        );
+        client1.graceful_disconnect().await?;
 
-        // Give the server a moment to broadcast
-        tokio::time::sleep(Duration::from_millis(50)).await;
+        for mut client in [client2, client3] {
+            client
+                .read_line_assert_contains("Server is shutting down")
+                .await?;
 
-        // All clients should receive the shutdown message (may be interleaved with "left" messages
-        // as clients disconnect)
-        client1
-            .read_until_line_contains("Server is shutting down")
-            .await?;
-        client2
-            .read_until_line_contains("Server is shutting down")
-            .await?;
-        client3
-            .read_until_line_contains("Server is shutting down")
-            .await?;
+            // Nothing follows the shutdown notice before the server closes the connection
+            assert_eq!(client.read_line_assert_contains("").await?, "");
+        }
 
         Ok(())
     })
@@ -46,14 +52,12 @@ fn shutdown_broadcasts_to_all_connected_clients() -> Result<()> {
 #[test]
 fn shutdown_waits_for_clients_to_disconnect_gracefully() -> Result<()> {
     tokio_test(async {
-        let (addr, shutdown_tx, server_handle) = test_server::spawn_with_shutdown().await?;
+        let (addr, shutdown, server_handle) = test_server::spawn_with_shutdown().await?;
 
         let mut client = TestClient::connect_with_username("alice", &addr).await?;
 
         // Trigger shutdown
-        shutdown_tx
-            .send(())
-            .map_err(|()| anyhow!("Failed to send shutdown signal"))?;
+        shutdown.trigger();
 
         // Read the shutdown message
         client
@@ -62,7 +66,7 @@ fn shutdown_waits_for_clients_to_disconnect_gracefully() -> Result<()> {
 
         tokio::time::sleep(Duration::from_secs(1)).await;
 
-        // Server should still be waiting for client to close connection (4s per-client timeout)
+        // Server should still be waiting for client to close connection (per-client timeout)
         assert!(
             !server_handle.is_finished(),
             "Server should still be waiting for client to disconnect"
@@ -86,35 +90,129 @@ fn shutdown_waits_for_clients_to_disconnect_gracefully() -> Result<()> {
 #[test]
 fn shutdown_times_out_when_client_does_not_disconnect() -> Result<()> {
     tokio_test(async {
-        let (addr, shutdown_tx, server_handle) = test_server::spawn_with_shutdown().await?;
+        let (addr, shutdown, server_handle) = test_server::spawn_with_shutdown().await?;
 
         let mut client = TestClient::connect_with_username("alice", &addr).await?;
 
         // Trigger shutdown
-        shutdown_tx
-            .send(())
-            .map_err(|()| anyhow!("Failed to send shutdown signal"))?;
+        shutdown.trigger();
 
         // Client receives shutdown message but stays connected
         client
             .read_line_assert_contains("Server is shutting down")
             .await?;
 
-        tokio::time::sleep(Duration::from_secs(2)).await;
+        tokio::time::sleep(CLIENT_DISCONNECT_TIMEOUT / 2).await;
 
-        // Verify server is still waiting before the 4s per-client timeout
+        // Verify server is still waiting before the per-client timeout
         assert!(
             !server_handle.is_finished(),
             "Server should still be waiting for client (before client timeout)"
         );
 
-        // Keep the client connected and wait for the 4s per-client timeout
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        // Keep the client connected and wait for the per-client timeout
+        tokio::time::sleep(CLIENT_DISCONNECT_TIMEOUT / 2 + Duration::from_millis(500)).await;
 
         // Server should have shut down after the timeout despite the client still being connected
         assert!(
             server_handle.is_finished(),
-            "Server should have shut down after 4s timeout despite client still being connected"
+            "Server should have shut down after client timeout despite client still being connected"
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn second_shutdown_trigger_stops_waiting_for_clients() -> Result<()> {
+    tokio_test(async {
+        let (addr, shutdown, server_handle) = test_server::spawn_with_shutdown().await?;
+
+        let mut client = TestClient::connect_with_username("alice", &addr).await?;
+
+        shutdown.trigger();
+
+        // Client receives shutdown message but stays connected, so the server keeps waiting
+        client
+            .read_line_assert_contains("Server is shutting down")
+            .await?;
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(
+            !server_handle.is_finished(),
+            "Server should still be waiting for client after the first trigger"
+        );
+
+        // Triggering again makes the server exit well before the per-client timeout
+        shutdown.trigger();
+        tokio::time::timeout(Duration::from_millis(500), server_handle).await??;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn shutdown_finishes_as_soon_as_the_last_client_leaves() -> Result<()> {
+    tokio_test(async {
+        // Polling alone would keep the server waiting far longer than the test allows
+        let (addr, shutdown, server_handle) = test_server::spawn_with_config(Config {
+            shutdown_timeout: Duration::from_secs(30),
+            shutdown_poll_interval: Duration::from_secs(30),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut bob = TestClient::connect_with_username("bob", &addr).await?;
+        alice.read_line_assert_contains("bob joined").await?;
+
+        shutdown.trigger();
+
+        alice
+            .read_line_assert_contains("Server is shutting down")
+            .await?;
+        bob.read_line_assert_contains("Server is shutting down")
+            .await?;
+
+        alice.graceful_disconnect().await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(
+            !server_handle.is_finished(),
+            "Server should still be waiting for Bob"
+        );
+
+        bob.graceful_disconnect().await?;
+        tokio::time::timeout(Duration::from_millis(200), server_handle).await??;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn client_disconnect_timeout_can_be_shorter_than_the_shutdown_timeout() -> Result<()> {
+    tokio_test(async {
+        let (addr, shutdown, server_handle) = test_server::spawn_with_config(Config {
+            shutdown_timeout: Duration::from_secs(10),
+            client_disconnect_timeout: Duration::from_secs(1),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut client = TestClient::connect_with_username("alice", &addr).await?;
+
+        shutdown.trigger();
+
+        // Client receives shutdown message but stays connected
+        client
+            .read_line_assert_contains("Server is shutting down")
+            .await?;
+
+        // The per-client timeout ends the wait long before the global timeout would
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        assert!(
+            server_handle.is_finished(),
+            "Server should have shut down after the short per-client timeout"
         );
 
         Ok(())
@@ -124,12 +222,10 @@ fn shutdown_times_out_when_client_does_not_disconnect() -> Result<()> {
 #[test]
 fn shutdown_proceeds_with_no_clients_ever() -> Result<()> {
     tokio_test(async {
-        let (_, shutdown_tx, server_handle) = test_server::spawn_with_shutdown().await?;
+        let (_, shutdown, server_handle) = test_server::spawn_with_shutdown().await?;
 
         // Don't connect any clients and trigger shutdown immediately
-        shutdown_tx
-            .send(())
-            .map_err(|()| anyhow!("Failed to send shutdown signal"))?;
+        shutdown.trigger();
 
         // Give the server time to shut down
         tokio::time::sleep(Duration::from_millis(100)).await;
@@ -147,7 +243,7 @@ fn shutdown_proceeds_with_no_clients_ever() -> Result<()> {
 #[test]
 fn shutdown_proceeds_if_all_clients_already_left() -> Result<()> {
     tokio_test(async {
-        let (addr, shutdown_tx, server_handle) = test_server::spawn_with_shutdown().await?;
+        let (addr, shutdown, server_handle) = test_server::spawn_with_shutdown().await?;
 
         // Connect two clients, disconnect them, and then shut down
         let mut client1 = TestClient::connect_with_username("alice", &addr).await?;
@@ -157,11 +253,9 @@ fn shutdown_proceeds_if_all_clients_already_left() -> Result<()> {
         client1.graceful_disconnect().await?;
         client2.graceful_disconnect().await?;
 
-        shutdown_tx
-            .send(())
-            .map_err(|()| anyhow!("Failed to send shutdown signal"))?;
+        shutdown.trigger();
 
-        // Server should proceed to shutdown after at most one polling interval (100ms)
+        // Server should proceed to shutdown without waiting
         tokio::time::sleep(Duration::from_millis(150)).await;
 
         assert!(
@@ -176,12 +270,10 @@ fn shutdown_proceeds_if_all_clients_already_left() -> Result<()> {
 #[test]
 fn server_stops_accepting_new_connections_during_graceful_shutdown() -> Result<()> {
     tokio_test(async {
-        let (addr, shutdown_tx, server_handle) = test_server::spawn_with_shutdown().await?;
+        let (addr, shutdown, server_handle) = test_server::spawn_with_shutdown().await?;
         let mut client1 = TestClient::connect_with_username("alice", &addr).await?;
 
-        shutdown_tx
-            .send(())
-            .map_err(|()| anyhow!("Failed to send shutdown signal"))?;
+        shutdown.trigger();
 
         // Client receives shutdown message but does not immediately disconnect
         client1
@@ -218,7 +310,7 @@ fn server_stops_accepting_new_connections_during_graceful_shutdown() -> Result<(
 #[test]
 fn shutdown_during_username_selection_disconnects_gracefully() -> Result<()> {
     tokio_test(async {
-        let (addr, shutdown_tx, server_handle) = test_server::spawn_with_shutdown().await?;
+        let (addr, shutdown, server_handle) = test_server::spawn_with_shutdown().await?;
 
         // Connect but don't provide a username yet
         let mut client = TestClient::connect(&addr).await?;
@@ -229,9 +321,7 @@ fn shutdown_during_username_selection_disconnects_gracefully() -> Result<()> {
             .await?;
 
         // Trigger shutdown while still in username selection
-        shutdown_tx
-            .send(())
-            .map_err(|()| anyhow!("Failed to send shutdown signal"))?;
+        shutdown.trigger();
 
         // Client should receive shutdown message even during username selection
         client
@@ -239,23 +329,94 @@ fn shutdown_during_username_selection_disconnects_gracefully() -> Result<()> {
             .await?;
 
         // Client stays connected but doesn't close the connection
-        tokio::time::sleep(Duration::from_secs(2)).await;
+        tokio::time::sleep(CLIENT_DISCONNECT_TIMEOUT / 2).await;
 
-        // Verify server is still waiting before the 4s per-client timeout
+        // Verify server is still waiting before the per-client timeout
         assert!(
             !server_handle.is_finished(),
             "Server should still be waiting for client (before client timeout)"
         );
 
-        // Keep the client connected and wait for the 4s per-client timeout
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        // Keep the client connected and wait for the per-client timeout
+        tokio::time::sleep(CLIENT_DISCONNECT_TIMEOUT / 2 + Duration::from_millis(500)).await;
 
         // Server should have shut down after the timeout despite the client still being connected
         assert!(
             server_handle.is_finished(),
-            "Server should have shut down after 4s timeout despite client still being connected"
+            "Server should have shut down after client timeout despite client still being connected"
         );
 
         Ok(())
     })
 }
+
+#[test]
+fn shutdown_during_password_entry_disconnects_gracefully() -> Result<()> {
+    tokio_test(async {
+        let (addr, shutdown, server_handle) = test_server::spawn_with_config(Config {
+            password: Some("hunter2".to_string()),
+            ..Config::default()
+        })
+        .await?;
+
+        // Connect but don't provide the password yet
+        let mut client = TestClient::connect(&addr).await?;
+        client.read_line_assert_contains("Password:").await?;
+
+        // Trigger shutdown while still in password entry
+        shutdown.trigger();
+
+        // Client should receive the shutdown message and be disconnected
+        client
+            .read_until_line_contains("Server is shutting down")
+            .await?;
+        client.graceful_disconnect().await?;
+
+        // Server should shut down promptly once the client has closed the connection
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(server_handle.is_finished(), "Server should have shut down");
+
+        Ok(())
+    })
+}
+
+#[test]
+fn shutdown_redirects_clients_to_configured_address() -> Result<()> {
+    tokio_test(async {
+        let (addr, shutdown, _) = test_server::spawn_with_config(Config {
+            redirect_addr: Some(String::from("127.0.0.1:9000")),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let mut pending = TestClient::connect(&addr).await?;
+        pending
+            .read_line_assert_contains_all(&["Choose", "username"])
+            .await?;
+
+        shutdown.trigger();
+
+        // Both joined clients and those still choosing a username are redirected
+        for client in [&mut alice, &mut pending] {
+            client
+                .read_until_line_contains("Server is shutting down")
+                .await?;
+            client
+                .read_line_assert_contains("Please reconnect to 127.0.0.1:9000")
+                .await?;
+            let redirect = client
+                .read_line_assert_contains("\x05REDIRECT 127.0.0.1:9000")
+                .await?;
+            assert_eq!(
+                prattle_client::redirect_addr(&redirect),
+                Some("127.0.0.1:9000")
+            );
+        }
+
+        alice.graceful_disconnect().await?;
+        pending.graceful_disconnect().await?;
+
+        Ok(())
+    })
+}
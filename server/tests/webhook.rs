@@ -0,0 +1,119 @@
+mod common;
+
+use crate::common::{test_client::TestClient, test_server, tokio_test};
+use anyhow::{Context, Result};
+use prattle_server::config::Config;
+use serde_json::{Value, json};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+/// The amount of time to wait for the server to send a webhook request.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Accepts a single webhook request on `listener` and responds with `status`, returning the request
+/// line and the JSON body.
+async fn receive_webhook(listener: &TcpListener, status: &str) -> Result<(String, Value)> {
+    let (socket, _) = tokio::time::timeout(WEBHOOK_TIMEOUT, listener.accept())
+        .await
+        .context("Timeout waiting for webhook request")??;
+    let mut reader = BufReader::new(socket);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).await?;
+
+        if header.trim_end().is_empty() {
+            break;
+        }
+
+        if let Some(length) = header.strip_prefix("Content-Length: ") {
+            content_length = length.trim_end().parse()?;
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    reader
+        .into_inner()
+        .write_all(format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n").as_bytes())
+        .await?;
+
+    Ok((request_line, serde_json::from_slice(&body)?))
+}
+
+#[test]
+fn joins_messages_and_leaves_are_posted_to_the_webhook() -> Result<()> {
+    tokio_test(async {
+        let webhook_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            webhook_url: Some(format!(
+                "http://{}/hooks/prattle",
+                webhook_listener.local_addr()?
+            )),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        let (request_line, body) = receive_webhook(&webhook_listener, "204 No Content").await?;
+        assert_eq!(request_line, "POST /hooks/prattle HTTP/1.1\r\n");
+        assert_eq!(
+            body,
+            json!({ "event": "join", "user": "alice", "text": "* alice joined the server" })
+        );
+
+        // Failed requests don't stop later events from being sent
+        alice.send_line("hello, relay").await?;
+        alice
+            .read_line_assert_contains("alice: hello, relay")
+            .await?;
+        let (_, body) = receive_webhook(&webhook_listener, "500 Internal Server Error").await?;
+        assert_eq!(
+            body,
+            json!({ "event": "message", "user": "alice", "text": "alice: hello, relay" })
+        );
+
+        alice.send_line("/quit").await?;
+        alice.graceful_disconnect().await?;
+        let (_, body) = receive_webhook(&webhook_listener, "200 OK").await?;
+        assert_eq!(
+            body,
+            json!({ "event": "leave", "user": "alice", "text": "* alice left" })
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn webhooks_are_not_sent_for_other_notices() -> Result<()> {
+    tokio_test(async {
+        let webhook_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let (addr, _shutdown, _) = test_server::spawn_with_config(Config {
+            webhook_url: Some(format!("http://{}/", webhook_listener.local_addr()?)),
+            ..Config::default()
+        })
+        .await?;
+
+        let mut alice = TestClient::connect_with_username("alice", &addr).await?;
+        receive_webhook(&webhook_listener, "200 OK").await?;
+
+        alice.send_line("/color blue").await?;
+        alice.read_line_assert_contains("now shown in blue").await?;
+        alice.read_line_assert_contains("COLOR alice blue").await?;
+        alice.send_line("after the color").await?;
+
+        let (_, body) = receive_webhook(&webhook_listener, "200 OK").await?;
+        assert_eq!(body["event"], "message");
+
+        Ok(())
+    })
+}